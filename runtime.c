@@ -1,6 +1,10 @@
 #include <stdint.h>
+#include <inttypes.h>
 #include <stdio.h>
 #include <stdbool.h>
+#include <stdlib.h>
+#include <string.h>
+#include <ucontext.h>
 
 void printi8(int8_t a) {
     printf("%hhd", a);
@@ -15,7 +19,7 @@ void printi32(int32_t a) {
 }
 
 void printi64(int64_t a) {
-    printf("%ld", a);
+    printf("%" PRId64, a);
 }
 
 void printu8(uint8_t a) {
@@ -31,7 +35,7 @@ void printu32(uint32_t a) {
 }
 
 void printu64(uint64_t a) {
-    printf("%lu", a);
+    printf("%" PRIu64, a);
 }
 
 int8_t scani8() {
@@ -54,7 +58,7 @@ int32_t scani32() {
 
 int64_t scani64() {
     int64_t a;
-    scanf("%ld", &a);
+    scanf("%" SCNd64, &a);
     return a;
 }
 
@@ -78,7 +82,7 @@ uint32_t scanu32() {
 
 uint64_t scanu64() {
     uint64_t a;
-    scanf("%lu", &a);
+    scanf("%" SCNu64, &a);
     return a;
 }
 
@@ -116,4 +120,398 @@ bool scanbool() {
 
 void println() {
     printf("\n");
-}
\ No newline at end of file
+}
+
+// prints unicode scalar value `c` encoded as UTF-8
+void printc(uint32_t c) {
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | (c >> 6));
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | (c >> 12));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | (c >> 18));
+        putchar(0x80 | ((c >> 12) & 0x3F));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
+// reads one UTF-8 encoded character. Returns 0 on end of input.
+uint32_t scanc() {
+    int b = getchar();
+    if (b == EOF) return 0;
+
+    int extra = 0;
+    uint32_t c;
+    if      ((b & 0x80) == 0x00)   { c = b; }
+    else if ((b & 0xE0) == 0xC0)   { c = b & 0x1F; extra = 1; }
+    else if ((b & 0xF0) == 0xE0)   { c = b & 0x0F; extra = 2; }
+    else                           { c = b & 0x07; extra = 3; }
+
+    while (extra-- > 0) {
+        b = getchar();
+        if (b == EOF) return 0;
+        c = (c << 6) | (b & 0x3F);
+    }
+    return c;
+}
+
+void printstr(const char* a) {
+    printf("%s", a);
+}
+
+int64_t lenstr(const char* a) {
+    return (int64_t)strlen(a);
+}
+
+bool eqstr(const char* a, const char* b) {
+    return strcmp(a, b) == 0;
+}
+
+void array_index_out_of_bounds(int64_t line, int64_t index, int64_t size) {
+    fprintf(stderr, "Runtime error at line %" PRId64 ": index %" PRId64 " is out of bounds for array of size %" PRId64 "\n", 
+        line, index, size);
+    exit(1);
+}
+
+
+/* Green threads. Tasks are cooperative: each one runs on its own stack until it finishes or blocks,
+   and the main thread acts as the scheduler while it waits. */
+
+#define TASK_STACK_SIZE (64 * 1024)
+
+typedef struct task {
+    ucontext_t ctx;
+    void (*fn)(void*);
+    void* arg;
+    void* stack;
+    bool done;
+    struct task* next;
+} task_t;
+
+static ucontext_t scheduler_ctx;
+static task_t* current_task = NULL;     /* NULL while the main thread runs */
+static task_t* ready_head = NULL;
+static task_t* ready_tail = NULL;
+static task_t* blocked_tasks = NULL;
+static bool finish_registered = false;
+
+static void finish_tasks();
+
+static void ready_push(task_t* task) {
+    task->next = NULL;
+    if (ready_tail) ready_tail->next = task;
+    else            ready_head = task;
+    ready_tail = task;
+}
+
+static task_t* ready_pop() {
+    task_t* task = ready_head;
+    if (task) {
+        ready_head = task->next;
+        if (!ready_head) ready_tail = NULL;
+    }
+    return task;
+}
+
+static void runtime_error(const char* message, int64_t line) {
+    fflush(stdout);
+    if (line > 0) fprintf(stderr, "Runtime error at line %" PRId64 ": %s\n", line, message);
+    else          fprintf(stderr, "Runtime error: %s\n", message);
+    /* may be called while exiting, where `exit` can't be called again */
+    _Exit(1);
+}
+
+static void task_trampoline() {
+    current_task->fn(current_task->arg);
+    current_task->done = true;
+    /* returning resumes the scheduler through uc_link */
+}
+
+void spawn_task(void (*fn)(void*), void* arg) {
+    task_t* task = malloc(sizeof(task_t));
+    task->fn = fn;
+    task->arg = arg;
+    task->stack = malloc(TASK_STACK_SIZE);
+    task->done = false;
+
+    getcontext(&task->ctx);
+    task->ctx.uc_stack.ss_sp = task->stack;
+    task->ctx.uc_stack.ss_size = TASK_STACK_SIZE;
+    task->ctx.uc_link = &scheduler_ctx;
+    makecontext(&task->ctx, task_trampoline, 0);
+    ready_push(task);
+
+    /* tasks still pending when main returns are run to completion */
+    if (!finish_registered) {
+        finish_registered = true;
+        atexit(finish_tasks);
+    }
+}
+
+/* runs the next ready task until it finishes or blocks. Only called from the main thread. */
+static bool run_next_task() {
+    task_t* task = ready_pop();
+    if (!task) return false;
+
+    current_task = task;
+    swapcontext(&scheduler_ctx, &task->ctx);
+    current_task = NULL;
+
+    if (task->done) {
+        free(task->stack);
+        free(task);
+    }
+    return true;
+}
+
+/* blocks the caller until `task_wake_all()`. Waiters re-check their condition after returning. 
+   The main thread runs other tasks instead of blocking. */
+static void task_park() {
+    if (!current_task) {
+        if (!run_next_task()) runtime_error("all tasks are blocked (deadlock)", 0);
+        return;
+    }
+    task_t* task = current_task;
+    task->next = blocked_tasks;
+    blocked_tasks = task;
+    swapcontext(&task->ctx, &scheduler_ctx);
+}
+
+static void task_wake_all() {
+    while (blocked_tasks) {
+        task_t* task = blocked_tasks;
+        blocked_tasks = task->next;
+        ready_push(task);
+    }
+}
+
+void waitall() {
+    if (current_task) runtime_error("`waitall` can only be called from main", 0);
+    while (run_next_task()) {}
+    if (blocked_tasks) runtime_error("all tasks are blocked (deadlock)", 0);
+}
+
+/* like goroutines, tasks blocked forever when the program exits are dropped */
+static void finish_tasks() {
+    while (run_next_task()) {}
+}
+
+
+/* Channels. Values are copied in and out of a ring buffer of `elem_size` byte slots. 
+   An unbuffered channel has a single slot, and its sender waits until the value is received. */
+
+typedef struct chan {
+    int64_t elem_size;
+    int64_t capacity;   /* 0 for unbuffered */
+    char* buffer;
+    int64_t head;
+    int64_t count;
+    int64_t sent;       /* values sent so far */
+    int64_t received;   /* values received so far */
+    int64_t waiting_receivers;
+    bool closed;
+} chan_t;
+
+static int64_t chan_slots(chan_t* ch) {
+    return ch->capacity > 0 ? ch->capacity : 1;
+}
+
+void* chan_make(int64_t elem_size, int64_t capacity) {
+    if (capacity < 0) runtime_error("capacity of channel is negative", 0);
+    chan_t* ch = malloc(sizeof(chan_t));
+    ch->elem_size = elem_size;
+    ch->capacity = capacity;
+    ch->buffer = malloc(elem_size * (capacity > 0 ? capacity : 1));
+    ch->head = 0;
+    ch->count = 0;
+    ch->sent = 0;
+    ch->received = 0;
+    ch->waiting_receivers = 0;
+    ch->closed = false;
+    return ch;
+}
+
+void chan_send(void* channel, void* value, int64_t line) {
+    chan_t* ch = channel;
+    while (!ch->closed && ch->count == chan_slots(ch)) task_park();
+    if (ch->closed) runtime_error("send on a closed channel", line);
+
+    int64_t slot = (ch->head + ch->count) % chan_slots(ch);
+    memcpy(ch->buffer + slot * ch->elem_size, value, ch->elem_size);
+    ch->count++;
+    int64_t ticket = ++ch->sent;
+    task_wake_all();
+
+    if (ch->capacity == 0) {
+        while (ch->received < ticket) task_park();
+    }
+}
+
+/* false, with a zero value, once the channel is closed and empty */
+bool chan_recv(void* channel, void* value) {
+    chan_t* ch = channel;
+    bool announced = false;
+    while (ch->count == 0) {
+        if (ch->closed) {
+            memset(value, 0, ch->elem_size);
+            return false;
+        }
+        ch->waiting_receivers++;
+        /* a `select` parked on an unbuffered send can go ahead now. Woken once, 
+           so that receivers waiting for nothing don't keep waking each other */
+        if (!announced) {
+            task_wake_all();
+            announced = true;
+        }
+        task_park();
+        ch->waiting_receivers--;
+    }
+
+    memcpy(value, ch->buffer + ch->head * ch->elem_size, ch->elem_size);
+    ch->head = (ch->head + 1) % chan_slots(ch);
+    ch->count--;
+    ch->received++;
+    task_wake_all();
+    return true;
+}
+
+void chan_close(void* channel, int64_t line) {
+    chan_t* ch = channel;
+    if (ch->closed) runtime_error("close of a closed channel", line);
+    ch->closed = true;
+    task_wake_all();
+}
+
+
+/* one operation of a `select`, as laid out by the compiler */
+typedef struct select_case {
+    chan_t* channel;
+    void* value;
+    int64_t is_send;
+} select_case_t;
+
+static bool chan_can_send(chan_t* ch) {
+    if (ch->count == chan_slots(ch)) return false;
+    /* a value sent on an unbuffered channel needs a receiver to take it */
+    return ch->capacity > 0 || ch->waiting_receivers > 0;
+}
+
+/* index of the case that was run, or -1 for `default` when no case is ready */
+int64_t chan_select(select_case_t* cases, int64_t count, bool has_default, int64_t line) {
+    bool announced = false;
+    while (true) {
+        /* start from a random case, so that no ready case is favored */
+        int64_t start = count > 0 ? rand() % count : 0;
+        for (int64_t i = 0; i < count; i++) {
+            int64_t index = (start + i) % count;
+            select_case_t* c = &cases[index];
+            if (c->is_send) {
+                if (c->channel->closed) runtime_error("send on a closed channel", line);
+                if (chan_can_send(c->channel)) {
+                    chan_send(c->channel, c->value, line);
+                    return index;
+                }
+            } else if (c->channel->count > 0 || c->channel->closed) {
+                chan_recv(c->channel, c->value);
+                return index;
+            }
+        }
+        if (has_default) return -1;
+
+        for (int64_t i = 0; i < count; i++) {
+            if (!cases[i].is_send) cases[i].channel->waiting_receivers++;
+        }
+        /* like in `chan_recv`, other selects may be waiting to send to one of the receive cases */
+        if (!announced) {
+            task_wake_all();
+            announced = true;
+        }
+        task_park();
+        for (int64_t i = 0; i < count; i++) {
+            if (!cases[i].is_send) cases[i].channel->waiting_receivers--;
+        }
+    }
+}
+
+/* memory for `new`. It is zeroed, so fields holding references start out as `null` */
+void* heap_alloc(int64_t size, int64_t line) {
+    void* ptr = calloc(1, size > 0 ? (size_t)size : 1);
+    if (!ptr) runtime_error("out of memory", line);
+    return ptr;
+}
+
+void heap_free(void* ptr) {
+    free(ptr);
+}
+
+void null_dereference(int64_t line) {
+    runtime_error("null reference is dereferenced", line);
+}
+
+/* an rc points to its value, which follows the count. The header is 16 bytes to keep the value aligned */
+typedef struct rc_header {
+    int64_t count;
+    int64_t padding;
+} rc_header_t;
+
+static int64_t rc_live_count = 0;
+
+void* rc_alloc(int64_t size, int64_t line) {
+    rc_header_t* header = calloc(1, sizeof(rc_header_t) + (size > 0 ? (size_t)size : 1));
+    if (!header) runtime_error("out of memory", line);
+    header->count = 1;
+    rc_live_count++;
+    return header + 1;
+}
+
+void rc_retain(void* value) {
+    if (value) ((rc_header_t*)value - 1)->count++;
+}
+
+/* `drop` releases the rcs held by the value, before it is freed */
+void rc_release(void* value, void (*drop)(void*)) {
+    if (!value) return;
+    rc_header_t* header = (rc_header_t*)value - 1;
+    if (--header->count > 0) return;
+    if (drop) drop(value);
+    free(header);
+    rc_live_count--;
+}
+
+/* number of values behind rcs that are not freed yet */
+int64_t rclive() {
+    return rc_live_count;
+}
+
+/* the environment of a closure is shared by the copies of its function value. 
+   `drop` releases the values captured in it, before it is freed */
+typedef struct env_header {
+    int64_t count;
+    void (*drop)(void*);
+} env_header_t;
+
+void* env_alloc(int64_t size, void (*drop)(void*), int64_t line) {
+    env_header_t* header = calloc(1, sizeof(env_header_t) + (size > 0 ? (size_t)size : 1));
+    if (!header) runtime_error("out of memory", line);
+    header->count = 1;
+    header->drop = drop;
+    return header + 1;
+}
+
+void env_retain(void* env) {
+    if (env) ((env_header_t*)env - 1)->count++;
+}
+
+void env_release(void* env) {
+    if (!env) return;
+    env_header_t* header = (env_header_t*)env - 1;
+    if (--header->count > 0) return;
+    if (header->drop) header->drop(env);
+    free(header);
+}
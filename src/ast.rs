@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+
 use crate::lexer::Token;
+use crate::span::Span;
 use crate::globals::TokenType;
 
 #[allow(non_camel_case_types,dead_code)]
@@ -7,8 +10,17 @@ pub enum Datatype {
     int8, int16, int32, int64,
     uint8, uint16, uint32, uint64,
     float32, float64, bool, 
+    char, /* unicode scalar value */
     object{name: String},
+    enumeration{name: String},
     function{name: String, obj_name: Option<String>, returntype: Box<Datatype>, param_types: Vec<Box<Datatype>>},
+    array{elem_type: Box<Datatype>, size: u64},
+    dyn_trait{name: String}, /* any object implementing trait `name` */
+    channel{elem_type: Box<Datatype>}, /* `chan<T>`. Refers to a channel shared by all its copies */
+    tuple{elem_types: Vec<Datatype>}, /* several values returned together, `(int32, bool)` */
+    reference{elem_type: Box<Datatype>, mutable: bool}, /* `&T` or `&mut T`. Points to a value stored elsewhere */
+    rc{elem_type: Box<Datatype>}, /* `rc<T>`. Points to a heap value, freed when the last copy goes away */
+    generic{name: String, type_args: Vec<Datatype>}, /* `Name<T1, T2>`. Replaced by the specialized object type while type checking */
     string, yet_to_infer
 }
 
@@ -36,11 +48,15 @@ impl Datatype {
             TokenType::K_FLOAT32 => Datatype::float32,
             TokenType::K_FLOAT64 => Datatype::float64,
             TokenType::K_BOOL => Datatype::bool,
+            TokenType::K_STRING => Datatype::string,
+            TokenType::K_CHAR => Datatype::char,
             TokenType::INT_LITERAL => Datatype::int64,
             TokenType::HEX_LITERAL => Datatype::int64,
             TokenType::OCTAL_LITERAL => Datatype::int64,
             TokenType::STRING_LITERAL => Datatype::string,
+            TokenType::CHAR_LITERAL => Datatype::char,
             TokenType::FLOAT_LITERAL => Datatype::float64,
+            TokenType::K_NULL => Datatype::null_ref(),
             TokenType::IDENTIFIER => Datatype::object{name: String::new()},
             _ => Datatype::yet_to_infer
         };
@@ -111,6 +127,152 @@ impl Datatype {
         }
     }
 
+    #[allow(dead_code)]
+    pub fn is_array(dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::array{..}     => true,
+            _   => false
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_reference(dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::reference{..} => true,
+            _   => false
+        }
+    }
+
+    /// Name of the type as written in source. Used to name specializations like `Pair<int64, bool>`.
+    #[allow(dead_code)]
+    pub fn type_name(&self) -> String {
+        match self {
+            Datatype::object{name}          => name.clone(),
+            Datatype::enumeration{name}     => name.clone(),
+            Datatype::dyn_trait{name}       => format!("dyn {}", name),
+            Datatype::channel{elem_type}    => format!("chan<{}>", elem_type.type_name()),
+            Datatype::rc{elem_type}         => format!("rc<{}>", elem_type.type_name()),
+            Datatype::reference{elem_type, ..} if **elem_type == Datatype::yet_to_infer
+                => "null".to_string(),
+            Datatype::reference{elem_type, mutable}
+                => format!("&{}{}", if *mutable { "mut " } else { "" }, elem_type.type_name()),
+            Datatype::tuple{elem_types}
+                => format!("({})", elem_types.iter().map(|dtype| dtype.type_name()).collect::<Vec<String>>().join(", ")),
+            Datatype::array{elem_type, size}=> format!("[{}; {}]", elem_type.type_name(), size),
+            Datatype::generic{name, type_args}
+                => format!("{}<{}>", name, 
+                    type_args.iter().map(|arg| arg.type_name()).collect::<Vec<String>>().join(", ")),
+            Datatype::function{returntype, param_types, ..}
+                => format!("func({}) -> {}", 
+                    param_types.iter().map(|param| param.type_name()).collect::<Vec<String>>().join(", "),
+                    returntype.type_name()),
+            _                               => format!("{:?}", self)
+        }
+    }
+
+    /// Keyword of a primitive type, as written after `as`.
+    #[allow(dead_code)]
+    pub fn token_type(&self) -> Option<TokenType> {
+        match self {
+            Datatype::int8 => Some(TokenType::K_INT8),
+            Datatype::int16 => Some(TokenType::K_INT16),
+            Datatype::int32 => Some(TokenType::K_INT32),
+            Datatype::int64 => Some(TokenType::K_INT64),
+            Datatype::uint8 => Some(TokenType::K_UINT8),
+            Datatype::uint16 => Some(TokenType::K_UINT16),
+            Datatype::uint32 => Some(TokenType::K_UINT32),
+            Datatype::uint64 => Some(TokenType::K_UINT64),
+            Datatype::float32 => Some(TokenType::K_FLOAT32),
+            Datatype::float64 => Some(TokenType::K_FLOAT64),
+            Datatype::bool => Some(TokenType::K_BOOL),
+            Datatype::string => Some(TokenType::K_STRING),
+            Datatype::char => Some(TokenType::K_CHAR),
+            _ => None
+        }
+    }
+
+    /// Replaces type parameters (parsed as objects) with the types bound to them.
+    #[allow(dead_code)]
+    pub fn substitute(&self, bindings: &HashMap<String, Datatype>) -> Datatype {
+        match self {
+            Datatype::object{name}  => match bindings.get(name) {
+                Some(dtype) => dtype.clone(),
+                _           => self.clone()
+            },
+            Datatype::array{elem_type, size}
+                => Datatype::array{elem_type: Box::new(elem_type.substitute(bindings)), size: *size},
+            Datatype::channel{elem_type}
+                => Datatype::channel{elem_type: Box::new(elem_type.substitute(bindings))},
+            Datatype::rc{elem_type}
+                => Datatype::rc{elem_type: Box::new(elem_type.substitute(bindings))},
+            Datatype::reference{elem_type, mutable}
+                => Datatype::reference{elem_type: Box::new(elem_type.substitute(bindings)), mutable: *mutable},
+            Datatype::tuple{elem_types}
+                => Datatype::tuple{elem_types: elem_types.iter().map(|dtype| dtype.substitute(bindings)).collect()},
+            Datatype::generic{name, type_args}
+                => Datatype::generic{
+                    name: name.clone(), 
+                    type_args: type_args.iter().map(|arg| arg.substitute(bindings)).collect()
+                },
+            Datatype::function{name, obj_name, returntype, param_types}
+                => Datatype::function{
+                    name: name.clone(),
+                    obj_name: obj_name.clone(),
+                    returntype: Box::new(returntype.substitute(bindings)),
+                    param_types: param_types.iter().map(|param| Box::new(param.substitute(bindings))).collect()
+                },
+            _   => self.clone()
+        }
+    }
+
+    /// Type of a function used as a value. Only the signature is part of it, not the name.
+    #[allow(dead_code)]
+    pub fn function_value(returntype: Datatype, param_types: Vec<Datatype>) -> Datatype {
+        Datatype::function{
+            name: String::new(),
+            obj_name: None,
+            returntype: Box::new(returntype),
+            param_types: param_types.into_iter().map(Box::new).collect()
+        }
+    }
+
+    /// `()`, the empty tuple. Result of closures that return no value.
+    pub fn unit() -> Datatype {
+        Datatype::tuple{elem_types: vec![]}
+    }
+
+    /// Type of `null` until it is used where a particular reference type is expected.
+    #[allow(dead_code)]
+    pub fn null_ref() -> Datatype {
+        Datatype::reference{elem_type: Box::new(Datatype::yet_to_infer), mutable: true}
+    }
+
+    /// Type reached by `.` on a value of this type. References and rcs are followed to the value they point to.
+    #[allow(dead_code)]
+    pub fn auto_deref(&self) -> &Datatype {
+        match self {
+            Datatype::reference{elem_type, ..}  => elem_type,
+            Datatype::rc{elem_type}             => elem_type,
+            _                                   => self
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_rc(dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::rc{..}    => true,
+            _   => false
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_dyn_trait(dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::dyn_trait{..} => true,
+            _   => false
+        }
+    }
+
     #[allow(dead_code)]
     pub fn is_width_64(dtype: &Datatype) -> bool {
         match dtype {
@@ -122,7 +284,7 @@ impl Datatype {
     #[allow(dead_code)]
     pub fn is_width_32(dtype: &Datatype) -> bool {
         match dtype {
-            Datatype::int32|Datatype::uint32|Datatype::float32  => true,
+            Datatype::int32|Datatype::uint32|Datatype::float32|Datatype::char  => true,
             _   => false
         }
     }
@@ -143,15 +305,20 @@ impl Datatype {
         }
     }
 
+    /// Bit width of numeric types. 0 for the rest.
+    #[allow(dead_code)]
+    pub fn get_width(dtype: &Datatype) -> u32 {
+        if Datatype::is_width_64(dtype)         { 64 }
+        else if Datatype::is_width_32(dtype)    { 32 }
+        else if Datatype::is_width_16(dtype)    { 16 }
+        else if Datatype::is_width_8(dtype)     { 8 }
+        else { 0 }
+    }
+
     /// Checks if width of `l_type` is more than `r_type`
     #[allow(dead_code)]
     pub fn lhs_has_more_width(l_type: &Datatype, r_type: &Datatype) -> bool {
-        if Datatype::is_width_64(l_type) && !Datatype::is_width_64(r_type)      { true }
-        else if Datatype::is_width_32(l_type) && !Datatype::is_width_32(r_type) { true }
-        else if Datatype::is_width_16(l_type) && !Datatype::is_width_16(r_type) { true }
-        else if Datatype::is_width_8(l_type) && !Datatype::is_width_8(r_type)   { true }
-        else { false }
-        // unimplemented!();
+        Datatype::get_width(l_type) > Datatype::get_width(r_type)
     }
 }
 
@@ -174,50 +341,422 @@ pub enum Primary_Type {
 #[allow(dead_code)]
 #[derive(Debug,Clone)]
 pub enum Decl {
-    Prototype   {name:Token, parameters: Vec<(Token/*name*/, Token/*datatype*/)>, returntype: /*Vec<Token>*/Token},
-    FuncDef     {prototype: Box<Decl>/*Prototype*/, block: Box<Stmt>/*Block*/},
+    Prototype   {name:Token, parameters: Vec<(Token/*name*/, Datatype)>, returntype: /*Vec<Token>*/Datatype, span: Span},
+    FuncDef     {prototype: Box<Decl>/*Prototype*/, block: Box<Stmt>/*Block*/, span: Span},
     // FuncDecl    {prototype}
-    StructDecl  {name: Token, fields: Vec<(Token/*name*/, Token/*datatype*/)>},
-    ImplDecl    {name: Token, trait_name: Option<Token>, funcs: Vec<Box<Decl>>/*FuncDef*/},
-    TraitDecl   {name: Token, funcs: Vec<Box<Decl>>/*FuncDef/Prototype*/},
+    StructDecl  {name: Token, fields: Vec<(Token/*name*/, Datatype)>, span: Span},
+    ImplDecl    {name: Token, trait_name: Option<Token>, funcs: Vec<Box<Decl>>/*FuncDef*/, span: Span},
+    TraitDecl   {name: Token, funcs: Vec<Box<Decl>>/*FuncDef/Prototype*/, span: Span},
+    EnumDecl    {name: Token, variants: Vec<(Token/*variant name*/, VariantFields)>, span: Span},
+    Template    {type_params: Vec<(Token/*name*/, Option<Token>/*trait bound*/)>, decl: Box<Decl>/*FuncDef/StructDecl*/, span: Span},
     // Program     {decls: Vec<Box<Decl>>}
 }
 
+#[allow(dead_code)]
+#[derive(Debug,Clone)]
+pub enum VariantFields {
+    Unit,
+    Tuple   (Vec<Datatype>),
+    Struct  (Vec<(Token/*field name*/, Datatype)>),
+}
+
+impl VariantFields {
+    /// Payload types in declaration order.
+    #[allow(dead_code)]
+    pub fn types(&self) -> Vec<Datatype> {
+        match self {
+            VariantFields::Unit             => vec![],
+            VariantFields::Tuple(types)     => types.clone(),
+            VariantFields::Struct(fields)   => fields.iter().map(|(_, dtype)| dtype.clone()).collect(),
+        }
+    }
+
+    /// Position of a payload field given by name (struct-like variants) or position (tuple variants).
+    #[allow(dead_code)]
+    pub fn index_of(&self, field_name: &Option<Token>, position: usize) -> Option<usize> {
+        match (self, field_name) {
+            (VariantFields::Tuple(types), None) if position < types.len()  => Some(position),
+            (VariantFields::Struct(fields), Some(name))
+                => fields.iter().position(|(field, _)| field.value == name.value),
+            _ => None
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug,Clone)]
+pub enum Pattern {
+    Wildcard{token: Token},
+    Binding {name: Token},
+    Literal {value: Token},
+    Variant {   enum_name: Token, 
+                variant_name: Token, 
+                fields: Vec<(Option<Token>/*field name, for struct-like variants*/, Box<Pattern>)>},
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value of its type.
+    #[allow(dead_code)]
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard{..} | Pattern::Binding{..}   => true,
+            _                                               => false
+        }
+    }
+}
+
+/// Channel operation a `select` case waits on.
+#[allow(dead_code)]
+#[derive(Debug,Clone)]
+pub enum SelectCase {
+    Receive {   target: Option<Box<Expr>>/* `case x = <-ch` */, 
+                channel: Box<Expr>, 
+                arrow_token: Token, 
+                elem_type: Datatype/*filled by type checker*/},
+    Send    {   channel: Box<Expr>, arrow_token: Token, value: Box<Expr>},
+    Default {   default_token: Token },
+}
+
 #[allow(dead_code)]
 #[derive(Debug,Clone)]
 pub enum Stmt {
     If      {   if_token: Token,
                 condition: Box<Expr>, 
                 then_block: Box<Stmt>, /* Should be Block */
-                else_block: Option<Box<Stmt>> /*Else block could be an if statement*/},
-    Block   {statements: Vec<Box<Stmt>>},
-    Return  {expr: Box<Expr>},
-    While   {condition: Box<Expr>, block: Box<Stmt>},
+                else_block: Option<Box<Stmt>> /*Else block could be an if statement*/, span: Span},
+    Block   {statements: Vec<Box<Stmt>>, span: Span},
+    Return  {expr: Box<Expr>, span: Span},
+    While   {while_token: Token, condition: Box<Expr>, block: Box<Stmt>, span: Span},
     For     {   for_token: Token,
                 initialization: Option<Box<Expr>> /*should be Assignment */, 
                 condition: Option<Box<Expr>>, 
                 updation: Option<Box<Expr>>, 
-                block: Box<Stmt>},  
-    Expression  {expr: Box<Expr>},
-    Decl    { decl: Box<Decl> },
-    Var     { name: Token, datatype: Option<Token>, initialization_value: Option<Box<Expr>>}
+                block: Box<Stmt>, span: Span},  
+    Loop    {   loop_token: Token,
+                count: Option<Box<Expr>> /* None for infinite loop */,
+                block: Box<Stmt>, span: Span},
+    Expression  {expr: Box<Expr>, span: Span},
+    Decl    { decl: Box<Decl>, span: Span },
+    Var     { name: Token, datatype: Option<Datatype>, initialization_value: Option<Box<Expr>>, span: Span},
+    Destructure { names: Vec<Token>, initialization_value: Box<Expr>, span: Span }, /* `var a, b = f();` */
+    Break   { break_token: Token, span: Span },
+    Spawn   { spawn_token: Token, call: Box<Expr>/*Call*/, span: Span },
+    Delete  { delete_token: Token, expr: Box<Expr>/* reference returned by `new` */, span: Span },
+    Select  { select_token: Token, cases: Vec<(SelectCase, Box<Stmt>/*Block*/)>, span: Span },
+    Continue{ continue_token: Token, span: Span },
+    Error   { span: Span } /* stands in for a statement that failed to parse */
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Expr {
-    Variable  {name: Token, datatype: Datatype, struct_name: Option<String>/* Valid only if datatype is obj */},
-    Literal   {value: Token, datatype: Datatype},
+    Variable  {name: Token, datatype: Datatype, struct_name: Option<String>/* Valid only if datatype is obj */, span: Span},
+    Literal   {value: Token, datatype: Datatype, span: Span},
     Call        {callee: Box<Expr>/*Expr=Identifier*/, arguments: Vec<Box<Expr>>/*arguments: Box<Expr>*//*Expr= ExprList*/, 
-                    datatype: Datatype},
-    AttributeRef{object: Box<Expr>, name: Token, object_dtype: Datatype, datatype: Datatype},
-    Binary      {lhs: Box<Expr>, rhs: Box<Expr>, operator: Token, datatype: Datatype},
-    Unary       {operator: Token, operand: Box<Expr>, datatype: Datatype},
-    StructExpr  {struct_name: Token, fields: /*Vec<Expr>*/Vec<(Token, Box<Expr>)>, datatype: Datatype},
+                    datatype: Datatype, span: Span},
+    AttributeRef{object: Box<Expr>, name: Token, object_dtype: Datatype, datatype: Datatype, span: Span},
+    Binary      {lhs: Box<Expr>, rhs: Box<Expr>, operator: Token, datatype: Datatype, span: Span},
+    Unary       {operator: Token, operand: Box<Expr>, datatype: Datatype, span: Span},
+    StructExpr  {struct_name: Token, fields: /*Vec<Expr>*/Vec<(Token, Box<Expr>)>, datatype: Datatype, span: Span},
     // Assignment  {target_list: Vec<Box<Expr>>, /*Expr=Expr::Identifier */
     //     expr_list: Vec<Box<Expr>>, datatype: Datatype},
-    Assignment  {target: Box<Expr>, operator: Token, expr: Box<Expr>, datatype: Datatype},
-    Grouping    { expr: Box<Expr>, datatype: Datatype },
-    Cast        { variable: Box<Expr>, cast_type: Token, from_dtype: Datatype, to_dtype: Datatype},
-    ExprList    { expr_list: Vec<Box<Expr>>, datatype: Datatype}
-}
\ No newline at end of file
+    Assignment  {target: Box<Expr>/* ExprList when assigning several values */, operator: Token, expr: Box<Expr>, datatype: Datatype, span: Span},
+    Grouping    { expr: Box<Expr>, datatype: Datatype, span: Span },
+    Cast        { variable: Box<Expr>, cast_type: Token, from_dtype: Datatype, to_dtype: Datatype, span: Span},
+    EnumVariant { enum_name: Token, variant_name: Token, 
+                    values: Vec<(Option<Token>/*field name, for struct-like variants*/, Box<Expr>)>, datatype: Datatype, span: Span},
+    Match       { match_token: Token, scrutinee: Box<Expr>, 
+                    arms: Vec<(Pattern, Box<Stmt>/*Expression or Block*/)>, datatype: Datatype, span: Span},
+    ExprList    { expr_list: Vec<Box<Expr>>, datatype: Datatype, span: Span},
+    ArrayLiteral{ bracket_token: Token, elements: Vec<Box<Expr>>, datatype: Datatype, span: Span},
+    Index       { array: Box<Expr>, index: Box<Expr>, bracket_token: Token, datatype: Datatype, span: Span},
+    Closure     { func_token: Token, parameters: Vec<(Token, Datatype)>, returntype: Datatype, body: Box<Stmt>/*Block*/,
+                    captures: Vec<(Token, Datatype)>/*filled by type checker*/, datatype: Datatype, span: Span},
+    MakeChan    { chan_token: Token, elem_type: Datatype, capacity: Option<Box<Expr>>/* None for unbuffered */, datatype: Datatype, span: Span},
+    Send        { channel: Box<Expr>, arrow_token: Token, value: Box<Expr>, span: Span},
+    Receive     { arrow_token: Token, channel: Box<Expr>, datatype: Datatype, span: Span},
+    AddressOf   { amp_token: Token, mutable: bool, operand: Box<Expr>/*Variable, AttributeRef, Index or Deref*/, datatype: Datatype, span: Span},
+    Deref       { star_token: Token, operand: Box<Expr>, datatype: Datatype, span: Span},
+    New         { new_token: Token, value: Box<Expr>/* copied to the heap */, datatype: Datatype, span: Span},
+    MakeRc      { rc_token: Token, value: Box<Expr>/* moved to the heap */, datatype: Datatype, span: Span},
+    Error       { span: Span } /* stands in for an expression that failed to parse */
+}
+
+impl Decl {
+    /// Replaces type parameters in a declaration nested in a template's body.
+    #[allow(dead_code)]
+    pub fn substitute_types(&mut self, bindings: &HashMap<String, Datatype>) {
+        match self {
+            Decl::Prototype{parameters, returntype, ..} => {
+                for (_, dtype) in parameters.iter_mut() {
+                    *dtype = dtype.substitute(bindings);
+                }
+                *returntype = returntype.substitute(bindings);
+            },
+            Decl::FuncDef{prototype, block, ..} => {
+                prototype.substitute_types(bindings);
+                block.substitute_types(bindings);
+            },
+            Decl::StructDecl{fields, ..} => {
+                for (_, dtype) in fields.iter_mut() {
+                    *dtype = dtype.substitute(bindings);
+                }
+            },
+            Decl::EnumDecl{..} | Decl::ImplDecl{..} | Decl::TraitDecl{..} | Decl::Template{..} => ()
+        }
+    }
+
+    /// Where the declaration is in the source.
+    #[allow(dead_code)]
+    pub fn span(&self) -> Span {
+        match self {
+            Decl::Prototype{span, ..}
+            | Decl::FuncDef{span, ..}
+            | Decl::StructDecl{span, ..}
+            | Decl::ImplDecl{span, ..}
+            | Decl::TraitDecl{span, ..}
+            | Decl::EnumDecl{span, ..}
+            | Decl::Template{span, ..} => *span
+        }
+    }
+
+    /// Prototype of a function definition or of a trait method declaration.
+    #[allow(dead_code)]
+    pub fn get_prototype(&self) -> &Decl {
+        match self {
+            Decl::FuncDef{prototype, ..}    => &**prototype,
+            _                               => self
+        }
+    }
+}
+
+impl Stmt {
+    /// Where the statement is in the source.
+    #[allow(dead_code)]
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::If{span, ..}
+            | Stmt::Block{span, ..}
+            | Stmt::Return{span, ..}
+            | Stmt::While{span, ..}
+            | Stmt::For{span, ..}
+            | Stmt::Loop{span, ..}
+            | Stmt::Expression{span, ..}
+            | Stmt::Decl{span, ..}
+            | Stmt::Var{span, ..}
+            | Stmt::Destructure{span, ..}
+            | Stmt::Break{span, ..}
+            | Stmt::Spawn{span, ..}
+            | Stmt::Delete{span, ..}
+            | Stmt::Select{span, ..}
+            | Stmt::Continue{span, ..}
+            | Stmt::Error{span} => *span
+        }
+    }
+
+    /// Replaces type parameters everywhere in a template's body: annotations, casts, 
+    /// closures, channel constructors and nested declarations.
+    #[allow(dead_code)]
+    pub fn substitute_types(&mut self, bindings: &HashMap<String, Datatype>) {
+        match self {
+            Stmt::Block{statements, ..} => {
+                for statement in statements {
+                    statement.substitute_types(bindings);
+                }
+            },
+            Stmt::If{condition, then_block, else_block, ..} => {
+                condition.substitute_types(bindings);
+                then_block.substitute_types(bindings);
+                if let Some(else_block) = else_block {
+                    else_block.substitute_types(bindings);
+                }
+            },
+            Stmt::While{condition, block, ..} => {
+                condition.substitute_types(bindings);
+                block.substitute_types(bindings);
+            },
+            Stmt::For{initialization, condition, updation, block, ..} => {
+                for expr in [initialization, condition, updation] {
+                    if let Some(expr) = expr {
+                        expr.substitute_types(bindings);
+                    }
+                }
+                block.substitute_types(bindings);
+            },
+            Stmt::Loop{count, block, ..} => {
+                if let Some(count) = count {
+                    count.substitute_types(bindings);
+                }
+                block.substitute_types(bindings);
+            },
+            Stmt::Var{datatype, initialization_value, ..} => {
+                if let Some(dtype) = datatype {
+                    *dtype = dtype.substitute(bindings);
+                }
+                if let Some(value) = initialization_value {
+                    value.substitute_types(bindings);
+                }
+            },
+            Stmt::Return{expr, ..} | Stmt::Expression{expr, ..} | Stmt::Delete{expr, ..}
+            | Stmt::Spawn{call: expr, ..} | Stmt::Destructure{initialization_value: expr, ..}
+                => expr.substitute_types(bindings),
+            Stmt::Select{cases, ..} => {
+                for (case, block) in cases {
+                    match case {
+                        SelectCase::Receive{target, channel, elem_type, ..} => {
+                            if let Some(target) = target {
+                                target.substitute_types(bindings);
+                            }
+                            channel.substitute_types(bindings);
+                            *elem_type = elem_type.substitute(bindings);
+                        },
+                        SelectCase::Send{channel, value, ..} => {
+                            channel.substitute_types(bindings);
+                            value.substitute_types(bindings);
+                        },
+                        SelectCase::Default{..} => ()
+                    }
+                    block.substitute_types(bindings);
+                }
+            },
+            Stmt::Decl{decl, ..} => decl.substitute_types(bindings),
+            Stmt::Break{..} | Stmt::Continue{..} | Stmt::Error{..} => ()
+        }
+    }
+}
+
+impl Expr {
+    /// Replaces type parameters in the types written inside an expression of a template's body.
+    /// Types filled by the type checker are still `yet_to_infer` at this point.
+    #[allow(dead_code)]
+    pub fn substitute_types(&mut self, bindings: &HashMap<String, Datatype>) {
+        match self {
+            Expr::Cast{variable, cast_type, to_dtype, ..} => {
+                variable.substitute_types(bindings);
+                if let Some(dtype) = bindings.get(&cast_type.value) {
+                    match dtype {
+                        Datatype::dyn_trait{..} => *to_dtype = dtype.clone(),
+                        Datatype::object{name} | Datatype::enumeration{name} => cast_type.value = name.clone(),
+                        _ => if let Some(tok_type) = dtype.token_type() {
+                            cast_type.tok_type = tok_type;
+                            cast_type.value = dtype.type_name();
+                        }
+                    }
+                }
+            },
+            Expr::Closure{parameters, returntype, body, ..} => {
+                for (_, dtype) in parameters.iter_mut() {
+                    *dtype = dtype.substitute(bindings);
+                }
+                *returntype = returntype.substitute(bindings);
+                body.substitute_types(bindings);
+            },
+            Expr::MakeChan{elem_type, capacity, ..} => {
+                *elem_type = elem_type.substitute(bindings);
+                if let Some(capacity) = capacity {
+                    capacity.substitute_types(bindings);
+                }
+            },
+            Expr::Match{scrutinee, arms, ..} => {
+                scrutinee.substitute_types(bindings);
+                for (_, arm) in arms {
+                    arm.substitute_types(bindings);
+                }
+            },
+            Expr::Call{callee, arguments, ..} => {
+                callee.substitute_types(bindings);
+                for argument in arguments {
+                    argument.substitute_types(bindings);
+                }
+            },
+            Expr::ExprList{expr_list: exprs, ..} | Expr::ArrayLiteral{elements: exprs, ..} => {
+                for expr in exprs {
+                    expr.substitute_types(bindings);
+                }
+            },
+            Expr::StructExpr{fields, ..} => {
+                for (_, value) in fields {
+                    value.substitute_types(bindings);
+                }
+            },
+            Expr::EnumVariant{values, ..} => {
+                for (_, value) in values {
+                    value.substitute_types(bindings);
+                }
+            },
+            Expr::Binary{lhs, rhs, ..} | Expr::Assignment{target: lhs, expr: rhs, ..}
+            | Expr::Index{array: lhs, index: rhs, ..} | Expr::Send{channel: lhs, value: rhs, ..} => {
+                lhs.substitute_types(bindings);
+                rhs.substitute_types(bindings);
+            },
+            Expr::AttributeRef{object: operand, ..} | Expr::Unary{operand, ..} | Expr::Grouping{expr: operand, ..}
+            | Expr::Receive{channel: operand, ..} | Expr::AddressOf{operand, ..} | Expr::Deref{operand, ..}
+            | Expr::New{value: operand, ..} | Expr::MakeRc{value: operand, ..}
+                => operand.substitute_types(bindings),
+            Expr::Variable{..} | Expr::Literal{..} | Expr::Error{..} => ()
+        }
+    }
+
+    /// Where the expression is in the source.
+    #[allow(dead_code)]
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Variable{span, ..}
+            | Expr::Literal{span, ..}
+            | Expr::Call{span, ..}
+            | Expr::AttributeRef{span, ..}
+            | Expr::Binary{span, ..}
+            | Expr::Unary{span, ..}
+            | Expr::StructExpr{span, ..}
+            | Expr::Assignment{span, ..}
+            | Expr::Grouping{span, ..}
+            | Expr::Cast{span, ..}
+            | Expr::EnumVariant{span, ..}
+            | Expr::Match{span, ..}
+            | Expr::ExprList{span, ..}
+            | Expr::ArrayLiteral{span, ..}
+            | Expr::Index{span, ..}
+            | Expr::Closure{span, ..}
+            | Expr::MakeChan{span, ..}
+            | Expr::Send{span, ..}
+            | Expr::Receive{span, ..}
+            | Expr::AddressOf{span, ..}
+            | Expr::Deref{span, ..}
+            | Expr::New{span, ..}
+            | Expr::MakeRc{span, ..}
+            | Expr::Error{span} => *span
+        }
+    }
+
+    /// Datatype of the expression as filled in by the type checker.
+    #[allow(dead_code)]
+    pub fn get_datatype(&self) -> Datatype {
+        match self {
+            Expr::Variable{datatype, ..}
+            | Expr::Literal{datatype, ..}
+            | Expr::Call{datatype, ..}
+            | Expr::AttributeRef{datatype, ..}
+            | Expr::Binary{datatype, ..}
+            | Expr::Unary{datatype, ..}
+            | Expr::StructExpr{datatype, ..}
+            | Expr::Assignment{datatype, ..}
+            | Expr::Grouping{datatype, ..}
+            | Expr::ExprList{datatype, ..}
+            | Expr::ArrayLiteral{datatype, ..}
+            | Expr::Index{datatype, ..}
+            | Expr::EnumVariant{datatype, ..}
+            | Expr::Match{datatype, ..}
+            | Expr::Closure{datatype, ..}
+            | Expr::MakeChan{datatype, ..}
+            | Expr::Receive{datatype, ..}
+            | Expr::AddressOf{datatype, ..}
+            | Expr::Deref{datatype, ..}
+            | Expr::New{datatype, ..}
+            | Expr::MakeRc{datatype, ..}      => datatype.clone(),
+            Expr::Cast{to_dtype, ..}        => to_dtype.clone(),
+            /* sending doesn't produce a value */
+            Expr::Send{..}                  => Datatype::yet_to_infer,
+            Expr::Error{..}                 => Datatype::yet_to_infer,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::span::Span;
+
+/* error codes. The first digit after `E`/`W` is the phase reporting them:
+   0 lexer, 1 parser, 2 type checker, 3 code generation, 4 driver */
+pub const E_LEX: &str = "E0001";
+pub const E_SYNTAX: &str = "E0100";
+pub const E_TYPE: &str = "E0200";
+pub const E_DUPLICATE_VARIABLE: &str = "E0201";
+pub const E_ARITY: &str = "E0202";
+pub const E_ARGUMENT_TYPE: &str = "E0203";
+pub const E_NOT_CALLABLE: &str = "E0204";
+pub const E_RETURN_TYPE: &str = "E0205";
+pub const E_CODEGEN: &str = "E0300";
+pub const E_DRIVER: &str = "E0400";
+pub const E_IMPORT: &str = "E0401";
+pub const E_UNKNOWN_ITEM: &str = "E0402";
+pub const E_PRIVATE: &str = "E0403";
+pub const W_UNUSED_VARIABLE: &str = "W0201";
+pub const W_UNREACHABLE_ARM: &str = "W0202";
+
+/// How diagnostics are printed. Text goes to stderr with the compiler's other output. JSON goes to stdout,
+/// which carries nothing else, so that tools can parse it without filtering out traces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorFormat {
+    Human, /* message with the source line and a caret underline, on stderr */
+    Json   /* one JSON object per line on stdout, for editors and CI */
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub primary: Span,
+    pub secondary: Vec<Label>, /* related locations, e.g. an earlier declaration */
+    pub notes: Vec<String>,
+    pub suggestions: Vec<String>
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, primary: Span, message: String) -> Self {
+        Diagnostic{ severity, code, message, primary, secondary: vec![], notes: vec![], suggestions: vec![] }
+    }
+
+    pub fn error(code: &'static str, primary: Span, message: String) -> Self {
+        Diagnostic::new(Severity::Error, code, primary, message)
+    }
+
+    pub fn warning(code: &'static str, primary: Span, message: String) -> Self {
+        Diagnostic::new(Severity::Warning, code, primary, message)
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.secondary.push(Label{ span, message });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: String) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+}
+
+struct SourceFile {
+    name: String,
+    lines: Vec<String>
+}
+
+struct DiagnosticSink {
+    format: ErrorFormat,
+    files: Vec<SourceFile>, /* indexed by `Span::file` */
+    diagnostics: Vec<Diagnostic>
+}
+
+/// Collects the diagnostics of every phase. Clones share the same sink, so the lexer,
+/// parser, type checker and code generator all report into one place.
+#[derive(Clone)]
+pub struct Diagnostics {
+    sink: Rc<RefCell<DiagnosticSink>>
+}
+
+impl Diagnostics {
+    pub fn new(format: ErrorFormat) -> Self {
+        Diagnostics{
+            sink: Rc::new(RefCell::new(DiagnosticSink{ format, files: vec![], diagnostics: vec![] }))
+        }
+    }
+
+    /// Registers a source file so that spans can point into it. Returns its index for `Span::file`.
+    pub fn add_file(&self, name: &str, source: &str) -> usize {
+        let mut sink = self.sink.borrow_mut();
+        sink.files.push(SourceFile{
+            name: name.to_string(),
+            lines: source.lines().map(|line| line.to_string()).collect()
+        });
+        sink.files.len() - 1
+    }
+
+    /// Records `diagnostic` and prints it, to stderr as text or to stdout as JSON. 
+    /// Nothing else is written to stdout, so tools can read JSON diagnostics from it.
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        match self.sink.borrow().format {
+            ErrorFormat::Human => eprint!("{}", self.render(&diagnostic)),
+            ErrorFormat::Json => println!("{}", self.render_json(&diagnostic))
+        }
+        self.sink.borrow_mut().diagnostics.push(diagnostic);
+    }
+
+    pub fn error(&self, code: &'static str, span: Span, message: String) {
+        self.emit(Diagnostic::error(code, span, message));
+    }
+
+    pub fn warning(&self, code: &'static str, span: Span, message: String) {
+        self.emit(Diagnostic::warning(code, span, message));
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.sink.borrow().diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    /* error[E0202]: message
+         --> file.bs:3:5
+          |
+        3 |     foo(1, 2);
+          |     ^^^
+          = note: ...                      */
+    fn render(&self, diagnostic: &Diagnostic) -> String {
+        let sink = self.sink.borrow();
+        let mut out = format!("{}[{}]: {}\n", diagnostic.severity.name(), diagnostic.code, diagnostic.message);
+
+        let max_line = diagnostic.secondary.iter()
+            .map(|label| label.span.start.line)
+            .chain(std::iter::once(diagnostic.primary.start.line))
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(max_line.to_string().len());
+
+        if let Some(file) = sink.file(&diagnostic.primary) {
+            out += &format!("{}--> {}:{}:{}\n", gutter, file.name, diagnostic.primary.start.line, diagnostic.primary.start.col);
+            out += &file.snippet(&gutter, &diagnostic.primary, '^', "");
+        }
+        for label in &diagnostic.secondary {
+            match sink.file(&label.span) {
+                Some(file) if label.span.file == diagnostic.primary.file 
+                    => out += &file.snippet(&gutter, &label.span, '-', &label.message),
+                Some(file) => out += &format!("{} = note: {} ({}:{}:{})\n", 
+                    gutter, label.message, file.name, label.span.start.line, label.span.start.col),
+                None => out += &format!("{} = note: {}\n", gutter, label.message)
+            }
+        }
+        for note in &diagnostic.notes {
+            out += &format!("{} = note: {}\n", gutter, note);
+        }
+        for suggestion in &diagnostic.suggestions {
+            out += &format!("{} = help: {}\n", gutter, suggestion);
+        }
+        out
+    }
+
+    /* {"code": "E0202", "severity": "error", "file": "test.bs", "start_line": 3, "start_column": 5, 
+        "end_line": 3, "end_column": 8, "start_offset": 40, "end_offset": 43, "message": "...", "notes": [...], ...}
+       columns are 1-based and the end is exclusive. Locations are null when not known */
+    fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let sink = self.sink.borrow();
+        let strings = |items: &Vec<String>| format!("[{}]", 
+            items.iter().map(|item| json_string(item)).collect::<Vec<String>>().join(", "));
+        let labels: Vec<String> = diagnostic.secondary.iter()
+            .map(|label| format!("{{{}, \"message\": {}}}", sink.json_span(&label.span), json_string(&label.message)))
+            .collect();
+
+        format!("{{\"code\": {}, \"severity\": {}, {}, \"message\": {}, \"notes\": {}, \"suggestions\": {}, \"labels\": [{}]}}",
+            json_string(diagnostic.code), 
+            json_string(diagnostic.severity.name()), 
+            sink.json_span(&diagnostic.primary),
+            json_string(&diagnostic.message),
+            strings(&diagnostic.notes),
+            strings(&diagnostic.suggestions),
+            labels.join(", "))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'  => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl DiagnosticSink {
+    fn file(&self, span: &Span) -> Option<&SourceFile> {
+        if !span.is_known() { return None; }
+        self.files.get(span.file)
+    }
+
+    fn json_span(&self, span: &Span) -> String {
+        match self.file(span) {
+            Some(file) => format!("\"file\": {}, \"start_line\": {}, \"start_column\": {}, \"end_line\": {}, \"end_column\": {}, \"start_offset\": {}, \"end_offset\": {}", 
+                json_string(&file.name), span.start.line, span.start.col, span.end.line, span.end.col, 
+                span.start.offset, span.end.offset),
+            None => "\"file\": null, \"start_line\": null, \"start_column\": null, \"end_line\": null, \"end_column\": null, \"start_offset\": null, \"end_offset\": null".to_string()
+        }
+    }
+}
+
+impl SourceFile {
+    /* the first source line of `span` with `marker` under the spanned characters */
+    fn snippet(&self, gutter: &str, span: &Span, marker: char, label: &str) -> String {
+        let line = match self.lines.get(span.start.line - 1) {
+            Some(line) => line,
+            None => return String::new()
+        };
+        let line_no = format!("{:>width$}", span.start.line, width = gutter.len());
+        let indent: String = line.chars()
+            .take(span.start.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // spans over several lines are underlined till the end of their first line
+        let len = if span.end.line == span.start.line {
+            span.end.col.saturating_sub(span.start.col)
+        } else {
+            line.chars().count().saturating_sub(span.start.col - 1)
+        };
+        let underline = marker.to_string().repeat(len.max(1));
+        let mut out = format!("{} |\n", gutter);
+        out += &format!("{} | {}\n", line_no, line);
+        out += &format!("{} | {}{}", gutter, indent, underline);
+        if !label.is_empty() {
+            out += &format!(" {}", label);
+        }
+        out.push('\n');
+        out
+    }
+}
@@ -5,19 +5,20 @@ pub enum TokenType {
 
     // keywords
     K_VAR, K_STRUCT, K_IMPL, K_TRAIT, K_FUNC, K_OR, K_AND, K_IF, K_ELSE, K_FOR, K_WHILE,
-    K_RETURN, K_AS,/* K_SELF */
+    K_RETURN, K_AS,/* K_SELF */ K_BREAK, K_CONTINUE, K_LOOP, K_DYN, K_ENUM, K_MATCH, K_SPAWN, K_CHAN,
+    K_SELECT, K_CASE, K_DEFAULT, K_MUT, K_NEW, K_DELETE, K_RC, K_MODULE, K_IMPORT, K_PUB,
 
     // datatypes
     K_INT8, K_INT16, K_INT32, K_INT64,
     K_UINT8, K_UINT16, K_UINT32, K_UINT64,
-    K_FLOAT32, K_FLOAT64, K_BOOL, /*OBJECT_TYPE,*/
+    K_FLOAT32, K_FLOAT64, K_BOOL, K_STRING, K_CHAR, /*OBJECT_TYPE,*/
     K_NULL,
 
     K_TRUE, K_FALSE,
 
     IDENTIFIER, 
 
-    INT_LITERAL, HEX_LITERAL, OCTAL_LITERAL, STRING_LITERAL, FLOAT_LITERAL,
+    INT_LITERAL, HEX_LITERAL, OCTAL_LITERAL, STRING_LITERAL, FLOAT_LITERAL, CHAR_LITERAL,
     SEMICOLON, COLON, COMMA, DOT, UNDERSCORE,
 
     // operators
@@ -40,7 +41,8 @@ pub enum TokenType {
     LEFT_SHIFT, LEFT_SHIFT_EQUAL,
     RIGHT_SHIFT, RIGHT_SHIFT_EQUAL,
     
-    RIGHT_ARROW, LEFT_ARROW,
+    RIGHT_ARROW, LEFT_ARROW, FAT_ARROW,
+    COLON_COLON,
     DOLLAR, HASH,
 
     CURLY_OPEN, CURLY_CLOSE,
@@ -59,7 +61,8 @@ impl TokenType {
             TokenType::K_INT32,     TokenType::K_UINT32,
             TokenType::K_INT64,     TokenType::K_UINT64,
             TokenType::K_FLOAT32,   TokenType::K_FLOAT64,
-            TokenType::K_BOOL,      TokenType::IDENTIFIER
+            TokenType::K_BOOL,      TokenType::K_STRING,
+            TokenType::K_CHAR,      TokenType::IDENTIFIER
         ]
     }
 
@@ -133,8 +136,10 @@ impl TokenType {
             TokenType::FLOAT_LITERAL,
             TokenType::OCTAL_LITERAL,
             TokenType::STRING_LITERAL,
+            TokenType::CHAR_LITERAL,
             TokenType::K_TRUE,
-            TokenType::K_FALSE
+            TokenType::K_FALSE,
+            TokenType::K_NULL
         ]
     }
 }
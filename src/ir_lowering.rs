@@ -6,22 +6,24 @@
 
 use std::collections::HashMap;
 
+use inkwell::basic_block::BasicBlock;
 use inkwell::module::Linkage;
 // use inkwell::data_layout::DataLayout;
 // use inkwell::basic_block::BasicBlock;
 // use inkwell::builder;
-use inkwell::types::{BasicTypeEnum, StringRadix};
-use crate::logger;
+use std::convert::TryFrom;
+
+use inkwell::types::{BasicType, BasicTypeEnum, StringRadix, StructType};
+use crate::diagnostics::*;
+use crate::span::Span;
 // use generational_arena::Arena;
 // use inkwell::types::AnyTypeEnum;
 // use inkwell::types::IntType;
 // use inkwell::types::AnyTypeEnum;
-use inkwell::values::{AnyValue, AnyValueEnum, BasicValue, BasicValueEnum, FunctionValue, IntValue};
+use inkwell::values::{AnyValue, AnyValueEnum, BasicValue, BasicValueEnum, CallableValue, FunctionValue, GlobalValue, IntValue};
 
 use crate::globals::{self, Scope, TokenType};
 use crate::lexer::Token;
-use crate::logger::log_message;
-// use crate::logger::log_message;
 use crate::symbol_table::IRSymbolTable;
 use crate::visitor::{VisitorWithLifeTime};
 // use crate::visitor::MutableVisitor;
@@ -38,7 +40,17 @@ pub struct Codegen<'a, 'ctx> {
     pub symbol_table: IRSymbolTable<'ctx>,
     pub current_scope: globals::Scope,
     pub curr_fn_value : Option<FunctionValue<'ctx>>,
-    pub is_parsing_lvalue: bool
+    pub is_parsing_lvalue: bool,
+    /* innermost loop is at the top. (block `break` jumps to, block `continue` jumps to, open rc scopes outside the loop) */
+    pub loop_stack: Vec<(BasicBlock<'ctx>, BasicBlock<'ctx>, usize)>,
+    /* variables holding rcs in each open block of the current function, innermost last. 
+       They are released when their block is left */
+    pub rc_scopes: Vec<Vec<(inkwell::values::PointerValue<'ctx>, Datatype)>>,
+    /* set while the body of a function is visited, whose trailing expression is returned */
+    pub is_function_body: bool,
+    /* set while the trailing `if` of a function body is visited. The trailing expressions of its blocks are returned */
+    pub is_returned_if: bool,
+    pub diagnostics: Diagnostics
 }
 
 
@@ -53,28 +65,54 @@ impl<'a, 'ctx>
     for Codegen<'a, 'ctx> {
     fn visit_expr(&mut self, expr: &Expr) -> inkwell::values::BasicValueEnum<'ctx> {
         match expr {
-            Expr::Assignment{target, operator, expr, datatype} 
+            Expr::Assignment{target, operator, expr, datatype, ..} 
                 => self.visit_assignment_expr(target, operator, expr, datatype),
-            Expr::AttributeRef{object, name, object_dtype, datatype} 
+            Expr::AttributeRef{object, name, object_dtype, datatype, ..} 
                 => self.visit_attributeref_expr(object, name, object_dtype, datatype),
-            Expr::Binary{lhs, rhs, operator, datatype} 
+            Expr::Binary{lhs, rhs, operator, datatype, ..} 
                 => self.visit_binary_expr(lhs, rhs, operator, datatype),
-            Expr::Call{callee, arguments, datatype} 
+            Expr::Call{callee, arguments, datatype, ..} 
                 => self.visit_call_expr(callee, arguments, datatype),
-            Expr::Cast{variable, cast_type, from_dtype, to_dtype} 
+            Expr::Cast{variable, cast_type, from_dtype, to_dtype, ..} 
                 => self.visit_cast_expr(variable, cast_type, from_dtype, to_dtype),
-            Expr::ExprList{expr_list, datatype} 
+            Expr::ExprList{expr_list, datatype, ..} 
                 => self.visit_exprlist_expr(expr_list, datatype),
-            Expr::Grouping{expr, datatype} 
+            Expr::ArrayLiteral{bracket_token, elements, datatype, ..} 
+                => self.visit_array_literal_expr(bracket_token, elements, datatype),
+            Expr::Index{array, index, bracket_token, datatype, ..} 
+                => self.visit_index_expr(array, index, bracket_token, datatype),
+            Expr::Grouping{expr, datatype, ..} 
                 => self.visit_grouping_expr(expr, datatype),
-            Expr::Literal{value, datatype} 
+            Expr::Literal{value, datatype, ..} 
                 => self.visit_literal_expr(value, datatype),
-            Expr::StructExpr{struct_name, fields, datatype} 
+            Expr::StructExpr{struct_name, fields, datatype, ..} 
                 => self.visit_struct_expr(struct_name, fields, datatype),
-            Expr::Unary{operator, operand, datatype} 
+            Expr::Unary{operator, operand, datatype, ..} 
                 => self.visit_unary_expr(operator, operand, datatype),
-            Expr::Variable{name, datatype, struct_name} 
+            Expr::Variable{name, datatype, struct_name, ..} 
                 => self.visit_variable_expr(name, datatype, struct_name),
+            Expr::EnumVariant{enum_name, variant_name, values, datatype, ..}
+                => self.visit_enum_variant_expr(enum_name, variant_name, values, datatype),
+            Expr::Match{match_token, scrutinee, arms, datatype, ..}
+                => self.visit_match_expr(match_token, scrutinee, arms, datatype),
+            Expr::Closure{func_token, parameters, returntype, body, captures, datatype, ..}
+                => self.visit_closure_expr(func_token, parameters, returntype, body, captures, datatype),
+            Expr::MakeChan{chan_token, elem_type, capacity, datatype, ..}
+                => self.visit_make_chan_expr(chan_token, elem_type, capacity, datatype),
+            Expr::Send{channel, arrow_token, value, ..}
+                => self.visit_send_expr(channel, arrow_token, value),
+            Expr::Receive{arrow_token, channel, datatype, ..}
+                => self.visit_receive_expr(arrow_token, channel, datatype),
+            Expr::AddressOf{amp_token, mutable, operand, datatype, ..}
+                => self.visit_address_of_expr(amp_token, mutable, operand, datatype),
+            Expr::Deref{star_token, operand, datatype, ..}
+                => self.visit_deref_expr(star_token, operand, datatype),
+            Expr::New{new_token, value, datatype, ..}
+                => self.visit_new_expr(new_token, value, datatype),
+            Expr::MakeRc{rc_token, value, datatype, ..}
+                => self.visit_make_rc_expr(rc_token, value, datatype),
+            Expr::Error{..}
+                => unreachable!("code generation doesn't run on programs with syntax errors"),
             // _ => inkwell::values::AnyValueEnum::ArrayValue(_),
         }
         // unimplemented!();
@@ -82,16 +120,18 @@ impl<'a, 'ctx>
 
     fn visit_decl(&mut self, decl: &Decl) -> Option<inkwell::values::AnyValueEnum<'ctx>> {
         match decl {
-            Decl::FuncDef{prototype, block} 
+            Decl::FuncDef{prototype, block, ..} 
                 => self.visit_funcdef_decl(prototype, block),
-            Decl::ImplDecl{name, trait_name, funcs} 
+            Decl::ImplDecl{name, trait_name, funcs, ..} 
                 => self.visit_impl_decl(name, trait_name, funcs),
-            Decl::Prototype{name, parameters, returntype} 
+            Decl::Prototype{name, parameters, returntype, ..} 
                 => self.visit_prototype_decl(name, parameters, returntype),
-            Decl::StructDecl{name, fields} 
+            Decl::StructDecl{name, fields, ..} 
                 => self.visit_struct_decl(name, fields),
-            Decl::TraitDecl{name, funcs} 
+            Decl::TraitDecl{name, funcs, ..} 
                 => self.visit_trait_decl(name, funcs),
+            Decl::EnumDecl{name, variants, ..}
+                => self.visit_enum_decl(name, variants),
             _ => None
         }
         // unimplemented!();
@@ -99,32 +139,48 @@ impl<'a, 'ctx>
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> Option<inkwell::values::AnyValueEnum<'ctx>> {
         match stmt {
-            Stmt::Block{statements}     
+            Stmt::Block{statements, ..}     
                 => self.visit_block_stmt(statements),
-            Stmt::Decl{decl} 
+            Stmt::Decl{decl, ..} 
                 => self.visit_decl_stmt(decl),
-            Stmt::Expression{expr} 
+            Stmt::Expression{expr, ..} 
                 => self.visit_expression_stmt(expr),
             Stmt::For{
                 for_token, 
                 initialization, 
                 condition, 
                 updation,
-                block
+                block, ..
             } 
                 => self.visit_for_stmt(for_token, initialization, condition, updation, block),
             Stmt::If{
                 if_token,
                 condition,
                 then_block,
-                else_block
+                else_block, ..
             } => self.visit_if_stmt(if_token, condition, then_block, else_block),
-            Stmt::Return{expr} 
+            Stmt::Return{expr, ..} 
                 => self.visit_return_stmt(expr),
-            Stmt::Var{name, datatype, initialization_value} 
+            Stmt::Var{name, datatype, initialization_value, ..} 
                 => self.visit_var_stmt(name, datatype, initialization_value),
-            Stmt::While{..} => None,
-            _ => None
+            Stmt::Destructure{names, initialization_value, ..}
+                => self.visit_destructure_stmt(names, initialization_value),
+            Stmt::While{while_token, condition, block, ..}
+                => self.visit_while_stmt(while_token, condition, block),
+            Stmt::Loop{loop_token, count, block, ..}
+                => self.visit_loop_stmt(loop_token, count, block),
+            Stmt::Break{break_token, ..}
+                => self.visit_break_stmt(break_token),
+            Stmt::Continue{continue_token, ..}
+                => self.visit_continue_stmt(continue_token),
+            Stmt::Spawn{spawn_token, call, ..}
+                => self.visit_spawn_stmt(spawn_token, call),
+            Stmt::Select{select_token, cases, ..}
+                => self.visit_select_stmt(select_token, cases),
+            Stmt::Delete{delete_token, expr, ..}
+                => self.visit_delete_stmt(delete_token, expr),
+            Stmt::Error{..}
+                => unreachable!("code generation doesn't run on programs with syntax errors"),
         }
         // unimplemented!();
     }
@@ -269,33 +325,80 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         // void println();
         let func = self.context.void_type().fn_type(&[], false);
         self.module.add_function("println", func, Some(Linkage::External));
-    }
 
-    fn get_type(&mut self, token: &Token) -> inkwell::types::BasicTypeEnum<'ctx> {
-        match token {
-            Token{tok_type: TokenType::K_INT8, ..} 
-            | Token{tok_type: TokenType::K_UINT8, ..} => self.context.i8_type().into(),
-            Token{tok_type: TokenType::K_INT16, ..} 
-            | Token{tok_type: TokenType::K_UINT16, ..} => self.context.i16_type().into(),
-            Token{tok_type: TokenType::K_INT32, ..} 
-            | Token{tok_type: TokenType::K_UINT32, ..} => self.context.i32_type().into(),
-            Token{tok_type: TokenType::K_INT64, ..} 
-            | Token{tok_type: TokenType::K_UINT64, ..} => self.context.i64_type().into(),
-
-            Token{tok_type: TokenType::K_FLOAT32, ..} => self.context.f32_type().into(),
-            Token{tok_type: TokenType::K_FLOAT64, ..} => self.context.f64_type().into(),
-
-            Token{tok_type: TokenType::K_BOOL, ..} => self.context.bool_type().into(),
-            Token{tok_type: TokenType::IDENTIFIER, value, ..} => {
-                if let Some(struct_type) = self.module.get_struct_type(value) {
-                    return struct_type.into();
-                } else {
-                    return self.context.opaque_struct_type(value).into();
-                }
-            }
-            
-            _ => self.context.bool_type().into()
-        }
+        // void printc(uint32_t); uint32_t scanc();
+        let func = self.context.void_type().fn_type(&[BasicTypeEnum::IntType(self.context.i32_type())], false);
+        self.module.add_function("printc", func, Some(Linkage::External));
+        let func = self.context.i32_type().fn_type(&[], false);
+        self.module.add_function("scanc", func, Some(Linkage::External));
+
+        // void printstr(const char*); int64_t lenstr(const char*); bool eqstr(const char*, const char*);
+        let str_type = BasicTypeEnum::PointerType(self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic));
+        let func = self.context.void_type().fn_type(&[str_type], false);
+        self.module.add_function("printstr", func, Some(Linkage::External));
+        let func = self.context.i64_type().fn_type(&[str_type], false);
+        self.module.add_function("lenstr", func, Some(Linkage::External));
+        let func = self.context.bool_type().fn_type(&[str_type, str_type], false);
+        self.module.add_function("eqstr", func, Some(Linkage::External));
+
+        // void array_index_out_of_bounds(int64_t line, int64_t index, int64_t size);
+        let i64_type = BasicTypeEnum::IntType(self.context.i64_type());
+        let func = self.context.void_type().fn_type(&[i64_type, i64_type, i64_type], false);
+        self.module.add_function("array_index_out_of_bounds", func, Some(Linkage::External));
+
+        // i8* malloc(int64_t size); argument boxes of spawned tasks
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let func = i8_ptr_type.fn_type(&[i64_type], false);
+        self.module.add_function("malloc", func, Some(Linkage::External));
+
+        // void free(i8*); void spawn_task(void (*)(i8*), i8*); void waitall();
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("free", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false);
+        self.module.add_function("spawn_task", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[], false);
+        self.module.add_function("waitall", func, Some(Linkage::External));
+
+        // i8* chan_make(int64_t elem_size, int64_t capacity); void chan_send(i8*, i8* value, int64_t line);
+        // bool chan_recv(i8*, i8* value); void chan_close(i8*, int64_t line);
+        let func = i8_ptr_type.fn_type(&[i64_type, i64_type], false);
+        self.module.add_function("chan_make", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into(), i8_ptr_type.into(), i64_type], false);
+        self.module.add_function("chan_send", func, Some(Linkage::External));
+        let func = self.context.bool_type().fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false);
+        self.module.add_function("chan_recv", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into(), i64_type], false);
+        self.module.add_function("chan_close", func, Some(Linkage::External));
+
+        // int64_t chan_select(select_case_t* cases, int64_t count, bool has_default, int64_t line);
+        let func = i64_type.fn_type(&[i8_ptr_type.into(), i64_type, self.context.bool_type().into(), i64_type], false);
+        self.module.add_function("chan_select", func, Some(Linkage::External));
+
+        // i8* heap_alloc(int64_t size, int64_t line); void heap_free(i8*); void null_dereference(int64_t line);
+        let func = i8_ptr_type.fn_type(&[i64_type, i64_type], false);
+        self.module.add_function("heap_alloc", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("heap_free", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i64_type], false);
+        self.module.add_function("null_dereference", func, Some(Linkage::External));
+
+        // i8* rc_alloc(int64_t size, int64_t line); void rc_retain(i8*); void rc_release(i8*, void (*drop)(i8*)); 
+        // int64_t rclive();
+        let func = i8_ptr_type.fn_type(&[i64_type, i64_type], false);
+        self.module.add_function("rc_alloc", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("rc_retain", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false);
+        self.module.add_function("rc_release", func, Some(Linkage::External));
+        let func = i64_type.fn_type(&[], false);
+        self.module.add_function("rclive", func, Some(Linkage::External));
+
+        // i8* env_alloc(int64_t size, void (*drop)(i8*), int64_t line); void env_retain(i8*); void env_release(i8*);
+        let func = i8_ptr_type.fn_type(&[i64_type, i8_ptr_type.into(), i64_type], false);
+        self.module.add_function("env_alloc", func, Some(Linkage::External));
+        let func = self.context.void_type().fn_type(&[i8_ptr_type.into()], false);
+        self.module.add_function("env_retain", func, Some(Linkage::External));
+        self.module.add_function("env_release", func, Some(Linkage::External));
     }
 
     fn get_llvm_type(&mut self, datatype: &Datatype) -> inkwell::types::BasicTypeEnum<'ctx> {
@@ -303,14 +406,851 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             Datatype::int8      |   Datatype::uint8     => self.context.i8_type().into(),
             Datatype::int16     |   Datatype::uint16    => self.context.i16_type().into(),
             Datatype::int32     |   Datatype::uint32    => self.context.i32_type().into(),
+            Datatype::char                              => self.context.i32_type().into(),
             Datatype::int64     |   Datatype::uint64    => self.context.i64_type().into(),
             Datatype::float32                           => self.context.f32_type().into(),
             Datatype::float64                           => self.context.f64_type().into(),
             Datatype::bool                              => self.context.bool_type().into(),
+            Datatype::string    => self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic).into(),
+            Datatype::object{name}  => {
+                if let Some(struct_type) = self.module.get_struct_type(name) {
+                    return struct_type.into();
+                } else {
+                    return self.context.opaque_struct_type(name).into();
+                }
+            },
+            Datatype::array{elem_type, size}
+                => self.get_llvm_type(elem_type).array_type(*size as u32).into(),
+            Datatype::dyn_trait{..} => self.dyn_trait_type().into(),
+            Datatype::enumeration{name} => self.module.get_struct_type(name).unwrap().into(),
+            Datatype::function{..} => self.closure_type().into(),
+            Datatype::channel{..} => self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic).into(),
+            Datatype::reference{elem_type, ..} => self.get_llvm_type(elem_type).ptr_type(inkwell::AddressSpace::Generic).into(),
+            /* the count is kept by the runtime, in front of the value */
+            Datatype::rc{elem_type} => self.get_llvm_type(elem_type).ptr_type(inkwell::AddressSpace::Generic).into(),
+            /* returned as an LLVM struct */
+            Datatype::tuple{elem_types} => {
+                let elem_types: Vec<BasicTypeEnum> = elem_types.iter().map(|dtype| self.get_llvm_type(dtype)).collect();
+                self.context.struct_type(elem_types.as_slice(), false).into()
+            },
             _   => self.context.bool_type().into()
         }
     }
 
+    /* function value: { i8* function, i8* environment }. 
+       The function takes the environment as its first parameter, followed by the declared ones. */
+    fn closure_type(&self) -> StructType<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        return self.context.struct_type(&[i8_ptr_type.into(), i8_ptr_type.into()], false);
+    }
+
+    fn build_closure_value(&mut self, function: FunctionValue<'ctx>, env: inkwell::values::PointerValue<'ctx>)
+    -> BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let fn_ptr = self.builder.build_pointer_cast(
+            function.as_global_value().as_pointer_value(), i8_ptr_type, "closure.fn");
+        let closure = self.builder.build_insert_value(
+            self.closure_type().get_undef(), fn_ptr, 0, "closure.insert"
+        ).unwrap().into_struct_value();
+        let closure = self.builder.build_insert_value(closure, env, 1, "closure").unwrap().into_struct_value();
+        return BasicValueEnum::StructValue(closure);
+    }
+
+    /* a named function used as a value. It is wrapped in `name.closure`, which ignores the environment. */
+    fn function_closure(&mut self, function: FunctionValue<'ctx>) -> BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let thunk_name = function.get_name().to_str().unwrap().to_string() + ".closure";
+        let thunk = match self.module.get_function(&thunk_name) {
+            Some(thunk) => thunk,
+            None => {
+                let mut param_types: Vec<BasicTypeEnum> = vec![i8_ptr_type.into()];
+                param_types.extend(function.get_type().get_param_types());
+                // void runtime functions return a `bool` zero, like calls to them do.
+                let ret_type = function.get_type().get_return_type()
+                    .unwrap_or(self.context.bool_type().into());
+                let thunk = self.module.add_function(
+                    &thunk_name, ret_type.fn_type(param_types.as_slice(), false), Some(Linkage::Internal));
+
+                let prev_block = self.builder.get_insert_block();
+                let entry = self.context.append_basic_block(thunk, "entry");
+                self.builder.position_at_end(entry);
+                let args: Vec<BasicValueEnum> = thunk.get_param_iter().skip(1).collect();
+                let call_val = self.builder.build_call(function, &args, "closure.call").try_as_basic_value();
+                match call_val.left() {
+                    Some(val) => self.builder.build_return(Some(&val)),
+                    None => self.builder.build_return(Some(&self.context.bool_type().const_zero()))
+                };
+                if let Some(block) = prev_block {
+                    self.builder.position_at_end(block);
+                }
+                thunk
+            }
+        };
+        return self.build_closure_value(thunk, i8_ptr_type.const_null());
+    }
+
+    fn call_closure(&mut self, closure: BasicValueEnum<'ctx>, fn_dtype: &Datatype, arguments: &Vec<Box<Expr>>)
+    -> BasicValueEnum<'ctx> {
+        let mut args = vec![];
+        for argument in arguments {
+            args.push(self.visit_argument(argument));
+        }
+        return self.call_closure_with_values(closure, fn_dtype, args);
+    }
+
+    fn call_closure_with_values(&mut self, 
+        closure: BasicValueEnum<'ctx>, 
+        fn_dtype: &Datatype, 
+        arguments: Vec<BasicValueEnum<'ctx>>)
+    -> BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let closure = if closure.is_pointer_value() {
+            self.builder.build_load(closure.into_pointer_value(), "closure.load").into_struct_value()
+        } else {
+            closure.into_struct_value()
+        };
+        let fn_ptr = self.builder.build_extract_value(closure, 0, "closure.fn").unwrap();
+        let env = self.builder.build_extract_value(closure, 1, "closure.env").unwrap();
+
+        let mut param_types: Vec<BasicTypeEnum> = vec![i8_ptr_type.into()];
+        let mut ret_type = self.context.bool_type().into();
+        if let Datatype::function{returntype, param_types: dtypes, ..} = fn_dtype {
+            for dtype in dtypes {
+                param_types.push(self.get_llvm_type(dtype));
+            }
+            ret_type = self.get_llvm_type(returntype);
+        }
+        let fn_type = ret_type.fn_type(param_types.as_slice(), false);
+        let fn_ptr = self.builder.build_pointer_cast(
+            fn_ptr.into_pointer_value(), 
+            fn_type.ptr_type(inkwell::AddressSpace::Generic), 
+            "closure.fn.typed"
+        );
+
+        let mut args = vec![env];
+        args.extend(arguments);
+
+        let call_val = self.builder.build_call(
+            CallableValue::try_from(fn_ptr).unwrap(), &args, "closure.call").try_as_basic_value();
+        if call_val.is_left() {
+            return call_val.left().unwrap();
+        } else {
+            return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
+        }
+    }
+
+    /* allocas in the entry block are made once per call, not once per loop iteration. 
+       Matters for tasks, whose stacks are small. */
+    fn build_entry_alloca(&mut self, ty: BasicTypeEnum<'ctx>, name: &str) -> inkwell::values::PointerValue<'ctx> {
+        let entry = self.curr_fn_value.unwrap().get_first_basic_block().unwrap();
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry)
+        }
+        return builder.build_alloca(ty, name);
+    }
+
+    /* channels live in the runtime. A channel value is a pointer to it */
+    fn visit_make_chan_expr(&mut self, 
+        chan_token: &Token, 
+        elem_type: &Datatype, 
+        capacity: &Option<Box<Expr>>, 
+        datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let i64_type = self.context.i64_type();
+        let elem_size = self.get_llvm_type(elem_type).size_of().unwrap();
+        let capacity = match capacity {
+            Some(capacity) => {
+                let val = self.visit_expr(capacity).into_int_value();
+                self.builder.build_int_cast(val, i64_type, "chan.capacity")
+            },
+            None => i64_type.const_zero()
+        };
+        let chan_make = self.module.get_function("chan_make").unwrap();
+        return self.builder.build_call(chan_make, &[elem_size.into(), capacity.into()], "chan")
+            .try_as_basic_value().left().unwrap();
+    }
+
+    fn visit_send_expr(&mut self, channel: &Box<Expr>, arrow_token: &Token, value: &Box<Expr>)
+    -> BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let chan = self.visit_expr(channel);
+        let val = self.visit_expr(value);
+        self.rc_copy(value, val);
+
+        // the runtime copies the value from memory
+        let slot = self.build_entry_alloca(val.get_type(), "chan.send.value");
+        self.builder.build_store(slot, val);
+        let slot = self.builder.build_pointer_cast(slot, i8_ptr_type, "chan.send.ptr");
+        let line = self.context.i64_type().const_int(arrow_token.span.start.line as u64, false);
+        let chan_send = self.module.get_function("chan_send").unwrap();
+        self.builder.build_call(chan_send, &[chan.into(), slot.into(), line.into()], "");
+        return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
+    }
+
+    fn visit_receive_expr(&mut self, arrow_token: &Token, channel: &Box<Expr>, datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let chan = self.visit_expr(channel);
+
+        let elem_type = self.get_llvm_type(datatype);
+        let slot = self.build_entry_alloca(elem_type, "chan.recv.value");
+        let slot_ptr = self.builder.build_pointer_cast(slot, i8_ptr_type, "chan.recv.ptr");
+        let chan_recv = self.module.get_function("chan_recv").unwrap();
+        self.builder.build_call(chan_recv, &[chan.into(), slot_ptr.into()], "");
+        return self.builder.build_load(slot, "chan.recv");
+    }
+
+    fn close_channel(&mut self, channel: &Box<Expr>, close_token: &Token) -> BasicValueEnum<'ctx> {
+        let chan = self.visit_expr(channel);
+        let line = self.context.i64_type().const_int(close_token.span.start.line as u64, false);
+        let chan_close = self.module.get_function("chan_close").unwrap();
+        self.builder.build_call(chan_close, &[chan.into(), line.into()], "");
+        return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
+    }
+
+    /* captured variables are copied into a heap allocated environment when the closure is created, 
+       so the closure may outlive the function creating it */
+    /* a reference is the address of its place */
+    fn visit_address_of_expr(&mut self, amp_token: &Token, mutable: &bool, operand: &Box<Expr>, datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = true;
+        let ptr = self.visit_expr(operand);
+        self.is_parsing_lvalue = is_parsing_lvalue;
+        return ptr;
+    }
+
+    fn visit_deref_expr(&mut self, star_token: &Token, operand: &Box<Expr>, datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = false;
+        let ptr = self.visit_expr(operand).into_pointer_value();
+        self.is_parsing_lvalue = is_parsing_lvalue;
+        self.check_null(ptr, star_token);
+        if self.is_parsing_lvalue {
+            return BasicValueEnum::PointerValue(ptr);
+        }
+        return self.builder.build_load(ptr, "deref");
+    }
+
+    /* pointer to the object of an attribute reference or method call. A reference already is one */
+    fn object_ptr(&mut self, object: &Box<Expr>, object_dtype: &Datatype, name: &Token) -> BasicValueEnum<'ctx> {
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = match object_dtype {
+            Datatype::reference{..} | Datatype::rc{..} => false,
+            _ => true
+        };
+        let obj_ptr = self.visit_expr(object);
+        self.is_parsing_lvalue = is_parsing_lvalue;
+        if Datatype::is_reference(object_dtype) || Datatype::is_rc(object_dtype) {
+            self.check_null(obj_ptr.into_pointer_value(), name);
+        }
+        return obj_ptr;
+    }
+
+    /* aborts with a runtime error when `ptr` is null */
+    fn check_null(&mut self, ptr: inkwell::values::PointerValue<'ctx>, token: &Token) {
+        let current_fn = self.curr_fn_value.unwrap();
+        let is_null = self.builder.build_is_null(ptr, "ptr.isnull");
+        let fail_bb = self.context.append_basic_block(current_fn, "null.fail");
+        let ok_bb = self.context.append_basic_block(current_fn, "null.ok");
+        self.builder.build_conditional_branch(is_null, fail_bb, ok_bb);
+
+        self.builder.position_at_end(fail_bb);
+        let null_fn = self.module.get_function("null_dereference").unwrap();
+        let line = self.context.i64_type().const_int(token.span.start.line as u64, false);
+        self.builder.build_call(null_fn, &[line.into()], "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_bb);
+    }
+
+    /* the value is placed in zeroed heap memory, which lives until it is deleted */
+    fn visit_new_expr(&mut self, new_token: &Token, value: &Box<Expr>, datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let value_type = self.get_llvm_type(&value.get_datatype());
+        let heap_alloc = self.module.get_function("heap_alloc").unwrap();
+        let line = self.context.i64_type().const_int(new_token.span.start.line as u64, false);
+        let raw = self.builder.build_call(
+            heap_alloc, &[value_type.size_of().unwrap().into(), line.into()], "new.raw"
+        ).try_as_basic_value().left().unwrap().into_pointer_value();
+        let ptr = self.builder.build_pointer_cast(raw, value_type.ptr_type(inkwell::AddressSpace::Generic), "new.ptr");
+
+        let val = self.visit_expr(value);
+        self.rc_copy(value, val);
+        self.builder.build_store(ptr, val);
+        return BasicValueEnum::PointerValue(ptr);
+    }
+
+    fn visit_delete_stmt(&mut self, delete_token: &Token, expr: &Box<Expr>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let ptr = self.visit_expr(expr).into_pointer_value();
+        let value_dtype = expr.get_datatype().auto_deref().clone();
+        if self.contains_rc(&value_dtype) {
+            let value = self.builder.build_load(ptr, "delete.value");
+            self.rc_release_value(value, &value_dtype);
+        }
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let raw = self.builder.build_pointer_cast(ptr, i8_ptr_type, "delete.raw");
+        let heap_free = self.module.get_function("heap_free").unwrap();
+        self.builder.build_call(heap_free, &[raw.into()], "");
+        return None;
+    }
+
+    /* the value is moved to the heap, behind a count kept by the runtime */
+    fn visit_make_rc_expr(&mut self, rc_token: &Token, value: &Box<Expr>, datatype: &Datatype)
+    -> BasicValueEnum<'ctx> {
+        let value_type = self.get_llvm_type(&value.get_datatype());
+        let rc_alloc = self.module.get_function("rc_alloc").unwrap();
+        let line = self.context.i64_type().const_int(rc_token.span.start.line as u64, false);
+        let raw = self.builder.build_call(
+            rc_alloc, &[value_type.size_of().unwrap().into(), line.into()], "rc.raw"
+        ).try_as_basic_value().left().unwrap().into_pointer_value();
+        let ptr = self.builder.build_pointer_cast(raw, value_type.ptr_type(inkwell::AddressSpace::Generic), "rc.ptr");
+
+        let val = self.visit_expr(value);
+        self.rc_copy(value, val);
+        self.builder.build_store(ptr, val);
+        return BasicValueEnum::PointerValue(ptr);
+    }
+
+    /* whether values of the type hold rcs, whose counts change when the values are copied. 
+       Function values count the environment of their closure the same way */
+    fn contains_rc(&self, dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::rc{..} | Datatype::function{..} => true,
+            Datatype::array{elem_type, ..} => self.contains_rc(elem_type),
+            Datatype::tuple{elem_types} => elem_types.iter().any(|elem_type| self.contains_rc(elem_type)),
+            Datatype::object{name} => match self.symbol_table.struct_decls.get(name) {
+                Some(Decl::StructDecl{fields, ..}) => fields.iter().any(|(_, field_type)| self.contains_rc(field_type)),
+                _ => false
+            },
+            _ => false
+        }
+    }
+
+    /* values made by the expression itself are counted already. Values read from a place are copies, 
+       which are counted when they are stored */
+    fn is_fresh_value(expr: &Expr) -> bool {
+        match expr {
+            Expr::Call{..} | Expr::MakeRc{..} | Expr::StructExpr{..} | Expr::ArrayLiteral{..} | Expr::Literal{..} => true,
+            // a closure starts with one count. A sent value was counted for the channel, which gives it up
+            Expr::Closure{..} | Expr::Receive{..} => true,
+            Expr::ExprList{expr_list, ..} if expr_list.len() == 1 => Codegen::is_fresh_value(&expr_list[0]),
+            Expr::ExprList{..} => true,
+            Expr::Grouping{expr, ..} => Codegen::is_fresh_value(expr),
+            _ => false
+        }
+    }
+
+    /* counts the rcs in `value`, which is about to be stored, unless `expr` made it */
+    fn rc_copy(&mut self, expr: &Expr, value: BasicValueEnum<'ctx>) {
+        let dtype = expr.get_datatype();
+        if self.contains_rc(&dtype) && !Codegen::is_fresh_value(expr) {
+            self.rc_retain_value(value, &dtype);
+        }
+    }
+
+    /* arguments are owned by the callee, which releases them when it returns */
+    fn visit_argument(&mut self, argument: &Box<Expr>) -> BasicValueEnum<'ctx> {
+        let value = self.visit_expr(argument);
+        self.rc_copy(argument, value);
+        return value;
+    }
+
+    /* stores `value` of `expr` to `ptr`, releasing the rcs of the value it replaces */
+    fn rc_store(&mut self, ptr: inkwell::values::PointerValue<'ctx>, value: BasicValueEnum<'ctx>, expr: &Expr) {
+        self.rc_copy(expr, value);
+        self.rc_replace(ptr, value, &expr.get_datatype());
+    }
+
+    /* like `rc_store`, for a value whose rcs are counted already */
+    fn rc_replace(&mut self, ptr: inkwell::values::PointerValue<'ctx>, value: BasicValueEnum<'ctx>, dtype: &Datatype) {
+        if !self.contains_rc(dtype) {
+            self.builder.build_store(ptr, value);
+            return;
+        }
+        let old_value = self.builder.build_load(ptr, "rc.old");
+        self.builder.build_store(ptr, value);
+        self.rc_release_value(old_value, dtype);
+    }
+
+    fn rc_retain_value(&mut self, value: BasicValueEnum<'ctx>, dtype: &Datatype) {
+        self.rc_count_value(value, dtype, true);
+    }
+
+    fn rc_release_value(&mut self, value: BasicValueEnum<'ctx>, dtype: &Datatype) {
+        self.rc_count_value(value, dtype, false);
+    }
+
+    /* retains or releases every rc held by `value` */
+    fn rc_count_value(&mut self, value: BasicValueEnum<'ctx>, dtype: &Datatype, retain: bool) {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        match dtype {
+            Datatype::rc{elem_type} => {
+                let ptr = self.builder.build_pointer_cast(value.into_pointer_value(), i8_ptr_type, "rc.raw");
+                if retain {
+                    let rc_retain = self.module.get_function("rc_retain").unwrap();
+                    self.builder.build_call(rc_retain, &[ptr.into()], "");
+                } else {
+                    let drop = self.rc_drop_function(elem_type);
+                    let rc_release = self.module.get_function("rc_release").unwrap();
+                    self.builder.build_call(rc_release, &[ptr.into(), drop.into()], "");
+                }
+            },
+            Datatype::function{..} => {
+                let env = self.builder.build_extract_value(value.into_struct_value(), 1, "closure.env").unwrap();
+                let count_fn = self.module.get_function(if retain { "env_retain" } else { "env_release" }).unwrap();
+                self.builder.build_call(count_fn, &[env.into()], "");
+            },
+            Datatype::array{elem_type, size} if self.contains_rc(elem_type) => {
+                for i in 0..*size {
+                    let elem = self.builder.build_extract_value(value.into_array_value(), i as u32, "rc.elem").unwrap();
+                    self.rc_count_value(elem, elem_type, retain);
+                }
+            },
+            Datatype::tuple{elem_types} => {
+                for (i, elem_type) in elem_types.iter().enumerate() {
+                    if self.contains_rc(elem_type) {
+                        let elem = self.builder.build_extract_value(value.into_struct_value(), i as u32, "rc.elem").unwrap();
+                        self.rc_count_value(elem, elem_type, retain);
+                    }
+                }
+            },
+            Datatype::object{name} => {
+                let field_types = match self.symbol_table.struct_decls.get(name) {
+                    Some(Decl::StructDecl{fields, ..}) => fields.iter().map(|(_, dtype)| dtype.clone()).collect(),
+                    _ => vec![]
+                };
+                for (i, field_type) in field_types.iter().enumerate() {
+                    if self.contains_rc(field_type) {
+                        let field = self.builder.build_extract_value(value.into_struct_value(), i as u32, "rc.field").unwrap();
+                        self.rc_count_value(field, field_type, retain);
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+
+    /* `rc.drop.T` releases the rcs held by a T whose last rc went away. Null when a T holds none */
+    fn rc_drop_function(&mut self, elem_type: &Datatype) -> inkwell::values::PointerValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        if !self.contains_rc(elem_type) {
+            return i8_ptr_type.const_null();
+        }
+        let drop_name = format!("rc.drop.{}", elem_type.type_name());
+        let function = match self.module.get_function(&drop_name) {
+            Some(function) => function,
+            None => {
+                // added before its body is built, as the body of a recursive type refers to it
+                let function = self.module.add_function(
+                    &drop_name, 
+                    self.context.void_type().fn_type(&[i8_ptr_type.into()], false), 
+                    Some(Linkage::Internal)
+                );
+                let prev_block = self.builder.get_insert_block();
+                let entry = self.context.append_basic_block(function, "entry");
+                self.builder.position_at_end(entry);
+                let value_ptr = self.builder.build_pointer_cast(
+                    function.get_nth_param(0).unwrap().into_pointer_value(), 
+                    self.get_llvm_type(elem_type).ptr_type(inkwell::AddressSpace::Generic), 
+                    "value"
+                );
+                let value = self.builder.build_load(value_ptr, "value.load");
+                self.rc_release_value(value, elem_type);
+                self.builder.build_return(None);
+                if let Some(block) = prev_block {
+                    self.builder.position_at_end(block);
+                }
+                function
+            }
+        };
+        return self.builder.build_pointer_cast(
+            function.as_global_value().as_pointer_value(), i8_ptr_type, "rc.drop");
+    }
+
+    /* the variable owns the rcs it holds until its block is left */
+    fn rc_declare(&mut self, var_ptr: inkwell::values::PointerValue<'ctx>, dtype: &Datatype) {
+        if !self.contains_rc(dtype) {
+            return;
+        }
+        if let Some(scope) = self.rc_scopes.last_mut() {
+            scope.push((var_ptr, dtype.clone()));
+        }
+    }
+
+    /* variables holding rcs start out null, so that replacing or releasing them before a store does nothing */
+    fn rc_zero(&mut self, var_ptr: inkwell::values::PointerValue<'ctx>, dtype: &Datatype) {
+        if !self.contains_rc(dtype) {
+            return;
+        }
+        let zero: BasicValueEnum = match self.get_llvm_type(dtype) {
+            BasicTypeEnum::ArrayType(a)     => a.const_zero().into(),
+            BasicTypeEnum::FloatType(f)     => f.const_zero().into(),
+            BasicTypeEnum::IntType(i)       => i.const_zero().into(),
+            BasicTypeEnum::PointerType(p)   => p.const_zero().into(),
+            BasicTypeEnum::StructType(s)    => s.const_zero().into(),
+            BasicTypeEnum::VectorType(v)    => v.const_zero().into()
+        };
+        self.builder.build_store(var_ptr, zero);
+    }
+
+    /* releases the variables of the scopes from `depth` on, innermost first */
+    fn rc_release_scopes(&mut self, depth: usize) {
+        if self.builder.get_insert_block().unwrap().get_terminator().is_some() {
+            return;
+        }
+        let variables: Vec<(inkwell::values::PointerValue<'ctx>, Datatype)> = self.rc_scopes[depth..].iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev().cloned())
+            .collect();
+        for (var_ptr, dtype) in variables {
+            let value = self.builder.build_load(var_ptr, "rc.var");
+            self.rc_release_value(value, &dtype);
+        }
+    }
+
+    fn visit_closure_expr(&mut self, 
+        func_token: &Token, 
+        parameters: &Vec<(Token, Datatype)>, 
+        returntype: &Datatype, 
+        body: &Box<Stmt>, 
+        captures: &Vec<(Token, Datatype)>, 
+        datatype: &Datatype)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+
+        let mut capture_types = vec![];
+        for (_, dtype) in captures {
+            capture_types.push(self.get_llvm_type(dtype));
+        }
+        let env_type = self.context.struct_type(capture_types.as_slice(), false);
+
+        // the environment is counted like an rc. It is freed with the last copy of the function value
+        let env = if captures.is_empty() {
+            i8_ptr_type.const_null()
+        } else {
+            let drop = self.closure_drop_function(func_token, captures, env_type);
+            let env_alloc = self.module.get_function("env_alloc").unwrap();
+            let line = self.context.i64_type().const_int(func_token.span.start.line as u64, false);
+            let env = self.builder.build_call(
+                env_alloc, &[env_type.size_of().unwrap().into(), drop.into(), line.into()], "closure.env"
+            ).try_as_basic_value().left().unwrap().into_pointer_value();
+            let env_ptr = self.builder.build_pointer_cast(
+                env, env_type.ptr_type(inkwell::AddressSpace::Generic), "closure.env.typed");
+            for (i, (name, dtype)) in captures.iter().enumerate() {
+                let var_ptr = *self.symbol_table.variable_table.get(&name.value).unwrap();
+                let val = self.builder.build_load(var_ptr, (name.value.clone()+".load").as_str());
+                if self.contains_rc(dtype) {
+                    self.rc_retain_value(val, dtype);
+                }
+                let field_ptr = self.builder.build_struct_gep(env_ptr, i as u32, name.value.as_str()).unwrap();
+                self.builder.build_store(field_ptr, val);
+            }
+            env
+        };
+
+        let mut param_types: Vec<BasicTypeEnum> = vec![i8_ptr_type.into()];
+        for (_, dtype) in parameters {
+            param_types.push(self.get_llvm_type(dtype));
+        }
+        let ret_type = self.get_llvm_type(returntype);
+        let function = self.module.add_function(
+            format!("closure.{}.{}.{}", func_token.span.file, func_token.span.start.line, func_token.span.start.col).as_str(), 
+            ret_type.fn_type(param_types.as_slice(), false), 
+            Some(Linkage::Internal)
+        );
+
+        let prev_block = self.builder.get_insert_block().unwrap();
+        let prev_fn = self.curr_fn_value;
+        let prev_variables = std::mem::take(&mut self.symbol_table.variable_table);
+        let prev_loops = std::mem::take(&mut self.loop_stack);
+        let prev_rc_scopes = std::mem::replace(&mut self.rc_scopes, vec![vec![]]);
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = false;
+
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.curr_fn_value = Some(function);
+
+        // captured variables live in the environment, so changes to them last across calls.
+        if !captures.is_empty() {
+            let env_ptr = self.builder.build_pointer_cast(
+                function.get_nth_param(0).unwrap().into_pointer_value(), 
+                env_type.ptr_type(inkwell::AddressSpace::Generic), 
+                "env"
+            );
+            for (i, (name, _)) in captures.iter().enumerate() {
+                let field_ptr = self.builder.build_struct_gep(env_ptr, i as u32, name.value.as_str()).unwrap();
+                self.symbol_table.variable_table.insert(name.value.clone(), field_ptr);
+            }
+        }
+        for (i, (param_name, param_type)) in parameters.iter().enumerate() {
+            let arg = function.get_nth_param(i as u32 + 1).unwrap();
+            let alloca = self.builder.build_alloca(arg.get_type(), param_name.value.as_str());
+            self.builder.build_store(alloca, arg);
+            self.symbol_table.variable_table.insert(param_name.value.clone(), alloca);
+            self.rc_declare(alloca, param_type);
+        }
+
+        self.is_function_body = true;
+        let body_val = self.visit_stmt(body);
+        self.rc_release_scopes(0);
+        if self.builder.get_insert_block().unwrap().get_terminator().is_none() {
+            // like functions, a trailing expression is returned.
+            let ret_val = match body_val {
+                Some(AnyValueEnum::ArrayValue(v))   => Some(BasicValueEnum::ArrayValue(v)),
+                Some(AnyValueEnum::FloatValue(v))   => Some(BasicValueEnum::FloatValue(v)),
+                Some(AnyValueEnum::IntValue(v))     => Some(BasicValueEnum::IntValue(v)),
+                Some(AnyValueEnum::PointerValue(v)) => Some(BasicValueEnum::PointerValue(v)),
+                Some(AnyValueEnum::StructValue(v))  => Some(BasicValueEnum::StructValue(v)),
+                _ => None
+            };
+            // the type checker has made sure that the end is only reached with a value of the return type
+            match ret_val {
+                Some(val) if val.get_type() == ret_type => { self.builder.build_return(Some(&val)); },
+                _ if *returntype == Datatype::unit() => { self.builder.build_return(Some(&ret_type.into_struct_type().const_zero())); },
+                _ => { self.builder.build_unreachable(); }
+            }
+        }
+
+        self.symbol_table.variable_table = prev_variables;
+        self.curr_fn_value = prev_fn;
+        self.loop_stack = prev_loops;
+        self.rc_scopes = prev_rc_scopes;
+        self.is_parsing_lvalue = is_parsing_lvalue;
+        self.builder.position_at_end(prev_block);
+
+        return self.build_closure_value(function, env);
+    }
+
+    /* `closure.drop.<position>` releases the captured values of an environment that is freed. 
+       Null when none of them is counted */
+    fn closure_drop_function(&mut self, func_token: &Token, captures: &Vec<(Token, Datatype)>, env_type: StructType<'ctx>)
+    -> inkwell::values::PointerValue<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        if !captures.iter().any(|(_, dtype)| self.contains_rc(dtype)) {
+            return i8_ptr_type.const_null();
+        }
+        let function = self.module.add_function(
+            format!("closure.drop.{}.{}.{}", func_token.span.file, func_token.span.start.line, func_token.span.start.col).as_str(), 
+            self.context.void_type().fn_type(&[i8_ptr_type.into()], false), 
+            Some(Linkage::Internal)
+        );
+        let prev_block = self.builder.get_insert_block();
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        let env_ptr = self.builder.build_pointer_cast(
+            function.get_nth_param(0).unwrap().into_pointer_value(), 
+            env_type.ptr_type(inkwell::AddressSpace::Generic), 
+            "env"
+        );
+        for (i, (name, dtype)) in captures.iter().enumerate() {
+            if self.contains_rc(dtype) {
+                let field_ptr = self.builder.build_struct_gep(env_ptr, i as u32, name.value.as_str()).unwrap();
+                let value = self.builder.build_load(field_ptr, "capture.load");
+                self.rc_release_value(value, dtype);
+            }
+        }
+        self.builder.build_return(None);
+        if let Some(block) = prev_block {
+            self.builder.position_at_end(block);
+        }
+        return self.builder.build_pointer_cast(
+            function.as_global_value().as_pointer_value(), i8_ptr_type, "closure.drop");
+    }
+
+    /* upper bound of the size of a value in bytes, assuming no field is aligned to more than 8 bytes */
+    fn size_upper_bound(&self, datatype: &Datatype) -> u64 {
+        match datatype {
+            Datatype::int8  | Datatype::uint8 | Datatype::bool  => 1,
+            Datatype::int16 | Datatype::uint16                  => 2,
+            Datatype::int32 | Datatype::uint32 | Datatype::char | Datatype::float32 => 4,
+            Datatype::int64 | Datatype::uint64 | Datatype::float64 | Datatype::string => 8,
+            Datatype::dyn_trait{..} => 16,
+            Datatype::channel{..}   => 8,
+            Datatype::tuple{elem_types} => self.fields_size_upper_bound(elem_types),
+            Datatype::array{elem_type, size} => self.size_upper_bound(elem_type) * size,
+            Datatype::object{name} => match self.symbol_table.struct_decls.get(name) {
+                Some(Decl::StructDecl{fields, ..}) 
+                    => self.fields_size_upper_bound(&fields.iter().map(|(_, dtype)| dtype.clone()).collect()),
+                _ => 8
+            },
+            Datatype::enumeration{name} => match self.module.get_struct_type(name) {
+                Some(enum_type) => match enum_type.get_field_type_at_index(1) {
+                    Some(BasicTypeEnum::ArrayType(payload)) => 8 + 8 * payload.len() as u64,
+                    _ => 8
+                },
+                _ => 8
+            },
+            _ => 8
+        }
+    }
+
+    fn fields_size_upper_bound(&self, field_types: &Vec<Datatype>) -> u64 {
+        let mut size = 0;
+        for field_type in field_types {
+            size += (self.size_upper_bound(field_type) + 7) / 8 * 8;
+        }
+        return size;
+    }
+
+    /* trait object: { i8* data, i8** vtable } */
+    fn dyn_trait_type(&self) -> StructType<'ctx> {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        return self.context.struct_type(&[
+            i8_ptr_type.into(), 
+            i8_ptr_type.ptr_type(inkwell::AddressSpace::Generic).into()
+        ], false);
+    }
+
+    /* method names of a trait, in declaration order. This is also the order of vtable slots. */
+    fn trait_method_names(&self, trait_name: &String) -> Vec<String> {
+        let mut method_names = vec![];
+        if let Some(funcs) = self.symbol_table.trait_decls.get(trait_name) {
+            for func in funcs {
+                if let Decl::Prototype{name, ..} = func.get_prototype() {
+                    method_names.push(name.value.clone());
+                }
+            }
+        }
+        return method_names;
+    }
+
+    fn build_vtable(&mut self, struct_name: &String, trait_name: &String) {
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let mut methods = vec![];
+        for method_name in self.trait_method_names(trait_name) {
+            match self.module.get_function((struct_name.clone()+"."+method_name.as_str()).as_str()) {
+                Some(function) 
+                    => methods.push(function.as_global_value().as_pointer_value().const_cast(i8_ptr_type)),
+                None => methods.push(i8_ptr_type.const_null())
+            }
+        }
+
+        let vtable = self.get_vtable(struct_name, trait_name);
+        vtable.set_initializer(&i8_ptr_type.const_array(methods.as_slice()));
+        vtable.set_constant(true);
+        vtable.set_linkage(Linkage::Internal);
+    }
+
+    /* vtable global of an impl. A cast can be lowered before the impl, so the global is declared
+       on first use and given its methods once the impl is visited. */
+    fn get_vtable(&self, struct_name: &String, trait_name: &String) -> GlobalValue<'ctx> {
+        let vtable_name = "vtable.".to_string()+struct_name.as_str()+"."+trait_name.as_str();
+        match self.module.get_global(vtable_name.as_str()) {
+            Some(vtable) => vtable,
+            None => {
+                let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+                let size = self.trait_method_names(trait_name).len() as u32;
+                self.module.add_global(i8_ptr_type.array_type(size), None, vtable_name.as_str())
+            }
+        }
+    }
+
+    /* `object as dyn Trait`. Trait object points to the object, it doesn't own a copy of it. */
+    fn build_trait_object(&mut self, variable: &Box<Expr>, struct_name: &String, trait_name: &String)
+    -> BasicValueEnum<'ctx> {
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = true;
+        let obj = self.visit_expr(variable);
+        self.is_parsing_lvalue = is_parsing_lvalue;
+
+        let obj_ptr = if obj.is_pointer_value() {
+            obj.into_pointer_value()
+        } else {
+            // temporaries like struct expressions need a stack slot to point to.
+            let tmp = self.build_entry_alloca(obj.get_type(), "dyn.tmp");
+            self.builder.build_store(tmp, obj);
+            tmp
+        };
+
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let data = self.builder.build_pointer_cast(obj_ptr, i8_ptr_type, "dyn.data");
+        let vtable = self.get_vtable(struct_name, trait_name);
+        let vtable_ptr = self.builder.build_pointer_cast(
+            vtable.as_pointer_value(), 
+            i8_ptr_type.ptr_type(inkwell::AddressSpace::Generic), 
+            "dyn.vtable"
+        );
+
+        let trait_object = self.builder.build_insert_value(
+            self.dyn_trait_type().get_undef(), data, 0, "dyn.insert"
+        ).unwrap().into_struct_value();
+        let trait_object = self.builder.build_insert_value(
+            trait_object, vtable_ptr, 1, "dyn"
+        ).unwrap().into_struct_value();
+        return BasicValueEnum::StructValue(trait_object);
+    }
+
+    /* loads the method from the vtable and calls it with the data pointer as `self` */
+    fn call_dyn_method(
+        &mut self, 
+        trait_object: BasicValueEnum<'ctx>, 
+        trait_name: &String, 
+        method_name: &Token, 
+        arguments: &Vec<Box<Expr>>)
+    -> BasicValueEnum<'ctx> {
+        let trait_object = if trait_object.is_pointer_value() {
+            self.builder.build_load(trait_object.into_pointer_value(), "dyn.load").into_struct_value()
+        } else {
+            trait_object.into_struct_value()
+        };
+        let data = self.builder.build_extract_value(trait_object, 0, "dyn.data").unwrap();
+        let vtable = self.builder.build_extract_value(trait_object, 1, "dyn.vtable").unwrap();
+
+        let method_names = self.trait_method_names(trait_name);
+        let slot_index = method_names.iter().position(|name| *name == method_name.value).unwrap();
+        let slot = unsafe {
+            self.builder.build_in_bounds_gep(
+                vtable.into_pointer_value(), 
+                &[self.context.i64_type().const_int(slot_index as u64, false)], 
+                "dyn.slot"
+            )
+        };
+        let method = self.builder.build_load(slot, "dyn.method").into_pointer_value();
+
+        let mut param_types = vec![data.get_type()];
+        let mut ret_type = self.context.bool_type().into();
+        for func in self.symbol_table.trait_decls.get(trait_name).unwrap().clone() {
+            if let Decl::Prototype{name, parameters, returntype, ..} = func.get_prototype() {
+                if name.value == method_name.value {
+                    for param in parameters {
+                        param_types.push(self.get_llvm_type(&param.1));
+                    }
+                    ret_type = self.get_llvm_type(returntype);
+                    break;
+                }
+            }
+        }
+        let fn_type = ret_type.fn_type(param_types.as_slice(), false);
+        let method = self.builder.build_pointer_cast(
+            method, 
+            fn_type.ptr_type(inkwell::AddressSpace::Generic), 
+            "dyn.fn"
+        );
+
+        let mut args = vec![data];
+        for argument in arguments {
+            args.push(self.visit_argument(argument));
+        }
+
+        let call_val = self.builder.build_call(
+            CallableValue::try_from(method).unwrap(), 
+            &args, 
+            (trait_name.clone()+"."+method_name.value.as_str()).as_str()
+        ).try_as_basic_value();
+
+        if call_val.is_left() {
+            return call_val.left().unwrap();
+        } else {
+            return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
+        }
+    }
+
     fn add_(&mut self, lhs: BasicValueEnum<'ctx>, rhs: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx> {
         if lhs.is_int_value() {
             return BasicValueEnum::IntValue(
@@ -857,13 +1797,13 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         if object_name.is_some() {
             let obj_name = object_name.as_ref().unwrap();
             // pass object as reference in function call.
-            println!("Symbol Table: {:#?}", self.symbol_table);
+            eprintln!("Symbol Table: {:#?}", self.symbol_table);
             args.push(object.unwrap());
             // args.push(BasicValueEnum::PointerValue(*self.symbol_table.variable_table.get(obj_name.as_str()).unwrap()));
         }
 
         for argument in arguments {
-            args.push(self.visit_expr(argument));
+            args.push(self.visit_argument(argument));
         }
         
         let call_val =  self.builder.build_call(
@@ -877,7 +1817,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             // }
         ).try_as_basic_value(); 
         
-        println!("Codegen-Call_function: CallVal: {:?}", call_val);
+        eprintln!("Codegen-Call_function: CallVal: {:?}", call_val);
         if call_val.is_left() {
             return call_val.left().unwrap();
         } else {
@@ -899,25 +1839,32 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         self.is_parsing_lvalue = true;
         let lhs_ptr = self.visit_expr(target);
         self.is_parsing_lvalue = is_lvalue_parsing;
-        println!("Codegen-ObjAssignExpr: LHS: {:#?}", lhs_ptr);
+        eprintln!("Codegen-ObjAssignExpr: LHS: {:#?}", lhs_ptr);
 
         if let Datatype::object{name} = datatype {
-            if let Decl::StructDecl{name, fields:fields_decl} 
+            if let Decl::StructDecl{name, fields:fields_decl, ..} 
                 = self.symbol_table.struct_decls.get(name).unwrap()
             {
                 
                 
-                println!("Codegen-ObjAssignExpr: StructDecl: {:#?}", fields_decl.clone());
-                println!("Codegen-ObjAssignExpr: Expr: {:#?}", expr.clone());
+                eprintln!("Codegen-ObjAssignExpr: StructDecl: {:#?}", fields_decl.clone());
+                eprintln!("Codegen-ObjAssignExpr: Expr: {:#?}", expr.clone());
                 let mut field_name_index_map = HashMap::new();
                 for (i, (field_name, _)) in fields_decl.into_iter().enumerate() {
                     field_name_index_map.insert(field_name.value.clone(), i);
                 }
 
-                if let Expr::StructExpr{struct_name, fields, datatype}
+                // rcs of the replaced value are released after the new one is stored
+                let old_val = if self.contains_rc(datatype) {
+                    Some(self.builder.build_load(lhs_ptr.into_pointer_value(), "rc.old"))
+                } else {
+                    None
+                };
+
+                if let Expr::StructExpr{struct_name, fields, datatype, ..}
                     = &**expr 
                 {
-                    println!("Codegen-ObjAssignExpr: Fields: {:#?}", fields.clone());
+                    eprintln!("Codegen-ObjAssignExpr: Fields: {:#?}", fields.clone());
                     for (i, (field_name, field_expr)) in fields.into_iter().enumerate() {
                         let igep = self.builder.build_struct_gep(
                             lhs_ptr.into_pointer_value(), 
@@ -925,9 +1872,18 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                             field_name.value.as_str()
                         ).unwrap();
                         let field_val = self.visit_expr(field_expr);
+                        self.rc_copy(field_expr, field_val);
                         self.builder.build_store(igep, field_val);
                     }
 
+                } else {
+                    let val = self.visit_expr(expr);
+                    self.rc_copy(expr, val);
+                    self.builder.build_store(lhs_ptr.into_pointer_value(), val);
+                }
+
+                if let Some(old_val) = old_val {
+                    self.rc_release_value(old_val, datatype);
                 }
             }
         }
@@ -946,15 +1902,15 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
     // }
 
 
-    fn visit_struct_decl(&mut self, name: &Token, fields: &Vec<(Token, Token)>)
+    fn visit_struct_decl(&mut self, name: &Token, fields: &Vec<(Token, Datatype)>)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> {
         self.symbol_table.struct_decls.insert(
             name.value.clone(), 
-            Decl::StructDecl{name:name.clone(), fields: fields.clone()}
+            Decl::StructDecl{name: name.clone(), fields: fields.clone(), span: name.span}
         );
         let mut field_types = vec![];
         for (_, dtype) in fields {
-            field_types.push(self.get_type(dtype));
+            field_types.push(self.get_llvm_type(dtype));
         }
 
         if let Some(struct_type) = self.module.get_struct_type(&name.value) {
@@ -968,10 +1924,10 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         // unimplemented!();
     }
 
-    fn visit_prototype_decl(&mut self, name: &Token, parameters: &Vec<(Token, Token)>, returntype: &Token)
+    fn visit_prototype_decl(&mut self, name: &Token, parameters: &Vec<(Token, Datatype)>, returntype: &Datatype)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> {
         // TODO: add support for object references for struct methods.
-        let ret_type = self.get_type(returntype);
+        let ret_type = self.get_llvm_type(returntype);
         let mut param_types = vec![];
         let mut is_method = false;
         
@@ -982,7 +1938,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         }
 
         for param in parameters {
-            param_types.push(self.get_type(&param.1));
+            param_types.push(self.get_llvm_type(&param.1));
         }
 
         let param_types = param_types.as_slice();
@@ -1002,8 +1958,8 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 => impl_name.clone() + "." + name.value.clone().as_str(),
             globals::Scope::Trait{name: trait_name} => trait_name.clone() + "." + name.value.clone().as_str()
         };
-        println!("Codegen-Prototype: name:{}", name);
-        println!("Codegen-Prototype: num_params:{}", fn_type.count_param_types());
+        eprintln!("Codegen-Prototype: name:{}", name);
+        eprintln!("Codegen-Prototype: num_params:{}", fn_type.count_param_types());
         let fn_val = self.module.add_function(name.as_str(), fn_type, None);
 
         for (i, arg) in fn_val.get_param_iter().enumerate() {
@@ -1044,7 +2000,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         }
 
         match &**prototype {
-            Decl::Prototype{name, parameters, returntype}
+            Decl::Prototype{name, parameters, returntype, ..}
                 => {
                     proto_name = name;
                     proto_args = parameters;
@@ -1065,8 +2021,9 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         self.curr_fn_value = Some(function);
 
 
-        let prev_env = self.symbol_table.clone();
-        self.symbol_table.variable_table.reserve(function.count_params() as usize);
+        let prev_variables = std::mem::take(&mut self.symbol_table.variable_table);
+        // parameters are in a scope of their own, around the body
+        let prev_rc_scopes = std::mem::replace(&mut self.rc_scopes, vec![vec![]]);
 
         
         
@@ -1087,26 +2044,32 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 BasicTypeEnum::ArrayType(a) => builder_new.build_alloca(a, arg_name.as_str()),
                 BasicTypeEnum::FloatType(f) => builder_new.build_alloca(f, arg_name.as_str()),
                 BasicTypeEnum::IntType(i)   => builder_new.build_alloca(i, arg_name.as_str()),
-                BasicTypeEnum::PointerType(p)   => arg.into_pointer_value(),
-                    // => builder_new.build_alloca(p, arg_name.as_str()),
+                BasicTypeEnum::PointerType(p)   
+                    => if i == 0 && is_method { arg.into_pointer_value() } else { builder_new.build_alloca(p, arg_name.as_str()) },
                 BasicTypeEnum::StructType(s)    => builder_new.build_alloca(s, arg_name.as_str()),
                 BasicTypeEnum::VectorType(v)    => builder_new.build_alloca(v, arg_name.as_str())
             };
             // let alloca = self.create_entry_block_alloca(arg_name);
 
-            println!("Codegen-FuncDef: Arg_name: {:#?}, \nArg_Type: {:#?},\nAlloca: {:#?},\nArg: {:#?}",
+            eprintln!("Codegen-FuncDef: Arg_name: {:#?}, \nArg_Type: {:#?},\nAlloca: {:#?},\nArg: {:#?}",
                 arg_name, arg_type, alloca, arg);
 
-            if let BasicTypeEnum::PointerType(_) = arg_type {
-                
+            if i == 0 && is_method {
+                // `self` is already a pointer to the object.
             } else {
                 self.builder.build_store(alloca, arg);
+                self.rc_declare(alloca, &proto_args[i-1].1);
             }
             self.symbol_table.variable_table.insert(arg_name.clone(), alloca);
         }
 
-        println!("Codegen-FuncDef: VariableTable: {:#?}", self.symbol_table.variable_table);
-        let body = self.visit_stmt(block)?;
+        eprintln!("Codegen-FuncDef: VariableTable: {:#?}", self.symbol_table.variable_table);
+        self.is_function_body = true;
+        let body = self.visit_stmt(block);
+        self.rc_release_scopes(0);
+        self.rc_scopes = prev_rc_scopes;
+        self.symbol_table.variable_table = prev_variables;
+        let body = body?;
         
         match body {
             // only basic blocks.
@@ -1127,7 +2090,6 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             }
         };
 
-        self.symbol_table = prev_env;
         // self.symbol_table.func_table = 
 
         return Some(body);
@@ -1147,23 +2109,243 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             self.visit_decl(func);
         }
 
+        if let Some(tok) = trait_name {
+            self.build_vtable(&name.value, &tok.value);
+        }
+
         self.current_scope = prev_scope.clone();
         return None;
         unimplemented!();
     }
 
-    fn visit_trait_decl(&mut self, name: &Token, funcs: &Vec<Box<Decl>>)
-    -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
-        let prev_scope = self.current_scope.clone();
-        self.current_scope = globals::Scope::Trait{name: name.value.clone()};
+    fn visit_trait_decl(&mut self, name: &Token, funcs: &Vec<Box<Decl>>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
+        let prev_scope = self.current_scope.clone();
+        self.current_scope = globals::Scope::Trait{name: name.value.clone()};
+        // default methods are copied into every impl lacking them by the type checker, 
+        // so they are only generated as `Struct.method`.
+        self.symbol_table.trait_decls.insert(name.value.clone(), funcs.clone());
+
+        self.current_scope = prev_scope.clone();
+        return None;
+        unimplemented!();
+    }
+
+    /* every enum is `{ i32 tag, [K x i64] payload }`. The payload of variant `V` is read and 
+       written through a pointer to the struct `Enum.V`, K is large enough for the biggest variant. */
+    fn visit_enum_decl(&mut self, name: &Token, variants: &Vec<(Token, VariantFields)>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        self.symbol_table.enum_decls.insert(
+            name.value.clone(), 
+            Decl::EnumDecl{name: name.clone(), variants: variants.clone(), span: name.span}
+        );
+
+        let mut payload_words = 0;
+        for (variant_name, fields) in variants {
+            let mut field_types = vec![];
+            for dtype in fields.types() {
+                field_types.push(self.get_llvm_type(&dtype));
+            }
+            let variant_type = self.context.opaque_struct_type(
+                (name.value.clone()+"."+variant_name.value.as_str()).as_str());
+            variant_type.set_body(&field_types, false);
+
+            let words = self.fields_size_upper_bound(&fields.types()) / 8;
+            if words > payload_words {
+                payload_words = words;
+            }
+        }
+
+        let enum_type = match self.module.get_struct_type(&name.value) {
+            Some(enum_type) => enum_type,
+            None => self.context.opaque_struct_type(&name.value)
+        };
+        enum_type.set_body(&[
+            self.context.i32_type().into(),
+            self.context.i64_type().array_type(payload_words as u32).into()
+        ], false);
+
+        return None;
+    }
+
+    fn get_enum_variant(&self, enum_name: &String, variant_name: &String) -> (u64/*tag*/, VariantFields) {
+        if let Some(Decl::EnumDecl{variants, ..}) = self.symbol_table.enum_decls.get(enum_name) {
+            for (tag, (name, fields)) in variants.iter().enumerate() {
+                if name.value == *variant_name {
+                    return (tag as u64, fields.clone());
+                }
+            }
+        }
+        unimplemented!();
+    }
+
+    /* pointer to the payload of `enum_ptr`, viewed as variant `variant_name` */
+    fn variant_payload_ptr(&mut self, enum_ptr: inkwell::values::PointerValue<'ctx>, enum_name: &String, variant_name: &String)
+    -> inkwell::values::PointerValue<'ctx> {
+        let payload_ptr = self.builder.build_struct_gep(enum_ptr, 1, "enum.payload").unwrap();
+        let variant_type = self.module.get_struct_type(
+            (enum_name.clone()+"."+variant_name.as_str()).as_str()).unwrap();
+        return self.builder.build_pointer_cast(
+            payload_ptr, 
+            variant_type.ptr_type(inkwell::AddressSpace::Generic), 
+            (enum_name.clone()+"."+variant_name.as_str()).as_str()
+        );
+    }
+
+    fn visit_enum_variant_expr(&mut self, 
+        enum_name: &Token, 
+        variant_name: &Token, 
+        values: &Vec<(Option<Token>, Box<Expr>)>, 
+        datatype: &Datatype)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let enum_type = self.get_llvm_type(datatype);
+        let (tag, _) = self.get_enum_variant(&enum_name.value, &variant_name.value);
+
+        let enum_ptr = self.build_entry_alloca(enum_type, "enum.tmp");
+        let tag_ptr = self.builder.build_struct_gep(enum_ptr, 0, "enum.tag").unwrap();
+        self.builder.build_store(tag_ptr, self.context.i32_type().const_int(tag, false));
+
+        // the type checker has put values of struct-like variants in declaration order.
+        if !values.is_empty() {
+            let variant_ptr = self.variant_payload_ptr(enum_ptr, &enum_name.value, &variant_name.value);
+            // enums aren't counted, so what a payload holds is kept for good
+            for (i, (_, value)) in values.iter().enumerate() {
+                let field_val = self.visit_expr(value);
+                self.rc_copy(value, field_val);
+                let field_ptr = self.builder.build_struct_gep(variant_ptr, i as u32, "enum.field").unwrap();
+                self.builder.build_store(field_ptr, field_val);
+            }
+        }
+
+        return self.builder.build_load(enum_ptr, "enum.val");
+    }
+
+    /* emits the checks of `pattern` against the value at `value_ptr`, branching to `fail_bb` on mismatch.
+       Bindings are declared as aliases of the matched parts, the scrutinee is a private copy. */
+    fn lower_pattern(&mut self, 
+        pattern: &Pattern, 
+        value_ptr: inkwell::values::PointerValue<'ctx>, 
+        datatype: &Datatype, 
+        fail_bb: BasicBlock<'ctx>) {
+        let current_fn = self.curr_fn_value.unwrap();
+
+        let cond = match pattern {
+            Pattern::Wildcard{..} => return,
+            Pattern::Binding{name} => {
+                self.symbol_table.variable_table.insert(name.value.clone(), value_ptr);
+                return;
+            },
+            Pattern::Literal{value} => {
+                let val = self.builder.build_load(value_ptr, "match.load");
+                match datatype {
+                    Datatype::string => {
+                        let literal = self.builder.build_global_string_ptr(&value.value, "str.literal").as_pointer_value();
+                        self.string_equal(val, BasicValueEnum::PointerValue(literal))
+                    },
+                    Datatype::float32 | Datatype::float64 => {
+                        let literal = self.get_llvm_type(datatype).into_float_type().const_float_from_string(&value.value);
+                        self.builder.build_float_compare(
+                            inkwell::FloatPredicate::OEQ, val.into_float_value(), literal, "match.eqeq_")
+                    },
+                    _ => {
+                        let int_type = self.get_llvm_type(datatype).into_int_type();
+                        let literal = match value.tok_type {
+                            TokenType::K_TRUE   => int_type.const_int(1, false),
+                            TokenType::K_FALSE  => int_type.const_int(0, false),
+                            TokenType::CHAR_LITERAL 
+                                => int_type.const_int(value.value.chars().next().unwrap_or('\0') as u64, false),
+                            _ => int_type.const_int_from_string(&value.value, self.get_radix_type(&value.tok_type)).unwrap()
+                        };
+                        self.builder.build_int_compare(
+                            inkwell::IntPredicate::EQ, val.into_int_value(), literal, "match.eqeq_")
+                    }
+                }
+            },
+            Pattern::Variant{enum_name, variant_name, ..} => {
+                let (tag, _) = self.get_enum_variant(&enum_name.value, &variant_name.value);
+                let tag_ptr = self.builder.build_struct_gep(value_ptr, 0, "enum.tag").unwrap();
+                let tag_val = self.builder.build_load(tag_ptr, "enum.tag.load").into_int_value();
+                self.builder.build_int_compare(
+                    inkwell::IntPredicate::EQ, 
+                    tag_val, 
+                    self.context.i32_type().const_int(tag, false), 
+                    "match.tag")
+            }
+        };
+
+        let ok_bb = self.context.append_basic_block(current_fn, "match.ok");
+        self.builder.build_conditional_branch(cond, ok_bb, fail_bb);
+        self.builder.position_at_end(ok_bb);
+
+        if let Pattern::Variant{enum_name, variant_name, fields} = pattern {
+            if fields.is_empty() { return; }
+            let (_, variant_fields) = self.get_enum_variant(&enum_name.value, &variant_name.value);
+            let field_types = variant_fields.types();
+            let variant_ptr = self.variant_payload_ptr(value_ptr, &enum_name.value, &variant_name.value);
+            for (i, (field_name, field_pattern)) in fields.iter().enumerate() {
+                let index = variant_fields.index_of(field_name, i).unwrap();
+                let field_ptr = self.builder.build_struct_gep(variant_ptr, index as u32, "enum.field").unwrap();
+                self.lower_pattern(field_pattern, field_ptr, &field_types[index], fail_bb);
+            }
+        }
+    }
+
+    /* arms are tried in order. Each arm jumps to the next one when its pattern doesn't match. */
+    fn visit_match_expr(&mut self, 
+        match_token: &Token, 
+        scrutinee: &Box<Expr>, 
+        arms: &Vec<(Pattern, Box<Stmt>)>, 
+        datatype: &Datatype)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let current_fn = self.curr_fn_value.unwrap();
 
-        for func in funcs {
-            self.visit_decl(func);
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        self.is_parsing_lvalue = false;
+        let scrutinee_val = self.visit_expr(scrutinee);
+        let scrutinee_ptr = self.build_entry_alloca(scrutinee_val.get_type(), "match.val");
+        self.builder.build_store(scrutinee_ptr, scrutinee_val);
+        let scrutinee_dtype = scrutinee.get_datatype();
+
+        let cont_bb = self.context.append_basic_block(current_fn, "match.cont");
+        let mut incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)> = vec![];
+        for (pattern, body) in arms {
+            let next_bb = self.context.append_basic_block(current_fn, "match.next");
+
+            self.symbol_table.variable_table.push_scope();
+            self.lower_pattern(pattern, scrutinee_ptr, &scrutinee_dtype, next_bb);
+            let arm_val = match &**body {
+                Stmt::Expression{expr, ..} => Some(self.visit_expr(expr)),
+                _ => {
+                    self.visit_stmt(body);
+                    None
+                }
+            };
+            self.symbol_table.variable_table.pop_scope();
+
+            // arm bodies may end in `return`, `break` or `continue`.
+            let arm_bb = self.builder.get_insert_block().unwrap();
+            if arm_bb.get_terminator().is_none() {
+                if let Some(val) = arm_val {
+                    incoming.push((val, arm_bb));
+                }
+                self.builder.build_unconditional_branch(cont_bb);
+            }
+
+            self.builder.position_at_end(next_bb);
         }
+        // the type checker has made sure that some arm matches.
+        self.builder.build_unreachable();
 
-        self.current_scope = prev_scope.clone();
-        return None;
-        unimplemented!();
+        self.builder.position_at_end(cont_bb);
+        self.is_parsing_lvalue = is_parsing_lvalue;
+        if *datatype != Datatype::yet_to_infer && !incoming.is_empty() {
+            let phi = self.builder.build_phi(self.get_llvm_type(datatype), "match.res");
+            for (val, bb) in incoming.iter() {
+                phi.add_incoming(&[(val as &dyn BasicValue<'ctx>, *bb)]);
+            }
+            return phi.as_basic_value();
+        }
+        return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
     }
 
     fn visit_if_stmt(&mut self, 
@@ -1173,6 +2355,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         else_block: &Option<Box<Stmt>>
     ) -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
         let current_fn = self.curr_fn_value.unwrap();
+        let is_returned = std::mem::replace(&mut self.is_returned_if, false);
 
         let cond = self.visit_expr(condition).into_int_value();
 
@@ -1184,6 +2367,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
         // build then block
         self.builder.position_at_end(then_bb);
+        self.is_function_body = is_returned;
         let then_ret = self.visit_stmt(then_block)?;
         self.builder.build_unconditional_branch(cont_bb);
 
@@ -1193,6 +2377,10 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         self.builder.position_at_end(else_bb);
         let mut else_ret: AnyValueEnum;
         if else_block.is_some() {
+            match &**else_block.as_ref().unwrap() {
+                Stmt::If{..} => self.is_returned_if = is_returned,
+                _ => self.is_function_body = is_returned
+            }
             else_ret = self.visit_stmt(else_block.as_ref().unwrap())?;
         } else {
             else_ret = then_ret.clone();
@@ -1202,8 +2390,8 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
         // emti merge block
         self.builder.position_at_end(cont_bb);
-        println!("Codegen-IfStmt: Then_val: {:#?}", then_ret);
-        println!("Codegen-IfStmt: Else_val: {:#?}", else_ret);
+        eprintln!("Codegen-IfStmt: Then_val: {:#?}", then_ret);
+        eprintln!("Codegen-IfStmt: Else_val: {:#?}", else_ret);
         match then_ret {
             AnyValueEnum::ArrayValue(a) => {
                 let phi = self.builder.build_phi(a.get_type(), "ifphi");
@@ -1211,7 +2399,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&a, then_bb),
                     (&else_ret.into_array_value(), else_bb)
                 ]);
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::ArrayValue(phi.as_basic_value().into_array_value()));
             },
             AnyValueEnum::FloatValue(f) => {
@@ -1220,7 +2408,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&f, then_bb),
                     (&else_ret.into_float_value(), else_bb)
                 ]);
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::FloatValue(phi.as_basic_value().into_float_value()));
             },
             AnyValueEnum::IntValue(i)     => {
@@ -1230,7 +2418,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&else_ret.into_int_value(), else_bb)
                 ]);
                 
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::IntValue(phi.as_basic_value().into_int_value()));
             },
             AnyValueEnum::PointerValue(p)   => {
@@ -1239,7 +2427,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&p, then_bb),
                     (&else_ret.into_pointer_value(), else_bb)
                 ]);
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::PointerValue(phi.as_basic_value().into_pointer_value()));
 
             },
@@ -1249,7 +2437,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&s, then_bb),
                     (&else_ret.into_struct_value(), else_bb)
                 ]);
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::StructValue(phi.as_basic_value().into_struct_value()));
             },
             AnyValueEnum::VectorValue(v) => {
@@ -1258,7 +2446,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     (&v, then_bb),
                     (&else_ret.into_vector_value(), else_bb)
                 ]);
-                println!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
+                eprintln!("Codegen-IfStmt: {:#?}", phi.print_to_string().to_str());
                 return Some(AnyValueEnum::VectorValue(phi.as_basic_value().into_vector_value()));
             },
             AnyValueEnum::PhiValue(p) => {
@@ -1277,10 +2465,41 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
     fn visit_block_stmt(&mut self, statements: &Vec<Box<Stmt>>)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
+        let is_function_body = std::mem::replace(&mut self.is_function_body, false);
+        self.rc_scopes.push(vec![]);
+        self.symbol_table.variable_table.push_scope();
+
         let mut return_val = None;
-        for stmt in statements {
-            return_val = self.visit_stmt(stmt);
+        for (i, stmt) in statements.iter().enumerate() {
+            let expr = match &**stmt {
+                Stmt::Expression{expr, ..} if self.contains_rc(&expr.get_datatype()) => expr,
+                _ => {
+                    if let Stmt::If{..} = &**stmt {
+                        self.is_returned_if = is_function_body && i + 1 == statements.len();
+                    }
+                    return_val = self.visit_stmt(stmt);
+                    continue;
+                }
+            };
+            let value = self.visit_expr(expr);
+            return_val = Some(value.as_any_value_enum());
+            if i + 1 == statements.len() {
+                // the trailing value of a function is returned, so it is counted like in `return`
+                if is_function_body {
+                    self.rc_copy(expr, value);
+                } else if Codegen::is_fresh_value(expr) {
+                    self.rc_release_value(value, &expr.get_datatype());
+                }
+            } else if Codegen::is_fresh_value(expr) {
+                // nothing keeps it
+                self.rc_release_value(value, &expr.get_datatype());
+            }
         }
+
+        let depth = self.rc_scopes.len() - 1;
+        self.rc_release_scopes(depth);
+        self.rc_scopes.pop();
+        self.symbol_table.variable_table.pop_scope();
         return return_val;
         // unimplemented!(); 
     }
@@ -1288,6 +2507,9 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
     fn visit_return_stmt(&mut self, expr: &Box<Expr>)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
         let expr_val = self.visit_expr(expr);
+        // the caller owns the returned value. Everything else held by the function is released
+        self.rc_copy(expr, expr_val);
+        self.rc_release_scopes(0);
         return Some(
             AnyValueEnum::InstructionValue(
                 match expr_val {
@@ -1315,7 +2537,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
     ) -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
         let current_fn = self.curr_fn_value.unwrap();
 
-        let prev_env = self.symbol_table.clone();
+        self.symbol_table.variable_table.push_scope();
         
         if initialization.is_some() {
             self.visit_expr(initialization.as_ref().unwrap());
@@ -1323,6 +2545,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
         let cond_bb = self.context.append_basic_block(current_fn, "condbr");
         let loop_bb = self.context.append_basic_block(current_fn, "loopbr");
+        let update_bb = self.context.append_basic_block(current_fn, "updatebr");
         let cont_bb = self.context.append_basic_block(current_fn, "contbr");
 
         // jumping to condbr 
@@ -1339,24 +2562,411 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         }
         let cond_bb = self.builder.get_insert_block().unwrap();
         
-        // emit body and updation in loopbr
+        // emit body in loopbr. `continue` has to run the updation too, so it goes to updatebr.
         self.builder.position_at_end(loop_bb);
+        self.loop_stack.push((cont_bb, update_bb, self.rc_scopes.len()));
         self.visit_stmt(block);
+        self.loop_stack.pop();
+        self.builder.build_unconditional_branch(update_bb);
+        let loop_bb = self.builder.get_insert_block().unwrap();
+
+        // emit updation in updatebr
+        self.builder.position_at_end(update_bb);
         if updation.is_some() {
             self.visit_expr(updation.as_ref().unwrap());
         }
 
-        // go unconditionally to condbr from loopbr
+        // go unconditionally to condbr from updatebr
         self.builder.build_unconditional_branch(cond_bb);
-        let loop_bb = self.builder.get_insert_block().unwrap();
 
         // switching to cont_bb
         self.builder.position_at_end(cont_bb);
+        self.symbol_table.variable_table.pop_scope();
                 
         return None;
         // unimplemented!(); 
     }
 
+    fn visit_while_stmt(&mut self,
+        while_token: &Token,
+        condition: &Box<Expr>,
+        block: &Box<Stmt>
+    ) -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let current_fn = self.curr_fn_value.unwrap();
+
+        let cond_bb = self.context.append_basic_block(current_fn, "whilecond");
+        let body_bb = self.context.append_basic_block(current_fn, "whilebody");
+        let cont_bb = self.context.append_basic_block(current_fn, "whilecont");
+
+        // jumping to whilecond
+        self.builder.build_unconditional_branch(cond_bb);
+        self.builder.position_at_end(cond_bb);
+
+        // condition is re-evaluated on every iteration
+        let condres = self.visit_expr(condition).into_int_value();
+        self.builder.build_conditional_branch(condres, body_bb, cont_bb);
+
+        // emit body in whilebody. Variables declared inside the body shouldn't outlive it.
+        self.builder.position_at_end(body_bb);
+        self.symbol_table.variable_table.push_scope();
+        self.loop_stack.push((cont_bb, cond_bb, self.rc_scopes.len()));
+        self.visit_stmt(block);
+        self.loop_stack.pop();
+        self.symbol_table.variable_table.pop_scope();
+
+        // go unconditionally to whilecond from whilebody
+        self.builder.build_unconditional_branch(cond_bb);
+
+        // switching to whilecont
+        self.builder.position_at_end(cont_bb);
+
+        return None;
+    }
+
+    fn visit_loop_stmt(&mut self,
+        loop_token: &Token,
+        count: &Option<Box<Expr>>,
+        block: &Box<Stmt>
+    ) -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let current_fn = self.curr_fn_value.unwrap();
+        self.symbol_table.variable_table.push_scope();
+
+        if count.is_none() {
+            // infinite loop. Only way out is `break` or `return`.
+            let body_bb = self.context.append_basic_block(current_fn, "loopbody");
+            let cont_bb = self.context.append_basic_block(current_fn, "loopcont");
+
+            self.builder.build_unconditional_branch(body_bb);
+            self.builder.position_at_end(body_bb);
+
+            self.loop_stack.push((cont_bb, body_bb, self.rc_scopes.len()));
+            self.visit_stmt(block);
+            self.loop_stack.pop();
+            self.builder.build_unconditional_branch(body_bb);
+
+            self.builder.position_at_end(cont_bb);
+            self.symbol_table.variable_table.pop_scope();
+            return None;
+        }
+
+        // counted loop. Count is evaluated only once, before the first iteration.
+        let count_expr = count.as_ref().unwrap();
+        let count_dtype = count_expr.get_datatype();
+        let count_val = self.visit_expr(count_expr);
+        let counter_type = count_val.into_int_value().get_type();
+        let counter_ptr = self.build_entry_alloca(counter_type.into(), "loop.counter");
+        self.builder.build_store(counter_ptr, counter_type.const_zero());
+
+        let cond_bb = self.context.append_basic_block(current_fn, "loopcond");
+        let body_bb = self.context.append_basic_block(current_fn, "loopbody");
+        let inc_bb = self.context.append_basic_block(current_fn, "loopinc");
+        let cont_bb = self.context.append_basic_block(current_fn, "loopcont");
+
+        // jumping to loopcond
+        self.builder.build_unconditional_branch(cond_bb);
+        self.builder.position_at_end(cond_bb);
+        let counter_val = self.builder.build_load(counter_ptr, "loop.counter.load");
+        let condres = self.less_than(counter_val, count_val, &count_dtype).into_int_value();
+        self.builder.build_conditional_branch(condres, body_bb, cont_bb);
+
+        // emit body in loopbody. `continue` goes to loopinc so that the counter moves forward.
+        self.builder.position_at_end(body_bb);
+        self.loop_stack.push((cont_bb, inc_bb, self.rc_scopes.len()));
+        self.visit_stmt(block);
+        self.loop_stack.pop();
+        self.builder.build_unconditional_branch(inc_bb);
+
+        // incrementing counter in loopinc
+        self.builder.position_at_end(inc_bb);
+        let counter_val = self.builder.build_load(counter_ptr, "loop.counter.load");
+        let counter_next = self.add_(counter_val, BasicValueEnum::IntValue(counter_type.const_int(1, false)));
+        self.builder.build_store(counter_ptr, counter_next);
+        self.builder.build_unconditional_branch(cond_bb);
+
+        // switching to loopcont
+        self.builder.position_at_end(cont_bb);
+        self.symbol_table.variable_table.pop_scope();
+
+        return None;
+    }
+
+    /// Jumps to `target` and moves the builder to a fresh block, so that statements
+    /// following `break`/`continue` in the same block don't end up after a terminator.
+    fn build_loop_jump(&mut self, target: BasicBlock<'ctx>, dead_block_name: &str) {
+        let current_fn = self.curr_fn_value.unwrap();
+        self.builder.build_unconditional_branch(target);
+        let dead_bb = self.context.append_basic_block(current_fn, dead_block_name);
+        self.builder.position_at_end(dead_bb);
+    }
+
+    fn visit_break_stmt(&mut self, break_token: &Token)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        match self.loop_stack.last() {
+            Some((break_bb, _, depth)) => {
+                let (break_bb, depth) = (*break_bb, *depth);
+                self.rc_release_scopes(depth);
+                self.build_loop_jump(break_bb, "afterbreak");
+            },
+            None => self.diagnostics.error(E_CODEGEN, break_token.span,
+                "`break` outside of a loop".to_string())
+        }
+        return None;
+    }
+
+    fn visit_continue_stmt(&mut self, continue_token: &Token)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        match self.loop_stack.last() {
+            Some((_, continue_bb, depth)) => {
+                let (continue_bb, depth) = (*continue_bb, *depth);
+                self.rc_release_scopes(depth);
+                self.build_loop_jump(continue_bb, "aftercontinue");
+            },
+            None => self.diagnostics.error(E_CODEGEN, continue_token.span,
+                "`continue` outside of a loop".to_string())
+        }
+        return None;
+    }
+
+    /* the callee and arguments are evaluated at the spawn site and copied into a heap allocated box. 
+       The task function `spawn.L.C` unpacks the box, makes the call and frees the box. 
+       Methods are called on a copy of their object. */
+    fn visit_spawn_stmt(&mut self, spawn_token: &Token, call: &Box<Expr>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let (callee, arguments) = match &**call {
+            Expr::Call{callee, arguments, ..} => (callee, arguments),
+            _ => return None
+        };
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+
+        // a module function or method is called directly, anything else through its function value.
+        let mut values: Vec<BasicValueEnum<'ctx>> = vec![];
+        let mut has_object = false;
+        let function = match &**callee {
+            Expr::AttributeRef{datatype: Datatype::function{obj_name: None, ..}, ..}
+                => None,
+            Expr::AttributeRef{name, object, object_dtype: Datatype::channel{..}, ..}
+                => {
+                    values.push(self.visit_expr(object));
+                    values.push(self.context.i64_type().const_int(name.span.start.line as u64, false).into());
+                    self.module.get_function("chan_close")
+                },
+            Expr::AttributeRef{name, object, object_dtype, ..} if Datatype::is_object(object_dtype.auto_deref())
+                => {
+                    let obj_name = object_dtype.auto_deref().type_name();
+                    let obj = self.object_ptr(object, object_dtype, name);
+                    let obj = if obj.is_pointer_value() {
+                        self.builder.build_load(obj.into_pointer_value(), "spawn.object")
+                    } else {
+                        obj
+                    };
+                    // the task keeps its own copy of the object until the method returns
+                    self.rc_retain_value(obj, object_dtype.auto_deref());
+                    values.push(obj);
+                    has_object = true;
+                    self.module.get_function((obj_name.clone()+"."+name.value.as_str()).as_str())
+                },
+            Expr::Variable{name, ..} if !self.symbol_table.variable_table.contains_key(&name.value)
+                => self.module.get_function(name.value.as_str()),
+            _ => None
+        };
+        if function.is_none() {
+            let closure = self.visit_expr(callee);
+            let closure = if closure.is_pointer_value() {
+                self.builder.build_load(closure.into_pointer_value(), "spawn.closure")
+            } else {
+                closure
+            };
+            self.rc_copy(callee, closure);
+            values.push(closure);
+        }
+        for argument in arguments {
+            values.push(self.visit_argument(argument));
+        }
+
+        let field_types: Vec<BasicTypeEnum> = values.iter().map(|val| val.get_type()).collect();
+        let box_type = self.context.struct_type(field_types.as_slice(), false);
+        let malloc = self.module.get_function("malloc").unwrap();
+        let raw_box = self.builder.build_call(
+            malloc, &[box_type.size_of().unwrap().into()], "spawn.box"
+        ).try_as_basic_value().left().unwrap().into_pointer_value();
+        let box_ptr = self.builder.build_pointer_cast(
+            raw_box, box_type.ptr_type(inkwell::AddressSpace::Generic), "spawn.box.typed");
+        for (i, val) in values.iter().enumerate() {
+            let field_ptr = self.builder.build_struct_gep(box_ptr, i as u32, "spawn.field").unwrap();
+            self.builder.build_store(field_ptr, *val);
+        }
+
+        let task = self.module.add_function(
+            format!("spawn.{}.{}.{}", spawn_token.span.file, spawn_token.span.start.line, spawn_token.span.start.col).as_str(), 
+            self.context.void_type().fn_type(&[i8_ptr_type.into()], false), 
+            Some(Linkage::Internal)
+        );
+        let prev_block = self.builder.get_insert_block().unwrap();
+        let entry = self.context.append_basic_block(task, "entry");
+        self.builder.position_at_end(entry);
+
+        let task_box = task.get_nth_param(0).unwrap().into_pointer_value();
+        let task_box_ptr = self.builder.build_pointer_cast(
+            task_box, box_type.ptr_type(inkwell::AddressSpace::Generic), "box");
+        let mut fields = vec![];
+        for i in 0..values.len() {
+            let field_ptr = self.builder.build_struct_gep(task_box_ptr, i as u32, "field").unwrap();
+            if i == 0 && has_object {
+                // methods take their object by reference
+                fields.push(BasicValueEnum::PointerValue(field_ptr));
+            } else {
+                fields.push(self.builder.build_load(field_ptr, "field.load"));
+            }
+        }
+        match function {
+            Some(function) => { self.builder.build_call(function, &fields, "spawn.call"); },
+            None => {
+                let closure = fields.remove(0);
+                self.call_closure_with_values(closure, &callee.get_datatype(), fields);
+                self.rc_release_value(closure, &callee.get_datatype());
+            }
+        }
+        if has_object {
+            if let Expr::AttributeRef{object_dtype, ..} = &**callee {
+                let field_ptr = self.builder.build_struct_gep(task_box_ptr, 0, "object").unwrap();
+                let obj = self.builder.build_load(field_ptr, "object.load");
+                self.rc_release_value(obj, object_dtype.auto_deref());
+            }
+        }
+        let free = self.module.get_function("free").unwrap();
+        self.builder.build_call(free, &[task_box.into()], "");
+        self.builder.build_return(None);
+        self.builder.position_at_end(prev_block);
+
+        let spawn_task = self.module.get_function("spawn_task").unwrap();
+        let task_ptr = self.builder.build_pointer_cast(
+            task.as_global_value().as_pointer_value(), i8_ptr_type, "spawn.fn");
+        self.builder.build_call(spawn_task, &[task_ptr.into(), raw_box.into()], "");
+        return None;
+    }
+
+    /* every channel and value to send is evaluated before the runtime picks a ready case. 
+       `chan_select` returns the index of that case among the send and receive cases, or -1 for `default`. */
+    fn visit_select_stmt(&mut self, select_token: &Token, cases: &Vec<(SelectCase, Box<Stmt>)>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let current_fn = self.curr_fn_value.unwrap();
+        let i8_ptr_type = self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic);
+        let i64_type = self.context.i64_type();
+
+        // { i8* channel, i8* value, i64 is_send }, the runtime's `select_case_t`
+        let case_type = self.context.struct_type(&[i8_ptr_type.into(), i8_ptr_type.into(), i64_type.into()], false);
+        let op_count = cases.iter().filter(|(case, _)| match case {
+            SelectCase::Default{..} => false,
+            _ => true
+        }).count();
+        let ops = self.build_entry_alloca(case_type.array_type(op_count as u32).into(), "select.cases");
+
+        // index among the operations and value slot of each case. None for `default`.
+        let mut slots: Vec<Option<(u64, inkwell::values::PointerValue<'ctx>)>> = vec![];
+        for (case, _) in cases {
+            let (chan, slot, is_send) = match case {
+                SelectCase::Receive{channel, elem_type, ..} => {
+                    let chan = self.visit_expr(channel);
+                    let elem_type = self.get_llvm_type(elem_type);
+                    (chan, self.build_entry_alloca(elem_type, "select.recv.value"), 0)
+                },
+                SelectCase::Send{channel, value, ..} => {
+                    let chan = self.visit_expr(channel);
+                    let val = self.visit_expr(value);
+                    // counted before the runtime copies it, the cases not chosen release it again
+                    self.rc_copy(value, val);
+                    let slot = self.build_entry_alloca(val.get_type(), "select.send.value");
+                    self.builder.build_store(slot, val);
+                    (chan, slot, 1)
+                },
+                SelectCase::Default{..} => {
+                    slots.push(None);
+                    continue;
+                }
+            };
+
+            let op_index = slots.iter().filter(|slot| slot.is_some()).count() as u64;
+            let op_ptr = unsafe {
+                self.builder.build_in_bounds_gep(ops, &[i64_type.const_zero(), i64_type.const_int(op_index, false)], "select.case")
+            };
+            let field_ptr = self.builder.build_struct_gep(op_ptr, 0, "select.case.chan").unwrap();
+            self.builder.build_store(field_ptr, chan);
+            let field_ptr = self.builder.build_struct_gep(op_ptr, 1, "select.case.value").unwrap();
+            self.builder.build_store(field_ptr, self.builder.build_pointer_cast(slot, i8_ptr_type, "select.value.ptr"));
+            let field_ptr = self.builder.build_struct_gep(op_ptr, 2, "select.case.is_send").unwrap();
+            self.builder.build_store(field_ptr, i64_type.const_int(is_send, false));
+            slots.push(Some((op_index, slot)));
+        }
+
+        let has_default = op_count != cases.len();
+        let chan_select = self.module.get_function("chan_select").unwrap();
+        let chosen = self.builder.build_call(chan_select, &[
+            self.builder.build_pointer_cast(ops, i8_ptr_type, "select.cases.ptr").into(),
+            i64_type.const_int(op_count as u64, false).into(),
+            self.context.bool_type().const_int(has_default as u64, false).into(),
+            i64_type.const_int(select_token.span.start.line as u64, false).into()
+        ], "select.chosen").try_as_basic_value().left().unwrap().into_int_value();
+
+        let end_bb = self.context.append_basic_block(current_fn, "select.end");
+        let mut case_bbs = vec![];
+        let mut switch_cases = vec![];
+        let mut default_bb = None;
+        for slot in slots.iter() {
+            let case_bb = self.context.append_basic_block(current_fn, "select.case");
+            match slot {
+                Some((op_index, _)) => switch_cases.push((i64_type.const_int(*op_index, false), case_bb)),
+                None => default_bb = Some(case_bb)
+            }
+            case_bbs.push(case_bb);
+        }
+        match default_bb {
+            Some(default_bb) => { self.builder.build_switch(chosen, default_bb, switch_cases.as_slice()); },
+            None => {
+                // without `default` the runtime waits for some case to be ready.
+                let none_bb = self.context.append_basic_block(current_fn, "select.none");
+                self.builder.build_switch(chosen, none_bb, switch_cases.as_slice());
+                self.builder.position_at_end(none_bb);
+                self.builder.build_unreachable();
+            }
+        }
+
+        for (i, (case, body)) in cases.iter().enumerate() {
+            self.builder.position_at_end(case_bbs[i]);
+            for (j, (other, _)) in cases.iter().enumerate() {
+                if let (SelectCase::Send{value, ..}, Some((_, slot))) = (other, slots[j]) {
+                    if j != i && self.contains_rc(&value.get_datatype()) {
+                        let val = self.builder.build_load(slot, "select.unsent");
+                        self.rc_release_value(val, &value.get_datatype());
+                    }
+                }
+            }
+            // a received value is owned by the case, like the value of `<-ch`
+            match (case, slots[i]) {
+                (SelectCase::Receive{target: Some(target), elem_type, ..}, Some((_, slot))) => {
+                    let val = self.builder.build_load(slot, "select.recv");
+                    let is_parsing_lvalue = self.is_parsing_lvalue;
+                    self.is_parsing_lvalue = true;
+                    let target_ptr = self.visit_expr(target).into_pointer_value();
+                    self.is_parsing_lvalue = is_parsing_lvalue;
+                    self.rc_replace(target_ptr, val, elem_type);
+                },
+                (SelectCase::Receive{target: None, elem_type, ..}, Some((_, slot))) if self.contains_rc(elem_type) => {
+                    let val = self.builder.build_load(slot, "select.recv");
+                    self.rc_release_value(val, elem_type);
+                },
+                _ => ()
+            }
+            self.visit_stmt(body);
+            // cases may end in `return`, `break` or `continue`.
+            if self.builder.get_insert_block().unwrap().get_terminator().is_none() {
+                self.builder.build_unconditional_branch(end_bb);
+            }
+        }
+
+        self.builder.position_at_end(end_bb);
+        return None;
+    }
+
     fn visit_expression_stmt(&mut self, expr: &Box<Expr>)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> { 
         // unimplemented!(); 
@@ -1369,29 +2979,52 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         return self.visit_decl(decl);
     }
 
-    fn visit_var_stmt(&mut self, name: &Token, datatype: &Option<Token>, initialization_value: &Option<Box<Expr>>)
+    fn visit_destructure_stmt(&mut self, names: &Vec<Token>, initialization_value: &Box<Expr>)
+    -> Option<inkwell::values::AnyValueEnum<'ctx>> {
+        let tuple_val = self.visit_expr(initialization_value);
+        self.rc_copy(initialization_value, tuple_val);
+        let tuple = tuple_val.into_struct_value();
+        let elem_types = match initialization_value.get_datatype() {
+            Datatype::tuple{elem_types} => elem_types,
+            _ => vec![]
+        };
+        for (i, name) in names.iter().enumerate() {
+            let val = self.builder.build_extract_value(tuple, i as u32, name.value.as_str()).unwrap();
+            let val_ptr = self.build_entry_alloca(val.get_type(), &name.value);
+            self.builder.build_store(val_ptr, val);
+            self.symbol_table.variable_table.insert(name.value.clone(), val_ptr);
+            if let Some(elem_type) = elem_types.get(i) {
+                self.rc_declare(val_ptr, elem_type);
+            }
+        }
+        return None;
+    }
+
+    fn visit_var_stmt(&mut self, name: &Token, datatype: &Option<Datatype>, initialization_value: &Option<Box<Expr>>)
     -> Option<inkwell::values::AnyValueEnum<'ctx>> {
 
         if datatype.is_some() && initialization_value.is_some() {
             
-            let dtype = self.get_type(datatype.as_ref().unwrap());
-            let val_ptr = self.builder.build_alloca(dtype, &name.value);
+            let dtype = self.get_llvm_type(datatype.as_ref().unwrap());
+            let val_ptr = self.build_entry_alloca(dtype, &name.value);
     
             self.symbol_table.variable_table.insert(
                 name.value.clone(), 
                 val_ptr
             );
+            self.rc_zero(val_ptr, datatype.as_ref().unwrap());
+            self.rc_declare(val_ptr, datatype.as_ref().unwrap());
 
-            if datatype.as_ref().unwrap().tok_type == TokenType::IDENTIFIER {
+            if let Some(Datatype::object{name: struct_name}) = datatype {
                 // if struct, creating a custom assignment expression.
-                println!("Codegen-VarStmt: name: {:#?}, datatype: {:#?}, init_value: {:#?}", 
+                eprintln!("Codegen-VarStmt: name: {:#?}, datatype: {:#?}, init_value: {:#?}", 
                     name, datatype, initialization_value);
-                let dtype = self.get_type(datatype.as_ref().unwrap());
-                let dtype_datatype = Datatype::get_tok_datatype(datatype.as_ref().unwrap());
+                let dtype_datatype = datatype.as_ref().unwrap().clone();
                 let var_expr = Expr::Variable{
                     name: name.clone(), 
                     datatype: dtype_datatype.clone(),
-                    struct_name: Some(datatype.as_ref().unwrap().value.clone())
+                    struct_name: Some(struct_name.clone()),
+                    span: name.span
                 };
 
                 let assignment_expr = Expr::Assignment{
@@ -1399,21 +3032,22 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                     operator: Token { 
                         tok_type: TokenType::EQUAL, 
                         value: "=".to_string(), 
-                        line: usize::MAX, 
-                        col: usize::MAX 
+                        span: Span::unknown()
                     },
                     expr: initialization_value.as_ref().unwrap().clone(),
-                    datatype: dtype_datatype.clone()
+                    datatype: dtype_datatype.clone(),
+                    span: name.span
                 };
                 
                 let val = self.visit_expr(&assignment_expr).as_any_value_enum();
-                println!("Codegen-VarStmt: VarExpr: {:#?}", var_expr);
-                println!("Codegen-VarStmt: AssignmentExpr: {:#?}", assignment_expr);
-                println!("Codegen-VarStmt: Result: {:#?}", val);
+                eprintln!("Codegen-VarStmt: VarExpr: {:#?}", var_expr);
+                eprintln!("Codegen-VarStmt: AssignmentExpr: {:#?}", assignment_expr);
+                eprintln!("Codegen-VarStmt: Result: {:#?}", val);
                 return Some(val);
             }
 
             let val = self.visit_expr(initialization_value.as_ref().unwrap());
+            self.rc_copy(initialization_value.as_ref().unwrap(), val);
             match val {
                 BasicValueEnum::ArrayValue(v)      => self.builder.build_store(val_ptr, v),
                 BasicValueEnum::FloatValue(v)      => self.builder.build_store(val_ptr, v),
@@ -1428,7 +3062,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         if initialization_value.is_some() {
             let val = self.visit_expr(initialization_value.as_ref().unwrap());
             let dtype = val.get_type();
-            let val_ptr = self.builder.build_alloca(dtype, &name.value);
+            let val_ptr = self.build_entry_alloca(dtype, &name.value);
     
             self.symbol_table.variable_table.insert(
                 name.value.clone(), 
@@ -1436,33 +3070,9 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             );
 
 
-            if let BasicValueEnum::StructValue(s) = val {
-                // if struct, create a custom assignment expr. 
-                // This will evaluate `initialization_value` again.
-
-                let var_expr = Box::new(Expr::Variable{
-                    name: name.clone(),
-                    datatype: Datatype::object{name:s.get_type().get_name()?.to_str().unwrap().to_string()},
-                    struct_name: Some(s.get_type().get_name()?.to_str().unwrap().to_string()),
-                });
-
-                let assignment_expr = Expr::Assignment{
-                    target: var_expr,
-                    operator: Token { 
-                        tok_type: TokenType::EQUAL,  
-                        value: "=".to_string(), 
-                        line: usize::MAX, 
-                        col: usize::MAX
-                    },
-                    expr: initialization_value.as_ref().unwrap().clone(),
-                    datatype: Datatype::object{name:s.get_type().get_name()?.to_str().unwrap().to_string()},
-                };
-
-                let val = self.visit_expr(&assignment_expr);
-                return Some(val.as_any_value_enum());
-            }
-
-            // if not, store the val already calculated
+            // structs are built as values, so everything is stored directly
+            self.rc_copy(initialization_value.as_ref().unwrap(), val);
+            self.rc_declare(val_ptr, &initialization_value.as_ref().unwrap().get_datatype());
             match val {
                 BasicValueEnum::ArrayValue(v)      => self.builder.build_store(val_ptr, v),
                 BasicValueEnum::FloatValue(v)      => self.builder.build_store(val_ptr, v),
@@ -1476,12 +3086,14 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             
         // self.builder.build_store(val_ptr, val.into());
         if datatype.is_some() {
-            let dtype = self.get_type(datatype.as_ref().unwrap());
-            let val_ptr = self.builder.build_alloca(dtype, &name.value);
+            let dtype = self.get_llvm_type(datatype.as_ref().unwrap());
+            let val_ptr = self.build_entry_alloca(dtype, &name.value);
             self.symbol_table.variable_table.insert(
                 name.value.clone(), 
                 val_ptr
             );
+            self.rc_zero(val_ptr, datatype.as_ref().unwrap());
+            self.rc_declare(val_ptr, datatype.as_ref().unwrap());
             
             return Some(AnyValueEnum::PointerValue(val_ptr));
         }
@@ -1497,7 +3109,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 // return BasicValueEnum::PointerValue(var_ptr.into_pointer_value());
                 return BasicValueEnum::PointerValue(*var_ptr);
             }
-            println!("Codegen-VariableExpr: {:#?}", var_ptr);
+            eprintln!("Codegen-VariableExpr: {:#?}", var_ptr);
             return self.builder.build_load(
                 // var_ptr.into_pointer_value(), 
                 *var_ptr,
@@ -1505,6 +3117,10 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             ).into();
         }
 
+        if let Some(function) = self.module.get_function(&name.value) {
+            return self.function_closure(function);
+        }
+
         return BasicValueEnum::IntValue(self.context.i8_type().const_zero());
         unimplemented!(); 
     }
@@ -1513,7 +3129,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
     -> inkwell::values::BasicValueEnum<'ctx> { 
         return match datatype {
             Datatype::bool 
-                => BasicValueEnum::IntValue(self.context.bool_type().const_int(1, false)),
+                => BasicValueEnum::IntValue(self.context.bool_type().const_int((value.tok_type == TokenType::K_TRUE) as u64, false)),
             Datatype::uint8 | Datatype::int8
                 => BasicValueEnum::<'ctx>::IntValue(
                     self.context.i8_type().const_int_from_string(
@@ -1548,6 +3164,16 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             Datatype::float64 => BasicValueEnum::<'ctx>::FloatValue(
                 self.context.f64_type().const_float_from_string(&value.value)
             ),
+            Datatype::char
+                => BasicValueEnum::IntValue(
+                    self.context.i32_type().const_int(value.value.chars().next().unwrap_or('\0') as u64, false)
+                ),
+            Datatype::string 
+                => BasicValueEnum::PointerValue(
+                    self.builder.build_global_string_ptr(&value.value, "str.literal").as_pointer_value()
+                ),
+            Datatype::reference{..} | Datatype::rc{..}
+                => BasicValueEnum::PointerValue(self.get_llvm_type(datatype).into_pointer_type().const_null()),
             Datatype::object{..} => /* *self.symbol_table.variable_table.get(&value.value).unwrap() */
                     BasicValueEnum::PointerValue(*self.symbol_table.variable_table.get(&value.value).unwrap()),
             _ => BasicValueEnum::<'ctx>::IntValue(self.context.bool_type().const_zero()),
@@ -1560,30 +3186,50 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
     -> inkwell::values::BasicValueEnum<'ctx> {
 
         match &**callee {
-            Expr::AttributeRef{name, object, object_dtype, datatype}
+            // a struct field holding a function value
+            Expr::AttributeRef{datatype: fn_dtype @ Datatype::function{obj_name: None, ..}, ..}
+                => {
+                    let closure = self.visit_expr(callee);
+                    return self.call_closure(closure, fn_dtype, arguments);
+            },
+            // `ch.close()`
+            Expr::AttributeRef{name, object, object_dtype: Datatype::channel{..}, ..}
+                => return self.close_channel(object, name),
+            Expr::AttributeRef{name, object, object_dtype, datatype, ..}
                 => {
 
-                let is_parsing_lvalue = self.is_parsing_lvalue;
-                self.is_parsing_lvalue = true;
-                let obj = self.visit_expr(object);
-                self.is_parsing_lvalue = is_parsing_lvalue;
+                let obj = self.object_ptr(object, object_dtype, name);
+                let object_dtype = object_dtype.auto_deref();
 
                 if let Datatype::object{name: obj_name} = object_dtype {
                     let func_name = obj_name.clone()+"."+name.value.as_str();
                     return self.call_function(func_name, Some(obj), Some(obj_name.clone()), arguments);
                 } 
 
+                if let Datatype::dyn_trait{name: trait_name} = object_dtype {
+                    return self.call_dyn_method(obj, trait_name, name, arguments);
+                }
+
             },
-            Expr::Variable{name, datatype, struct_name}
+            Expr::Variable{name, datatype, struct_name, ..}
                 => {
                     if struct_name.is_some() { 
                         // print error and return
                     }
 
+                    // variables shadow functions of the same name
+                    if self.symbol_table.variable_table.contains_key(&name.value) {
+                        let closure = self.visit_expr(callee);
+                        return self.call_closure(closure, datatype, arguments);
+                    }
+
                     return self.call_function(name.value.clone(), None, None, arguments);
                 
             },
-            _ => ()
+            _ => {
+                let closure = self.visit_expr(callee);
+                return self.call_closure(closure, &callee.get_datatype(), arguments);
+            }
         }
 
         unimplemented!(); 
@@ -1595,15 +3241,13 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         obj_dtype: &Datatype,
         datatype: &Datatype)
     -> inkwell::values::BasicValueEnum<'ctx> {
-        let is_parsing_lvalue = self.is_parsing_lvalue;
-        self.is_parsing_lvalue = true;
-        let obj_ptr = self.visit_expr(object);
-        self.is_parsing_lvalue = is_parsing_lvalue;
+        let obj_ptr = self.object_ptr(object, obj_dtype, name);
+        let obj_dtype = obj_dtype.auto_deref();
 
         let mut attr_index = 0;
         let mut found_attr = false;
         if let Datatype::object{name: obj_name} = obj_dtype {
-            if let Decl::StructDecl{name:struct_name, fields}
+            if let Decl::StructDecl{name:struct_name, fields, ..}
              = self.symbol_table.struct_decls.get(&obj_name.clone()).unwrap() {
 
                 for (field_name, field_type) in fields {
@@ -1641,14 +3285,14 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             let attr_name = name.clone();
             let impl_decls = self.symbol_table.impl_decls.get(obj_name).unwrap();
             for impl_decl in impl_decls {
-                if let Decl::ImplDecl{name: struct_name, trait_name, funcs}
+                if let Decl::ImplDecl{name: struct_name, trait_name, funcs, ..}
                     = &**impl_decl {
 
                     for func in funcs {
 
-                        if let Decl::FuncDef{prototype, block} = &**func {
+                        if let Decl::FuncDef{prototype, block, ..} = &**func {
 
-                            if let Decl::Prototype{name:func_name, parameters, returntype} = &**prototype {
+                            if let Decl::Prototype{name:func_name, parameters, returntype, ..} = &**prototype {
 
                                 if func_name.value == attr_name.value {
                                     // need to return functionvalue but basicvalueenum doesnt have it.
@@ -1667,7 +3311,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         }
 
         // print some error.
-        log_message(logger::LogLevel::ERROR, name.col, name.line, 
+        self.diagnostics.error(E_CODEGEN, name.span, 
             "Given object doesn't have specified attribute".to_string());
         return BasicValueEnum::IntValue(self.context.bool_type().const_zero());
         unimplemented!();
@@ -1675,9 +3319,33 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
     fn visit_binary_expr(&mut self, lhs: &Box<Expr>, rhs: &Box<Expr>, operator: &Token, datatype: &Datatype)
     -> inkwell::values::BasicValueEnum<'ctx> { 
+        // `and`/`or` must not evaluate rhs unless needed.
+        match operator.tok_type {
+            TokenType::K_AND|TokenType::K_OR   => return self.logical_short_circuit(lhs, rhs, operator),
+            _ => ()
+        }
+
         let mut lhs_val = self.visit_expr(lhs);
         let mut rhs_val = self.visit_expr(rhs);
 
+        // references and rcs are compared by address.
+        if lhs_val.is_pointer_value() && (Datatype::is_reference(&lhs.get_datatype()) || Datatype::is_rc(&lhs.get_datatype())) {
+            let i64_type = self.context.i64_type();
+            lhs_val = BasicValueEnum::IntValue(
+                self.builder.build_ptr_to_int(lhs_val.into_pointer_value(), i64_type, "ref.addr"));
+            rhs_val = BasicValueEnum::IntValue(
+                self.builder.build_ptr_to_int(rhs_val.into_pointer_value(), i64_type, "ref.addr"));
+        }
+
+        // strings are compared by content, not by pointer.
+        if lhs.get_datatype() == Datatype::string {
+            let eq = self.string_equal(lhs_val, rhs_val);
+            if operator.tok_type == TokenType::BANG_EQUAL {
+                return BasicValueEnum::IntValue(self.builder.build_not(eq, "str.not_eq_"));
+            }
+            return BasicValueEnum::IntValue(eq);
+        }
+
         match operator.tok_type {
             TokenType::PLUS         =>  return self.add_(lhs_val, rhs_val),
             TokenType::MINUS        =>  return self.sub_(lhs_val, rhs_val),
@@ -1696,15 +3364,55 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             TokenType::LESS_EQUAL   =>  return self.less_equal(lhs_val, rhs_val, datatype),
             TokenType::GREAT_THAN   =>  return self.greater_than(lhs_val, rhs_val, datatype),
             TokenType::GREAT_EQUAL  =>  return self.greater_equal(lhs_val, rhs_val, datatype),
-
-            // operands are i1, result will be i1.
-            TokenType::K_AND        =>  return self.bitwise_and(lhs_val, rhs_val),
-            TokenType::K_OR         =>  return self.bitwise_or(lhs_val, rhs_val),
             _ => ()
         }
         unimplemented!(); 
     }
 
+    fn string_equal(&mut self, lhs: BasicValueEnum<'ctx>, rhs: BasicValueEnum<'ctx>) -> IntValue<'ctx> {
+        let eqstr = self.module.get_function("eqstr").unwrap();
+        return self.builder.build_call(eqstr, &[lhs, rhs], "str.eqeq_")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+    }
+
+    /// Lowers `lhs and rhs` / `lhs or rhs`. Operands are i1, result will be i1.
+    /// rhs is emitted in its own block and is only evaluated when lhs doesn't decide the result.
+    fn logical_short_circuit(&mut self, lhs: &Box<Expr>, rhs: &Box<Expr>, operator: &Token)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let current_fn = self.curr_fn_value.unwrap();
+        let is_and = operator.tok_type == TokenType::K_AND;
+
+        let lhs_val = self.visit_expr(lhs).into_int_value();
+        // block in which lhs ended. Needed for phi as lhs itself may have branched.
+        let lhs_bb = self.builder.get_insert_block().unwrap();
+
+        let rhs_bb = self.context.append_basic_block(current_fn, if is_and {"and.rhs"} else {"or.rhs"});
+        let cont_bb = self.context.append_basic_block(current_fn, if is_and {"and.cont"} else {"or.cont"});
+
+        // `and` needs rhs only when lhs is true. `or` needs rhs only when lhs is false.
+        if is_and {
+            self.builder.build_conditional_branch(lhs_val, rhs_bb, cont_bb);
+        } else {
+            self.builder.build_conditional_branch(lhs_val, cont_bb, rhs_bb);
+        }
+
+        self.builder.position_at_end(rhs_bb);
+        let rhs_val = self.visit_expr(rhs).into_int_value();
+        self.builder.build_unconditional_branch(cont_bb);
+        let rhs_bb = self.builder.get_insert_block().unwrap();
+
+        self.builder.position_at_end(cont_bb);
+        let bool_type = self.context.bool_type();
+        let short_circuit_val = bool_type.const_int(if is_and {0} else {1}, false);
+        let phi = self.builder.build_phi(bool_type, if is_and {"and.res"} else {"or.res"});
+        phi.add_incoming(&[(&short_circuit_val, lhs_bb), (&rhs_val, rhs_bb)]);
+
+        return phi.as_basic_value();
+    }
+
     fn visit_unary_expr(&mut self, operator: &Token, operand: &Box<Expr>, datatype: &Datatype)
     -> inkwell::values::BasicValueEnum<'ctx> { 
         let oprnd = self.visit_expr(operand);
@@ -1725,39 +3433,65 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         
         let mut field_name_index_map = HashMap::new();
         
-        if let Decl::StructDecl{name, fields: field_decls} = struct_decl {
+        if let Decl::StructDecl{name, fields: field_decls, ..} = struct_decl {
             for (i, (field_name, field_type)) in field_decls.into_iter().enumerate() {
                 field_name_index_map.insert(field_name.value.clone(), i);
             }
         }
 
-        let mut struct_field_vals = vec![];
-        // println!("");
-        struct_field_vals.reserve(field_name_index_map.len());
+        // fields may hold values only known at runtime, so the struct is built up field by field.
+        let mut struct_val = struct_type.get_undef();
         for (field_name, field_expr) in fields {
             
             let field_val = self.visit_expr(field_expr);
-            println!("COdegen-structexpr-Fieldval: {:#?}, clone: {:#?}", field_val, field_val.clone());
-            // let field_v = match field_val {
-            //     BasicValueEnum::ArrayValue(a) 
-            //     => BasicValueEnum::ArrayValue(a),
-            //     BasicValueEnum::FloatValue(f) 
-            //     => BasicValueEnum::FloatValue(f)
-            // };
-
-            struct_field_vals.insert(
-                *field_name_index_map.get(&field_name.value).unwrap(), 
-                field_val.clone());
+            self.rc_copy(field_expr, field_val);
+            eprintln!("COdegen-structexpr-Fieldval: {:#?}, clone: {:#?}", field_val, field_val.clone());
+
+            struct_val = self.builder.build_insert_value(
+                struct_val, 
+                field_val, 
+                *field_name_index_map.get(&field_name.value).unwrap() as u32, 
+                field_name.value.as_str()
+            ).unwrap().into_struct_value();
         }
 
         
-        return BasicValueEnum::StructValue(struct_type.const_named_struct(&struct_field_vals));
+        return BasicValueEnum::StructValue(struct_val);
         unimplemented!(); 
     }
 
+    /* every value is computed before any target is written, so `a, b = b, a` swaps */
+    fn visit_multi_assignment_expr(&mut self, targets: &Vec<Box<Expr>>, expr: &Box<Expr>)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let tuple_val = self.visit_expr(expr);
+        self.rc_copy(expr, tuple_val);
+        let tuple = tuple_val.into_struct_value();
+        for (i, target) in targets.iter().enumerate() {
+            let val = self.builder.build_extract_value(tuple, i as u32, "tuple.elem").unwrap();
+            let is_parsing_lvalue = self.is_parsing_lvalue;
+            self.is_parsing_lvalue = true;
+            let target_ptr = self.visit_expr(target).into_pointer_value();
+            self.is_parsing_lvalue = is_parsing_lvalue;
+            // the values are counted already, only the replaced ones are released
+            let target_dtype = target.get_datatype();
+            if self.contains_rc(&target_dtype) {
+                let old_val = self.builder.build_load(target_ptr, "rc.old");
+                self.builder.build_store(target_ptr, val);
+                self.rc_release_value(old_val, &target_dtype);
+            } else {
+                self.builder.build_store(target_ptr, val);
+            }
+        }
+        return BasicValueEnum::StructValue(tuple);
+    }
+
     fn visit_assignment_expr(&mut self, target: &Box<Expr>, operator: &Token, expr: &Box<Expr>, datatype: &Datatype)
     -> inkwell::values::BasicValueEnum<'ctx> { 
 
+        if let Expr::ExprList{expr_list: targets, ..} = &**target {
+            return self.visit_multi_assignment_expr(targets, expr);
+        }
+
         if let Datatype::object{..} = datatype {
             return self.visit_object_assignment_expr(target, operator, expr, datatype);
         }
@@ -1766,15 +3500,13 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         let lhs_ptr = self.visit_expr(target); /* Should be pointer */
         self.is_parsing_lvalue = false;
         let rhs_val = self.visit_expr(expr);
-        println!("Codegen-AssignmentExpr: lhs_ptr.is_ptr()={}", lhs_ptr.is_pointer_value());
+        eprintln!("Codegen-AssignmentExpr: lhs_ptr.is_ptr()={}", lhs_ptr.is_pointer_value());
 
         match operator.tok_type {
             TokenType::EQUAL => {
-                println!("Codegen-AssignmentExpr: TokenType::EQUAL");
-                self.builder.build_store(
-                lhs_ptr.into_pointer_value(), 
-                rhs_val
-            );},
+                eprintln!("Codegen-AssignmentExpr: TokenType::EQUAL");
+                self.rc_store(lhs_ptr.into_pointer_value(), rhs_val, expr);
+            },
             TokenType::PLUS_EQUAL => {
                 let lhs_val = self.builder.build_load(lhs_ptr.into_pointer_value(), "plus_eq");
                 self.builder.build_store(
@@ -1846,6 +3578,105 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         // unimplemented!(); 
     }
 
+    fn visit_array_literal_expr(&mut self, bracket_token: &Token, elements: &Vec<Box<Expr>>, datatype: &Datatype)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let array_type = self.get_llvm_type(datatype).into_array_type();
+        let mut array_val = array_type.get_undef();
+
+        for (i, element) in elements.iter().enumerate() {
+            let elem_val = self.visit_expr(element);
+            self.rc_copy(element, elem_val);
+            array_val = self.builder.build_insert_value(
+                array_val, 
+                elem_val, 
+                i as u32, 
+                "arr.insert"
+            ).unwrap().into_array_value();
+        }
+
+        return BasicValueEnum::ArrayValue(array_val);
+    }
+
+    /// Returns pointer to the element at `index`. Emits a runtime bounds check 
+    /// which aborts with line number of `bracket_token` when index is out of range.
+    fn array_element_ptr(&mut self, array: &Box<Expr>, index: &Box<Expr>, bracket_token: &Token)
+    -> inkwell::values::PointerValue<'ctx> {
+        let current_fn = self.curr_fn_value.unwrap();
+
+        let array_dtype = array.get_datatype();
+
+        let is_parsing_lvalue = self.is_parsing_lvalue;
+        // a reference or an rc to an array already is a pointer to it
+        let is_pointer = Datatype::is_reference(&array_dtype) || Datatype::is_rc(&array_dtype);
+        self.is_parsing_lvalue = !is_pointer;
+        let mut array_ptr = self.visit_expr(array);
+        self.is_parsing_lvalue = false;
+        let index_val = self.visit_expr(index).into_int_value();
+        self.is_parsing_lvalue = is_parsing_lvalue;
+
+        if is_pointer {
+            self.check_null(array_ptr.into_pointer_value(), bracket_token);
+        }
+
+        // arrays which are not stored anywhere (literals, call results) are spilled to stack.
+        if !array_ptr.is_pointer_value() {
+            let tmp_ptr = self.build_entry_alloca(array_ptr.get_type(), "arr.tmp");
+            self.builder.build_store(tmp_ptr, array_ptr);
+            array_ptr = BasicValueEnum::PointerValue(tmp_ptr);
+        }
+
+        let size = match array_dtype.auto_deref() {
+            Datatype::array{size, ..}   => *size,
+            _   => 0
+        };
+
+        // index is compared as unsigned 64-bit so that negative indices are caught as well.
+        let i64_type = self.context.i64_type();
+        let index_val = if Datatype::is_signed_int(&index.get_datatype()) {
+            self.builder.build_int_s_extend_or_bit_cast(index_val, i64_type, "idx.ext")
+        } else {
+            self.builder.build_int_z_extend_or_bit_cast(index_val, i64_type, "idx.ext")
+        };
+        let size_val = i64_type.const_int(size, false);
+
+        let in_bounds = self.builder.build_int_compare(inkwell::IntPredicate::ULT, index_val, size_val, "idx.inbounds");
+        let fail_bb = self.context.append_basic_block(current_fn, "idx.fail");
+        let ok_bb = self.context.append_basic_block(current_fn, "idx.ok");
+        self.builder.build_conditional_branch(in_bounds, ok_bb, fail_bb);
+
+        self.builder.position_at_end(fail_bb);
+        let bounds_fn = self.module.get_function("array_index_out_of_bounds").unwrap();
+        self.builder.build_call(
+            bounds_fn, 
+            &[
+                BasicValueEnum::IntValue(i64_type.const_int(bracket_token.span.start.line as u64, false)),
+                BasicValueEnum::IntValue(index_val),
+                BasicValueEnum::IntValue(size_val)
+            ], 
+            ""
+        );
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_bb);
+        let elem_ptr = unsafe {
+            self.builder.build_in_bounds_gep(
+                array_ptr.into_pointer_value(), 
+                &[i64_type.const_zero(), index_val], 
+                "arr.elem.ptr"
+            )
+        };
+        return elem_ptr;
+    }
+
+    fn visit_index_expr(&mut self, array: &Box<Expr>, index: &Box<Expr>, bracket_token: &Token, datatype: &Datatype)
+    -> inkwell::values::BasicValueEnum<'ctx> {
+        let elem_ptr = self.array_element_ptr(array, index, bracket_token);
+        if self.is_parsing_lvalue {
+            return BasicValueEnum::PointerValue(elem_ptr);
+        }
+        return self.builder.build_load(elem_ptr, "arr.elem");
+    }
+
     fn visit_grouping_expr(&mut self, expr: &Box<Expr>, datatype: &Datatype) 
     -> inkwell::values::BasicValueEnum<'ctx> { 
         return self.visit_expr(expr);
@@ -1858,13 +3689,21 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
         from_dtype: &Datatype, 
         to_dtype: &Datatype)
     -> inkwell::values::BasicValueEnum<'ctx> { 
+        if let Datatype::dyn_trait{name: trait_name} = to_dtype {
+            if let Datatype::object{name: struct_name} = from_dtype {
+                return self.build_trait_object(variable, struct_name, trait_name);
+            }
+            // already a trait object of the same trait
+            return self.visit_expr(variable);
+        }
+
         let var_value = self.visit_expr(variable);
         
         if var_value.is_int_value() {
             if /*Datatype::is_int(from_dtype) &&*/ Datatype::is_float(to_dtype) {
                 return self.cast_int_to_float(var_value, from_dtype, to_dtype);
             }
-            else if Datatype::is_int(to_dtype) {
+            else if Datatype::is_int(to_dtype) || *to_dtype == Datatype::char {
                 return self.cast_int_to_int(var_value, from_dtype, to_dtype);
             } else {
                 unimplemented!();
@@ -1888,6 +3727,23 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             unimplemented!();
         }
 
+        // several values make a tuple, as in `return a, b;`
+        if expr_list.len() > 1 {
+            let mut values = vec![];
+            for expr in expr_list {
+                let value = self.visit_expr(expr);
+                self.rc_copy(expr, value);
+                values.push(value);
+            }
+            let value_types: Vec<BasicTypeEnum> = values.iter().map(|val| val.get_type()).collect();
+            let mut tuple = self.context.struct_type(value_types.as_slice(), false).get_undef();
+            for (i, val) in values.into_iter().enumerate() {
+                tuple = self.builder.build_insert_value(tuple, val, i as u32, "tuple")
+                    .unwrap().into_struct_value();
+            }
+            return BasicValueEnum::StructValue(tuple);
+        }
+
         let reply = self.visit_expr(&expr_list[0]);
         let mut i = 1;
         while i < expr_list.len() {
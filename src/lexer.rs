@@ -1,6 +1,7 @@
 use unicode_segmentation::UnicodeSegmentation;
 use crate::globals::TokenType;
-use crate::logger;
+use crate::diagnostics::*;
+use crate::span::{Position, Span};
 use std::fmt;
 
 #[allow(dead_code)]
@@ -9,8 +10,7 @@ use std::fmt;
 pub struct Token {
     pub tok_type: TokenType,
     pub value: String,
-    pub line: usize,
-    pub col: usize
+    pub span: Span
 }
 
 impl fmt::Debug for Token {
@@ -25,9 +25,13 @@ pub struct Lexer {
     line_num: usize,
     current: usize,
     start: usize,
+    start_pos: Position, /* where the token being scanned begins */
+    file: usize,
+    offsets: Vec<usize>, /* byte offset of each grapheme in `str_vec`, and of the end */
     program: String,
     str_vec: Vec<String>,
     tokens: Vec<Token>,
+    diagnostics: Diagnostics,
     has_errors: bool
 }
 
@@ -35,15 +39,19 @@ pub struct Lexer {
 
 #[allow(dead_code)]
 impl Lexer {
-    pub fn new() -> Self {
+    pub fn new(diagnostics: Diagnostics, file: usize) -> Self {
         Lexer {
             col: 0,
             line_num: 1,
             current: 0,
             start: 0,
+            start_pos: Position::default(),
+            file,
+            offsets: Vec::new(),
             program: String::new(),
             str_vec: Vec::new(),
             tokens: Vec::new(),
+            diagnostics,
             has_errors: false
         }
     }
@@ -92,6 +100,15 @@ impl Lexer {
     }
 
 
+    fn position(&self) -> Position {
+        Position{ offset: self.offsets[self.current], line: self.line_num, col: self.col + 1 }
+    }
+
+    /* from `start` up to the next grapheme */
+    fn span_from(&self, start: Position) -> Span {
+        Span::new(self.file, start, self.position())
+    }
+
     fn increment_line(&mut self) {
         self.line_num += 1;
         self.col = 0;
@@ -142,8 +159,7 @@ impl Lexer {
             self.tokens.push(Token{
                 tok_type: TokenType::HEX_LITERAL,
                 value: (&self.str_vec[self.start..self.current]).join(""),
-                line: self.line_num,
-                col: self.col
+                span: self.span_from(self.start_pos)
             });
 
             return;
@@ -158,8 +174,7 @@ impl Lexer {
             self.tokens.push(Token{
                 tok_type: TokenType::OCTAL_LITERAL,
                 value: (&self.str_vec[self.start..self.current]).join(""),
-                line: self.line_num,
-                col: self.col
+                span: self.span_from(self.start_pos)
             });
 
             return;
@@ -170,8 +185,8 @@ impl Lexer {
         while Self::is_digit(self.peek()) && !self.is_end() { self.advance(); }
         if self.is_end() {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, self.col, 
-                self.line_num, "Unterminated integer/float literal".to_string());
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Unterminated integer/float literal".to_string());
         }
 
         is_int = !self.match_(".");
@@ -179,8 +194,8 @@ impl Lexer {
         while Self::is_digit(self.peek()) && !self.is_end() { self.advance(); }
         if self.is_end() {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, self.col, 
-                self.line_num, "Unterminated integer/float literal".to_string());
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Unterminated integer/float literal".to_string());
         }
 
         #[allow(unused_must_use)]
@@ -192,15 +207,14 @@ impl Lexer {
         while Self::is_digit(self.peek()) && !self.is_end() { self.advance(); }
         if self.is_end() {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, self.col, 
-                self.line_num, "Unterminated integer/float literal".to_string());
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Unterminated integer/float literal".to_string());
         }
 
         self.tokens.push(Token{
             tok_type: if is_int {TokenType::INT_LITERAL} else {TokenType::FLOAT_LITERAL},
             value: (&self.str_vec[self.start..self.current]).join(""),
-            col: self.col,
-            line: self.line_num
+            span: self.span_from(self.start_pos)
         });
 
         return;
@@ -208,24 +222,101 @@ impl Lexer {
 
 
     fn string_(&mut self) {
-        while self.peek() != "\"" && !self.is_end() {
-            if self.peek() == "\n" { self.increment_line(); }
-            self.advance();
+        let mut value = String::new();
+
+        while self.peek() != "\"" && self.peek() != "\0" {
+            let c = self.advance();
+            if c == "\n" { self.increment_line(); }
+
+            if c != "\\" {
+                value += c.as_str();
+                continue;
+            }
+
+            // escape sequences
+            if self.peek() == "\0" { break; }
+            if let Some(escaped) = self.escape_sequence() {
+                value += escaped.as_str();
+            }
         }
 
-        if self.curr() == "\0" {
+        if self.peek() == "\0" {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, self.col, self.line_num, 
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
                 "Unterminated string".to_string());
+            return;
         }
 
         // closing quote
         self.advance();
         self.tokens.push(Token { 
             tok_type: TokenType::STRING_LITERAL, 
-            value: (&self.str_vec[self.start..self.current]).join(""), 
-            line: self.line_num, 
-            col: self.col 
+            value, 
+            span: self.span_from(self.start_pos)
+        });
+    }
+
+
+
+    /// Decodes the character following a `\`. Logs an error and returns `None` if it is unknown.
+    fn escape_sequence(&mut self) -> Option<String> {
+        // the `\` is already consumed
+        let start = Position{ offset: self.offsets[self.current - 1], col: self.col, ..self.position() };
+        let escaped = self.advance();
+        return match escaped.as_str() {
+            "n"     => Some("\n".to_string()),
+            "t"     => Some("\t".to_string()),
+            "r"     => Some("\r".to_string()),
+            "0"     => Some("\0".to_string()),
+            "\\"    => Some("\\".to_string()),
+            "\""    => Some("\"".to_string()),
+            "'"     => Some("'".to_string()),
+            _       => {
+                self.has_errors = true;
+                self.diagnostics.error(E_LEX, self.span_from(start), 
+                    format!("Unknown escape sequence '\\{}'", escaped));
+                None
+            }
+        };
+    }
+
+
+    fn char_(&mut self) {
+        if self.peek() == "'" || self.peek() == "\n" || self.peek() == "\0" {
+            self.has_errors = true;
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Empty or unterminated character literal".to_string());
+            self.match_("'");
+            return;
+        }
+
+        let mut value = self.advance();
+        if value == "\\" && self.peek() != "\0" {
+            value = self.escape_sequence().unwrap_or_default();
+        }
+
+        if !self.match_("'") {
+            self.has_errors = true;
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Character literal should contain exactly one character and end with '".to_string());
+            // skipping till the closing quote on the same line
+            while self.peek() != "'" && self.peek() != "\n" && self.peek() != "\0" { self.advance(); }
+            self.match_("'");
+            return;
+        }
+
+        // a grapheme could be made of multiple unicode scalar values
+        if value.chars().count() != 1 {
+            self.has_errors = true;
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
+                "Character literal should be a single unicode scalar value".to_string());
+            return;
+        }
+
+        self.tokens.push(Token { 
+            tok_type: TokenType::CHAR_LITERAL, 
+            value, 
+            span: self.span_from(self.start_pos)
         });
     }
 
@@ -238,7 +329,7 @@ impl Lexer {
 
         if self.is_end() {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, self.col, self.line_num, 
+            self.diagnostics.error(E_LEX, self.span_from(self.start_pos), 
                 "Unterminated identifier".to_string());
         }
 
@@ -254,8 +345,20 @@ impl Lexer {
             "as"            => TokenType::K_AS,
             
             "bool"          => TokenType::K_BOOL,
+            "break"         => TokenType::K_BREAK,
+
+            "case"          => TokenType::K_CASE,
+            "chan"          => TokenType::K_CHAN,
+            "char"          => TokenType::K_CHAR,
+
+            "continue"      => TokenType::K_CONTINUE,
+
+            "default"       => TokenType::K_DEFAULT,
+            "delete"        => TokenType::K_DELETE,
+            "dyn"           => TokenType::K_DYN,
             
             "else"          => TokenType::K_ELSE,
+            "enum"          => TokenType::K_ENUM,
             
             "for"           => TokenType::K_FOR,
             "func"          => TokenType::K_FUNC,
@@ -265,17 +368,31 @@ impl Lexer {
 
             "if"            => TokenType::K_IF,
             "impl"          => TokenType::K_IMPL,
+            "import"        => TokenType::K_IMPORT,
             "int8"          => TokenType::K_INT8,
             "int16"         => TokenType::K_INT16,
             "int32"         => TokenType::K_INT32,
             "int64"         => TokenType::K_INT64,
 
+            "loop"          => TokenType::K_LOOP,
+
+            "match"         => TokenType::K_MATCH,
+            "module"        => TokenType::K_MODULE,
+            "mut"           => TokenType::K_MUT,
+
+            "new"           => TokenType::K_NEW,
             "null"          => TokenType::K_NULL,
 
             "or"            => TokenType::K_OR,
 
+            "pub"           => TokenType::K_PUB,
+
+            "rc"            => TokenType::K_RC,
             "return"        => TokenType::K_RETURN,
 
+            "select"        => TokenType::K_SELECT,
+            "spawn"         => TokenType::K_SPAWN,
+            "string"        => TokenType::K_STRING,
             "struct"        => TokenType::K_STRUCT,
 
             "true"          => TokenType::K_TRUE,
@@ -290,6 +407,8 @@ impl Lexer {
 
             "while"         => TokenType::K_WHILE,
 
+            "_"             => TokenType::UNDERSCORE,
+
             // _               => {
             //     // if identifier, check if it is a struct or impl name.
             //     let last_tok: Option<&Token> = self.tokens.last();
@@ -310,8 +429,7 @@ impl Lexer {
         self.tokens.push(Token{
             tok_type,
             value: idntfr,
-            col: self.col,
-            line: self.line_num
+            span: self.span_from(self.start_pos)
         });
        
         
@@ -363,9 +481,10 @@ impl Lexer {
     fn scan_token(&mut self) {
         self.skip_non_code();
         self.start = self.current;
+        self.start_pos = self.position();
 
         if self.current > 0 && self.curr() == "\0" {
-            self.tokens.push(Token{ tok_type: TokenType::FILE_EOF, value: String::new(), col: self.col, line: self.line_num});
+            self.tokens.push(Token{ tok_type: TokenType::FILE_EOF, value: String::new(), span: self.span_from(self.position()) });
             return;
         }
 
@@ -381,7 +500,9 @@ impl Lexer {
             "}"         => TokenType::CURLY_CLOSE,
             "["         => TokenType::SQUARE_OPEN,
             "]"         => TokenType::SQUARE_CLOSE,
-            "="         => if self.match_("=")  {TokenType::EQUAL_EQUAL}        else {TokenType::EQUAL},
+            "="         => if self.match_("=")  {TokenType::EQUAL_EQUAL}        
+                            else if self.match_(">") {TokenType::FAT_ARROW}
+                            else {TokenType::EQUAL},
             "*"         => if self.match_("=")  {TokenType::ASTERISK_EQUAL}     else {TokenType::ASTERISK},
             "/"         => if self.match_("=")  {TokenType::SLASH_EQUAL}        else {TokenType::SLASH},
             "+"         => if self.match_("=")  {TokenType::PLUS_EQUAL}         else {TokenType::PLUS},
@@ -411,11 +532,12 @@ impl Lexer {
             "$"         => TokenType::DOLLAR,
             "#"         => TokenType::HASH,
             ";"         => TokenType::SEMICOLON,
-            ":"         => TokenType::COLON,
+            ":"         => if self.match_(":")  {TokenType::COLON_COLON}        else {TokenType::COLON},
             ","         => TokenType::COMMA,
             "."         => TokenType::DOT,
             "_"         => TokenType::UNDERSCORE,
             "\""        => { self.string_(); TokenType::ERROR }
+            "'"         => { self.char_(); TokenType::ERROR }
             _           => TokenType::ERROR 
         };
 
@@ -423,8 +545,7 @@ impl Lexer {
             self.tokens.push(Token { 
                 tok_type: tok_type, 
                 value: (&self.str_vec[self.start..self.current]).join(""), 
-                line: self.line_num, 
-                col: self.col
+                span: self.span_from(self.start_pos)
             });
         }
         
@@ -436,16 +557,19 @@ impl Lexer {
         // let char_vec = self.program.graphemes(true).collect::<Vec<&str>>();
         self.str_vec = Vec::new();
         
-        for chr in self.program.graphemes(true) {
+        self.offsets = Vec::new();
+        for (offset, chr) in self.program.grapheme_indices(true) {
             self.str_vec.push(String::from(chr));
+            self.offsets.push(offset);
         }
+        self.offsets.push(self.program.len());
         
 
         while self.current < self.str_vec.len() {
             self.scan_token();
         }
 
-        self.tokens.push(Token { tok_type: TokenType::FILE_EOF, value: String::new(), line: usize::MAX, col: usize::MAX });
+        self.tokens.push(Token { tok_type: TokenType::FILE_EOF, value: String::new(), span: self.span_from(self.position()) });
         
     
         return (self.tokens.clone(), self.has_errors);
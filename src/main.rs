@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process;
 mod globals;
-mod logger;
+mod diagnostics;
+mod span;
 mod lexer;
 mod visitor;
 mod ast;
 mod parser;
+mod module;
 mod symbol_table;
 mod type_check_visitor;
 mod ir_lowering;
@@ -19,12 +22,15 @@ use inkwell::passes::PassManager;
 use inkwell::targets::TargetMachine;
 use inkwell::values::FunctionValue;
 use lexer::Lexer;
-use logger::log_message;
+use diagnostics::*;
+use module::*;
+use span::Span;
 // use ast::{Stmt, Expr};
 use parser::Parser;
 use visitor::Printer;
 
 use crate::ir_lowering::Codegen;
+use crate::symbol_table::ScopeChain;
 use crate::symbol_table::IRSymbolTable;
 use crate::symbol_table::SymbolTable;
 use crate::type_check_visitor::TypeChecker;
@@ -41,12 +47,14 @@ struct Driver {
     emit_typed_tree: bool,
     emit_llvm_ir: bool,
     target: String,
-    output_filename: String 
+    output_filename: String,
+    error_format: ErrorFormat
 }
 
 impl Driver {
-    fn lex(&self, filecontent: String) -> Vec<lexer::Token> {
-        let mut lexer: Lexer = Lexer::new();
+    fn lex(&self, file_name: &str, filecontent: String, diagnostics: &Diagnostics) -> Vec<lexer::Token> {
+        let file = diagnostics.add_file(file_name, &filecontent);
+        let mut lexer: Lexer = Lexer::new(diagnostics.clone(), file);
         let (tokens, has_errors) = lexer.tokenize(filecontent);
         
         if self.emit_tokens {
@@ -62,13 +70,15 @@ impl Driver {
     }
 
 
-    fn parse(&self, tokens: Vec<lexer::Token>) -> Vec<Box<ast::Decl>> {
-        let mut parser: Parser = Parser::new();
-        let (declarations, has_errors) = parser.parse(tokens.clone());
+    fn parse(&self, tokens: Vec<lexer::Token>, module_name: String, diagnostics: &Diagnostics) -> Module {
+        let mut parser: Parser = Parser::new(diagnostics.clone());
+        // syntax errors don't stop compilation here, the partial tree is type checked as well
+        // so that one run reports as many errors as possible
+        let (module, _) = parser.parse_module(tokens, module_name);
         if self.emit_parse_tree {
             eprintln!("===================================Parse Tree=================================");
             let mut printer: Printer = Printer{space_width: 0};
-            for decl in &declarations {
+            for decl in &module.decls {
                 printer.visit_decl(decl);
                 // eprintln!("Decl: {:?}", *decl);
             }
@@ -76,27 +86,106 @@ impl Driver {
             
         }
 
-        if has_errors {
-            process::exit(1);
+        return module;
+    }
+
+    /* Reads, lexes and parses module `name` and then the modules it imports, depth first. 
+       `modules` ends up in dependency order, a module comes after all of the ones it imports. 
+       `loading` is the chain of imports being followed, an import of one of them is a cycle. */
+    fn load_module(&self, name: String, path: &Path, import: Option<&Import>, 
+                    loading: &mut Vec<String>, modules: &mut Vec<Module>, diagnostics: &Diagnostics) {
+        let file_name = path.to_string_lossy().to_string();
+        let filecontent = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => match import {
+                Some(import) => {
+                    diagnostics.emit(Diagnostic::error(E_IMPORT, import.span, 
+                            format!("Can't read module `{}`", name))
+                        .with_note(format!("looked for it in {}: {}", file_name, err)));
+                    return;
+                },
+                None => {
+                    eprintln!("Problem occured while reading the file: {}", err);
+                    process::exit(1);
+                }
+            }
+        };
+
+        let tokens = self.lex(&file_name, filecontent, diagnostics);
+        let module = self.parse(tokens, name.clone(), diagnostics);
+
+        match (&module.header, import) {
+            (Some((header, span)), None) => diagnostics.error(E_IMPORT, *span, 
+                format!("The root file of the program can't be declared as module `{}`", header)),
+            (Some((header, span)), Some(import)) if *header != name => {
+                diagnostics.emit(Diagnostic::error(E_IMPORT, *span, 
+                        format!("File declares module `{}` but is imported as `{}`", header, name))
+                    .with_label(import.span, "imported here".to_string()));
+            },
+            _ => ()
+        }
+
+        for (i, import) in module.imports.iter().enumerate() {
+            if let Some(previous) = module.imports[..i].iter().find(|previous| previous.alias == import.alias) {
+                diagnostics.emit(Diagnostic::error(E_IMPORT, import.span, 
+                        format!("A module named `{}` is imported already", import.alias))
+                    .with_label(previous.span, "first imported here".to_string()));
+            }
+        }
+
+        loading.push(name);
+        let root_dir = Path::new(&self.file_name).parent().unwrap_or(Path::new(""));
+        for import in &module.imports {
+            if modules.iter().any(|loaded| loaded.name == import.path) {
+                continue;
+            }
+            if let Some(position) = loading.iter().position(|loaded| *loaded == import.path) {
+                let mut cycle = loading[position..].to_vec();
+                cycle.push(import.path.clone());
+                diagnostics.emit(Diagnostic::error(E_IMPORT, import.span, 
+                        format!("Module `{}` imports itself", import.path))
+                    .with_note(format!("import cycle: {}", cycle.join(" -> "))));
+                continue;
+            }
+            self.load_module(import.path.clone(), &module_file(root_dir, &import.path), 
+                Some(import), loading, modules, diagnostics);
         }
-        return declarations;
+        loading.pop();
+        modules.push(module);
     }
 
-    fn type_check(&self, declarations: Vec<Box<ast::Decl>>) -> Vec<Box<ast::Decl>> {
+    fn type_check(&self, modules: Vec<Module>, diagnostics: &Diagnostics) -> Vec<Box<ast::Decl>> {
         let mut type_checker: TypeChecker = TypeChecker { 
             symbol_table: SymbolTable{
-                variable_table: HashMap::new(),
+                variable_table: ScopeChain::new(),
                 struct_decls: HashMap::new(),
                 impl_decls: HashMap::new(),
                 trait_decls: HashMap::new(),
+                trait_impls: HashMap::new(),
+                templates: HashMap::new(),
+                enum_decls: HashMap::new(),
                 func_table: HashMap::new()
             },
             current_scope: globals::Scope::Global,
+            loop_depth: 0,
+            instances: vec![],
+            instance_decls: HashMap::new(),
+            closures: vec![],
+            returntypes: vec![],
+            local_refs: HashMap::new(),
+            current_module: String::new(),
+            diagnostics: diagnostics.clone(),
             has_errors: false 
         };
-        let mut decls = declarations.clone();
-        for decl in &mut decls {
-            type_checker.visit_decl(decl);
+        let mut decls = vec![];
+        for module in modules {
+            type_checker.current_module = module.name;
+            for mut decl in module.decls {
+                type_checker.visit_decl(&mut decl);
+                // specializations of templates go before the declaration that uses them
+                decls.append(&mut type_checker.instances);
+                decls.push(decl);
+            }
         }
         if self.emit_typed_tree {
             eprintln!("===================================Typed Tree=================================");
@@ -109,14 +198,14 @@ impl Driver {
             
         }
 
-        if type_checker.has_errors {
+        if type_checker.has_errors || diagnostics.has_errors() {
             process::exit(1);
         }
         return decls;
     }
 
 
-    fn generate_llvm(&self, decls: Vec<Box<ast::Decl>>) -> () {
+    fn generate_llvm(&self, decls: Vec<Box<ast::Decl>>, diagnostics: &Diagnostics) -> () {
         let context = inkwell::context::Context::create();
         let module = context.create_module("main_mod");
         let builder = context.create_builder();
@@ -139,15 +228,21 @@ impl Driver {
             builder: &builder,
             module: &module,
             symbol_table: IRSymbolTable { 
-                variable_table: HashMap::new(), 
+                variable_table: ScopeChain::new(), 
                 struct_decls: HashMap::new(),
                 impl_decls: HashMap::new(),
                 trait_decls: HashMap::new(),
+                enum_decls: HashMap::new(),
                 func_table: HashMap::new()
             },
             current_scope: globals::Scope::Global,
             curr_fn_value: None,
-            is_parsing_lvalue: false
+            is_parsing_lvalue: false,
+            loop_stack: vec![],
+            rc_scopes: vec![],
+            is_function_body: false,
+            is_returned_if: false,
+            diagnostics: diagnostics.clone()
         };
 
         codegenerator.add_runtime_declarations();
@@ -217,23 +312,20 @@ impl Driver {
 
 
     fn compile_to_llvm(&self) {
-        let  filecontent = fs::read_to_string(self.file_name.clone()).unwrap_or_else(|err| {
-            eprintln!("Problem occured while reading the file: {}", err);
-            process::exit(1);
-        });
+        let diagnostics = Diagnostics::new(self.error_format);
 
-        // lexing
-        let tokens= self.lex(filecontent);
-
-        // Parsing
-        let declarations = self.parse(tokens);
+        // lexing and parsing of the root file and of every module it imports, 
+        // imports are resolved relative to the directory of the root file
+        let mut modules = vec![];
+        self.load_module(String::new(), Path::new(&self.file_name), None, &mut vec![], &mut modules, &diagnostics);
+        check_references(&modules, &diagnostics);
         
         // Type checking
-        let declarations = self.type_check(declarations);
+        let declarations = self.type_check(modules, &diagnostics);
 
 
-        // LLVM IR
-        let _ = self.generate_llvm(declarations);  
+        // LLVM IR, all the modules go in one LLVM module and so in one object file
+        let _ = self.generate_llvm(declarations, &diagnostics);  
         
 
         eprintln!("Currnet Directory: {:?}", std::env::current_dir());
@@ -251,11 +343,11 @@ impl Driver {
                     .expect("Error occured while trying to create an executable");
 
                 eprintln!("Status: {}", output.status);
-                std::io::stdout().write_all(&output.stdout).unwrap();
+                std::io::stderr().write_all(&output.stdout).unwrap();
                 std::io::stderr().write_all(&output.stderr).unwrap();
 
             } else {
-                log_message(logger::LogLevel::ERROR, 0, 0, 
+                diagnostics.error(E_DRIVER, Span::unknown(), 
                     "No suitable C/C++ compiler to make an executable".to_string()
                 );
             }
@@ -272,7 +364,7 @@ impl Driver {
                 .expect("Error occured while trying to create an executable");
 
             eprintln!("Status: {}", output.status);
-            std::io::stdout().write_all(&output.stdout).unwrap();
+            std::io::stderr().write_all(&output.stdout).unwrap();
             std::io::stderr().write_all(&output.stderr).unwrap();
             
 
@@ -324,6 +416,12 @@ fn main()  {
                         .long("output")
                         .help("The filename of executable file name")
                         .takes_value(true))
+                    .arg(Arg::with_name("errorformat")
+                        .long("error-format")
+                        .value_name("FORMAT")
+                        .possible_values(&["human", "json"])
+                        .help("Prints errors and warnings as text with source snippets (human) or one JSON object per line (json)")
+                        .takes_value(true))
                     .get_matches();
         
         // matches.value_of(name)
@@ -337,7 +435,8 @@ fn main()  {
         target: matches.value_of("target")
             .unwrap_or(inkwell::targets::TargetMachine::get_default_triple().as_str().to_str().unwrap())
             .to_string(),
-        output_filename: matches.value_of("output").unwrap_or("a.out").to_string()
+        output_filename: matches.value_of("output").unwrap_or("a.out").to_string(),
+        error_format: if matches.value_of("errorformat") == Some("json") { ErrorFormat::Json } else { ErrorFormat::Human }
     };
 
     driver.compile_to_llvm();
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Decl;
+use crate::diagnostics::*;
+use crate::lexer::Token;
+use crate::span::Span;
+
+/* `import geometry::shapes;` */
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,  /* "geometry::shapes" */
+    pub alias: String, /* "shapes", the name the importing file uses for the module */
+    pub span: Span
+}
+
+/* a struct, enum, trait or function declared at the top level of a module */
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub is_pub: bool,
+    pub span: Span
+}
+
+/* a use of an item of an imported module, as in `shapes::area` */
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub module: String,
+    pub name: Token /* qualified name, with the span of the whole path */
+}
+
+/* one source file of the program.
+   Top level names of a module are qualified with its path ("geometry::shapes::area"),
+   the root file is the unnamed module and its names are left as they are. */
+#[derive(Debug, Default)]
+pub struct Module {
+    pub name: String,
+    pub header: Option<(String, Span)>, /* the name given by `module a::b;` */
+    pub imports: Vec<Import>,
+    pub items: HashMap<String, Item>, /* key: qualified name */
+    pub references: Vec<Reference>,
+    pub decls: Vec<Box<Decl>>
+}
+
+impl Module {
+    /* prefix of the names declared in this module */
+    pub fn qualify(&self, name: &str) -> String {
+        if self.name.is_empty() { return name.to_string(); }
+        format!("{}::{}", self.name, name)
+    }
+}
+
+/* `geometry::shapes` is read from `<root>/geometry/shapes.bs` */
+pub fn module_file(root_dir: &Path, path: &str) -> PathBuf {
+    let mut file = root_dir.to_path_buf();
+    for segment in path.split("::") {
+        file.push(segment);
+    }
+    file.set_extension("bs");
+    return file;
+}
+
+/* every path used by a module must name a public item of the module it imports */
+pub fn check_references(modules: &Vec<Module>, diagnostics: &Diagnostics) {
+    for module in modules {
+        for reference in &module.references {
+            let target = match modules.iter().find(|m| m.name == reference.module) {
+                Some(target) => target,
+                _ => continue /* the import itself failed and is reported already */
+            };
+            let item_name = reference.name.value[reference.module.len() + 2..].to_string();
+            match target.items.get(&reference.name.value) {
+                Some(item) if !item.is_pub => {
+                    diagnostics.emit(Diagnostic::error(E_PRIVATE, reference.name.span,
+                            format!("`{}` is private to module `{}`", item_name, reference.module))
+                        .with_label(item.span, "declared here without `pub`".to_string()));
+                },
+                Some(_) => (),
+                _ => diagnostics.error(E_UNKNOWN_ITEM, reference.name.span,
+                    format!("Module `{}` has no item named `{}`", reference.module, item_name))
+            }
+        }
+    }
+}
@@ -2,11 +2,18 @@
 // use std::fs::OpenOptions;
 // use std::usize::MAX;
 
-use crate::logger::log_message;
-use crate::{ast::*, logger};
+use std::collections::HashSet;
+
+use crate::ast::*;
+use crate::diagnostics::*;
+use crate::module::*;
+use crate::span::Span;
 use crate::globals::TokenType;
 use crate::lexer::Token;
 
+/* parsing stops after this many syntax errors, later ones are mostly caused by the earlier ones */
+const MAX_SYNTAX_ERRORS: usize = 20;
+
 #[allow(dead_code, non_camel_case_types)]
 #[derive(PartialEq)]
 enum Restriction {
@@ -19,6 +26,11 @@ pub struct Parser {
     start: usize,
     tokens: Vec<Token>,
     restrictions: Vec<Restriction>,
+    diagnostics: Diagnostics,
+    panic_mode: bool, /* an error was reported and the parser hasn't recovered from it yet */
+    error_count: usize,
+    module: Module, /* the module being parsed, its imports and the items it declares */
+    local_types: HashSet<String>, /* structs, enums and traits declared in this file, unqualified */
     has_errors: bool
 }
 
@@ -86,28 +98,42 @@ impl Parser {
         return Some(self.tokens[self.current - 1].clone());
     }
 
+    /* span of the next token, where the node about to be parsed starts */
+    fn start_span(&self) -> Span {
+        self.tokens[self.current].span
+    }
+
+    /* span of the last consumed token, for nodes starting with a keyword that is already matched */
+    fn previous_span(&self) -> Span {
+        if self.current == 0 { return Span::unknown(); }
+        self.tokens[self.current - 1].span
+    }
+
+    /* from `start` to the end of the last consumed token */
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    /* from the first to the last expression of a list */
+    fn list_span(exprs: &Vec<Box<Expr>>) -> Span {
+        match (exprs.first(), exprs.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => Span::unknown()
+        }
+    }
+
     fn advance(&mut self) -> Option<Token> {
         if !self.is_end() { self.current += 1; }
         return self.curr();
     }
 
     fn consume(&mut self, ttype: TokenType, error_msg: String) -> Option<Token> {
-        if self.check(ttype) { return self.advance(); }
-        match self.peek() {
-            Some(tok) => {
-                self.has_errors = true;
-                logger::log_message(logger::LogLevel::ERROR, tok.col, tok.line, error_msg)
-            },
-            _ => match self.curr() {
-                Some(tok) => logger::log_message(logger::LogLevel::ERROR, tok.col, tok.line, error_msg),
-                _ => {
-                    self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, usize::MAX, usize::MAX, 
-                    "Unexpected error occured\n".to_string())
-                }
-            }
-        };
-
+        if self.check(ttype.clone()) { return self.advance(); }
+        if self.can_insert(&ttype) {
+            return Some(self.insert(ttype, error_msg));
+        }
+        let span = self.start_span();
+        self.error(span, error_msg);
         return None;
     }
 
@@ -116,58 +142,297 @@ impl Parser {
             return self.curr();
         } 
 
-        match self.peek() {
-            Some(tok) => {
-                self.has_errors = true;
-                logger::log_message(logger::LogLevel::ERROR, tok.col, tok.line, error_msg)
+        let span = self.start_span();
+        self.error(span, error_msg);
+        return None;
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.report(Diagnostic::error(E_SYNTAX, span, message));
+    }
+
+    /* Only the first error of a statement is reported, the ones after it are mostly caused by it. 
+       `panic_mode` is cleared once the parser has skipped to the next statement or declaration. */
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.has_errors = true;
+        if self.panic_mode || self.error_count >= MAX_SYNTAX_ERRORS { return; }
+        self.panic_mode = true;
+        self.error_count += 1;
+
+        if self.error_count < MAX_SYNTAX_ERRORS {
+            self.diagnostics.emit(diagnostic);
+            return;
+        }
+        self.diagnostics.emit(diagnostic.with_note(
+            format!("stopped parsing after {} syntax errors", MAX_SYNTAX_ERRORS)));
+        self.current = self.tokens.len() - 1;
+    }
+
+    /* a missing ';', ')' or ']' is assumed to be forgotten when the code after it 
+       starts on a new line or clearly can't continue the current statement */
+    fn can_insert(&self, ttype: &TokenType) -> bool {
+        if *ttype != TokenType::SEMICOLON && *ttype != TokenType::BRACKET_CLOSE && *ttype != TokenType::SQUARE_CLOSE {
+            return false;
+        }
+        if self.is_end() || self.check(TokenType::CURLY_CLOSE) {
+            return true;
+        }
+        if self.start_span().start.line > self.previous_span().end.line {
+            return true;
+        }
+        return *ttype != TokenType::SEMICOLON && 
+            (self.check(TokenType::SEMICOLON) || self.check(TokenType::CURLY_OPEN));
+    }
+
+    /* reports the missing token right after the previous one and carries on as if it was there */
+    fn insert(&mut self, ttype: TokenType, error_msg: String) -> Token {
+        let previous = self.previous_span();
+        let span = Span::new(previous.file, previous.end, previous.end);
+        let value = match ttype {
+            TokenType::SEMICOLON => ";",
+            TokenType::BRACKET_CLOSE => ")",
+            _ => "]"
+        };
+
+        let panic_mode = self.panic_mode;
+        self.report(Diagnostic::error(E_SYNTAX, span, error_msg)
+            .with_suggestion(format!("insert `{}` here", value)));
+        // nothing has to be skipped, the statement goes on normally
+        self.panic_mode = panic_mode;
+        Token{ tok_type: ttype, value: value.to_string(), span }
+    }
+
+    /* a placeholder for an expression that is missing, so that the enclosing node is still built */
+    fn missing_expr(&mut self, error_msg: String) -> Box<Expr> {
+        let span = self.start_span();
+        self.error(span, error_msg);
+        Box::new(Expr::Error{span})
+    }
+
+    fn starts_statement(ttype: TokenType) -> bool {
+        match ttype {
+            TokenType::K_VAR|TokenType::K_IF|TokenType::K_WHILE|TokenType::K_FOR|TokenType::K_LOOP
+            |TokenType::K_RETURN|TokenType::K_BREAK|TokenType::K_CONTINUE|TokenType::K_SPAWN
+            |TokenType::K_DELETE|TokenType::K_SELECT
+            |TokenType::K_STRUCT|TokenType::K_IMPL|TokenType::K_ENUM|TokenType::K_TRAIT => true,
+            _ => false
+        }
+    }
+
+    /* Skips the rest of a statement which failed to parse, that started at token `start`. 
+       Stops after its ';', or before the '}' of the enclosing block or the next statement. 
+       Blocks inside the statement are skipped whole, even when they aren't closed. */
+    fn recover_statement(&mut self, start: usize) {
+        if self.current > start {
+            if let Some(tok) = self.curr() {
+                if tok.tok_type == TokenType::SEMICOLON || tok.tok_type == TokenType::CURLY_CLOSE { return; }
             }
-            _ => match self.curr() {
-                Some(tok) => logger::log_message(logger::LogLevel::ERROR, tok.col, tok.line, error_msg),
-                _ => {
-                    self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, usize::MAX, usize::MAX, 
-                    "Unexpected error occured\n".to_string())
-                }
+        }
+
+        let mut depth = 0;
+        while !self.is_end() {
+            let ttype = self.peek().unwrap().tok_type;
+            match ttype {
+                TokenType::CURLY_OPEN => depth += 1,
+                TokenType::CURLY_CLOSE if depth == 0 => return,
+                TokenType::CURLY_CLOSE => depth -= 1,
+                TokenType::SEMICOLON if depth == 0 => {
+                    self.advance();
+                    return;
+                },
+                _ if depth == 0 && self.current > start && Parser::starts_statement(ttype) => return,
+                _ => ()
             }
-        };
+            self.advance();
+        }
+    }
 
-        return None;
+    /* skips a method of an impl or a trait which failed to parse, up to the next method or the end of the body */
+    fn recover_method(&mut self) {
+        let mut depth = 0;
+        while !self.is_end() {
+            match self.peek().unwrap().tok_type {
+                TokenType::CURLY_OPEN => depth += 1,
+                TokenType::CURLY_CLOSE if depth == 0 => break,
+                TokenType::CURLY_CLOSE => depth -= 1,
+                TokenType::K_FUNC if depth == 0 => break,
+                _ => ()
+            }
+            self.advance();
+        }
+        self.panic_mode = false;
     }
 
+    /* skips to the next top level declaration after one failed to parse */
     fn synchronize(&mut self) {
-        self.advance();
-
+        let mut depth = 0;
         while !self.is_end() {
-            if let Some(tok) = self.curr() {
-                if tok.tok_type == TokenType::SEMICOLON || tok.tok_type == TokenType::CURLY_CLOSE
-                {return; }
+            match self.peek().unwrap().tok_type {
+                TokenType::CURLY_OPEN => depth += 1,
+                TokenType::CURLY_CLOSE => if depth > 0 { depth -= 1 },
+                TokenType::K_PUB|TokenType::K_STRUCT|TokenType::K_IMPL|TokenType::K_TRAIT|TokenType::K_FUNC|TokenType::K_ENUM
+                    if depth == 0 => return,
+                _ => ()
             }
+            self.advance();
+        }
+    }
 
-            if let Some(tok) = self.peek() {
-                match tok.tok_type {
-                    TokenType::K_STRUCT|TokenType::K_IMPL|TokenType::K_TRAIT|TokenType::K_FUNC
-                    => { return; },
-                    _ => { self.advance(); () }
-                }
+    /* names of the structs, enums and traits of the file, known before parsing so that 
+       their uses can be qualified even when they come before the declaration */
+    fn collect_local_types(&mut self) {
+        let mut depth = 0;
+        for (i, tok) in self.tokens.iter().enumerate() {
+            match tok.tok_type {
+                TokenType::CURLY_OPEN => depth += 1,
+                TokenType::CURLY_CLOSE => if depth > 0 { depth -= 1 },
+                TokenType::K_STRUCT|TokenType::K_ENUM|TokenType::K_TRAIT if depth == 0 => {
+                    if let Some(name) = self.tokens.get(i + 1) {
+                        if name.tok_type == TokenType::IDENTIFIER {
+                            self.local_types.insert(name.value.clone());
+                        }
+                    }
+                },
+                _ => ()
             }
+        }
+    }
 
+    /* a struct, enum or trait of this file is known by its qualified name everywhere */
+    fn qualify_type(&self, mut tok: Token) -> Token {
+        if self.local_types.contains(&tok.value) {
+            tok.value = self.module.qualify(&tok.value);
+        }
+        tok
+    }
+
+    fn is_module_path(&self) -> bool {
+        if !self.check(TokenType::IDENTIFIER) { return false; }
+        let alias = self.peek().unwrap().value;
+        return self.module.imports.iter().any(|import| import.alias == alias) && 
+            match self.peek_next() {
+                Some(next) => next.tok_type == TokenType::COLON_COLON,
+                None => false
+            };
+    }
+
+    // modulePath -> IDENTIFIER "::" IDENTIFIER       (the first IDENTIFIER being an imported module)
+    fn module_path(&mut self) -> Option<Token> {
+        let alias = self.advance().unwrap();
+        self.advance(); // '::'
+        let name: Token;
+        match self.consume(TokenType::IDENTIFIER, 
+            format!("Expected the name of an item of module `{}` after '::'", alias.value)) {
+            Some(tok) => name = tok,
+            _ => return None,
+        }
+
+        let module = self.module.imports.iter()
+            .find(|import| import.alias == alias.value).unwrap().path.clone();
+        let tok = Token{
+            tok_type: TokenType::IDENTIFIER, 
+            value: format!("{}::{}", module, name.value), 
+            span: alias.span.to(name.span)
+        };
+        self.module.references.push(Reference{module, name: tok.clone()});
+        return Some(tok);
+    }
+
+    // typeName -> modulePath | IDENTIFIER
+    fn type_name(&mut self, error_msg: String) -> Option<Token> {
+        if self.is_module_path() {
+            return self.module_path();
+        }
+        match self.consume(TokenType::IDENTIFIER, error_msg) {
+            Some(tok) => return Some(self.qualify_type(tok)),
+            _ => return None,
+        }
+    }
+
+    // path -> IDENTIFIER ("::" IDENTIFIER)*
+    fn path(&mut self, error_msg: String) -> Option<(String, Span)> {
+        let mut segments = vec![];
+        let start = self.start_span();
+        loop {
+            match self.consume(TokenType::IDENTIFIER, error_msg.clone()) {
+                Some(tok) => segments.push(tok.value),
+                _ => return None,
+            }
+            if !self.match_(TokenType::COLON_COLON) { break; }
+        }
+        return Some((segments.join("::"), self.span_from(start)));
+    }
+
+    // moduleDecl -> "module" path ";"
+    fn module_declaration(&mut self) {
+        let start = self.current;
+        let path = self.path("Expected a module name after 'module'".to_string());
+        if self.consume(TokenType::SEMICOLON, "Expected ';' after module name".to_string()).is_some() {
+            self.module.header = path;
+        }
+        self.recover_header(start);
+    }
+
+    /* a broken `module` or `import` line is skipped up to its ';' so that the imports after it are still read */
+    fn recover_header(&mut self, start: usize) {
+        if self.panic_mode {
+            self.recover_statement(start);
+            self.panic_mode = false;
+        }
+    }
+
+    // importDecl -> "import" path ";"
+    fn import_declaration(&mut self) {
+        let start = self.current;
+        let (path, span) = match self.path("Expected a module name after 'import'".to_string()) {
+            Some(path) => path,
+            _ => return self.recover_header(start),
+        };
+        if self.consume(TokenType::SEMICOLON, "Expected ';' after imported module name".to_string()).is_none() {
+            return self.recover_header(start);
         }
 
+        let alias = path.rsplit("::").next().unwrap().to_string();
+        self.module.imports.push(Import{path, alias, span});
     }
 
-    // declaration -> structDecl | implDecl | traitDecl | funcDef
+    /* qualifies the name of a top level declaration and records it as an item of the module */
+    fn add_item(&mut self, decl: &mut Decl, is_pub: bool) {
+        let name = match decl {
+            Decl::StructDecl{name, ..} | Decl::EnumDecl{name, ..} | Decl::TraitDecl{name, ..}
+                | Decl::Prototype{name, ..} => name,
+            Decl::FuncDef{prototype, ..} => return self.add_item(prototype, is_pub),
+            Decl::Template{decl, ..} => return self.add_item(decl, is_pub),
+            Decl::ImplDecl{..} => return
+        };
+        name.value = self.module.qualify(&name.value);
+        self.module.items.insert(name.value.clone(), Item{is_pub, span: name.span});
+    }
+
+    // declaration -> structDecl | implDecl | traitDecl | funcDef | enumDecl
     fn declaration(&mut self) -> Option<Box<Decl>> {
         eprintln!("In declaration()");
         if      self.match_(TokenType::K_STRUCT)    { return self.struct_declaration(); }
+        else if self.match_(TokenType::K_ENUM)      { return self.enum_declaration(); }
         else if self.match_(TokenType::K_IMPL)      { return self.impl_declaration(); }
         else if self.match_(TokenType::K_TRAIT)     { return self.trait_declaration(); }
         else if self.match_(TokenType::K_FUNC)      { return self.func(); }
-        else { self.synchronize(); return None; }
+        else if self.check(TokenType::K_MODULE) || self.check(TokenType::K_IMPORT) {
+            let span = self.start_span();
+            self.error(span, "'module' and 'import' go at the top of the file, before any declaration".to_string());
+            return None;
+        }
+        else { 
+            let span = self.start_span();
+            self.error(span, "Expected a declaration: struct, enum, impl, trait or func".to_string());
+            return None; 
+        }
     }
 
     // structDecl -> "struct" IDENTIFIER "{" (IDENTIFIER ("," IDENTIFIER)*) ":" TYPES "}"
     fn struct_declaration(&mut self) -> Option<Box<Decl>>   { 
         eprintln!("In struct_declaration()");
+        let start = self.previous_span();
         let name: Token;
         match self.consume(TokenType::IDENTIFIER, 
             "Expected identifier after 'struct' keyword".to_string()) {
@@ -175,6 +440,13 @@ impl Parser {
             _ => return None,
         };
 
+        let mut type_params = vec![];
+        if self.match_(TokenType::LESS_THAN) {
+            match self.type_parameters() {
+                Some(params) => type_params = params,
+                _ => return None,
+            }
+        }
 
         match self.consume(TokenType::CURLY_OPEN, "Expected '{' after struct identifier.".to_string()) {
             None => return None,
@@ -182,9 +454,9 @@ impl Parser {
         };
 
 
-        let mut fields: Vec<(Token, Token)> = Vec::new();
+        let mut fields: Vec<(Token, Datatype)> = Vec::new();
         while !self.match_(TokenType::CURLY_CLOSE) {
-            let field_name : Token; let field_type: Token;
+            let field_name : Token; let field_type: Datatype;
         
             match self.consume(TokenType::IDENTIFIER,
                 "Expected field name inside struct declaration".to_string()) {
@@ -200,9 +472,8 @@ impl Parser {
             };
 
             
-            match self.consume_multi(TokenType::get_datatypes(), 
-            "Expected datatype for the field".to_string()) {
-                Some(tok) => field_type = tok,
+            match self.datatype("Expected datatype for the field".to_string()) {
+                Some(dtype) => field_type = dtype,
                 _ => return None,
             }
 
@@ -215,8 +486,7 @@ impl Parser {
             if !self.match_(TokenType::COMMA) {
                 if let Some(peek) = self.peek() {
                     if peek.tok_type != TokenType::CURLY_CLOSE {
-                        self.has_errors = true;
-                        logger::log_message(logger::LogLevel::ERROR, peek.col, peek.line, 
+                        self.error(peek.span, 
                             "Expected ',' or '}' after field declaration".to_string());
                         return None;
                     }
@@ -225,16 +495,102 @@ impl Parser {
 
         }
 
-        return Some(Box::new(Decl::StructDecl{name, fields}));
+        let span = self.span_from(start);
+        if !type_params.is_empty() {
+            return Some(Box::new(Decl::Template{type_params, decl: Box::new(Decl::StructDecl{name, fields, span}), span}));
+        }
+        return Some(Box::new(Decl::StructDecl{name, fields, span}));
+    }
+
+    // enumDecl -> "enum" IDENTIFIER "{" (variant ("," variant)* ","?)? "}"
+    // variant  -> IDENTIFIER ( "(" type_list ")" | "{" IDENTIFIER ":" DATATYPE ("," IDENTIFIER ":" DATATYPE)* "}" )?
+    fn enum_declaration(&mut self) -> Option<Box<Decl>> {
+        let start = self.previous_span();
+        let name: Token;
+        match self.consume(TokenType::IDENTIFIER, 
+            "Expected identifier after 'enum' keyword".to_string()) {
+            Some(tok) => name = tok,
+            _ => return None,
+        }
+
+        match self.consume(TokenType::CURLY_OPEN, "Expected '{' after enum identifier".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+
+        let mut variants: Vec<(Token, VariantFields)> = vec![];
+        while !self.match_(TokenType::CURLY_CLOSE) {
+            let variant_name: Token;
+            match self.consume(TokenType::IDENTIFIER, "Expected variant name inside enum declaration".to_string()) {
+                Some(tok) => variant_name = tok,
+                _ => return None,
+            }
+
+            let mut fields = VariantFields::Unit;
+            if self.match_(TokenType::BRACKET_OPEN) {
+                let mut types = vec![];
+                while !self.match_(TokenType::BRACKET_CLOSE) {
+                    match self.datatype("Expected datatype of variant field".to_string()) {
+                        Some(dtype) => types.push(dtype),
+                        _ => return None,
+                    }
+                    if !self.match_(TokenType::COMMA) && !self.check(TokenType::BRACKET_CLOSE) {
+                        if let Some(peek) = self.peek() {
+                            self.error(peek.span, 
+                                "Expected ',' or ')' after variant field type".to_string());
+                        }
+                        return None;
+                    }
+                }
+                fields = VariantFields::Tuple(types);
+            } else if self.match_(TokenType::CURLY_OPEN) {
+                let mut named_fields = vec![];
+                while !self.match_(TokenType::CURLY_CLOSE) {
+                    let field_name: Token;
+                    match self.consume(TokenType::IDENTIFIER, "Expected field name of variant".to_string()) {
+                        Some(tok) => field_name = tok,
+                        _ => return None,
+                    }
+                    match self.consume(TokenType::COLON, "Expected ':' after field name of variant".to_string()) {
+                        Some(_) => (),
+                        _ => return None,
+                    }
+                    match self.datatype("Expected datatype of variant field".to_string()) {
+                        Some(dtype) => named_fields.push((field_name, dtype)),
+                        _ => return None,
+                    }
+                    if !self.match_(TokenType::COMMA) && !self.check(TokenType::CURLY_CLOSE) {
+                        if let Some(peek) = self.peek() {
+                            self.error(peek.span, 
+                                "Expected ',' or '}' after variant field".to_string());
+                        }
+                        return None;
+                    }
+                }
+                fields = VariantFields::Struct(named_fields);
+            }
+
+            variants.push((variant_name, fields));
+
+            if !self.match_(TokenType::COMMA) && !self.check(TokenType::CURLY_CLOSE) {
+                if let Some(peek) = self.peek() {
+                    self.error(peek.span, 
+                        "Expected ',' or '}' after enum variant".to_string());
+                }
+                return None;
+            }
+        }
+
+        return Some(Box::new(Decl::EnumDecl{name, variants, span: self.span_from(start)}));
     }
 
     fn impl_declaration(&mut self) -> Option<Box<Decl>>     { 
         eprintln!("In impl_declaration()");
+        let start = self.previous_span();
         let mut name: Token;
         let trait_name: Option<Token>;
 
-        match self.consume(TokenType::IDENTIFIER, 
-            "Expected struct/trait name after 'impl' keyword".to_string()) {
+        match self.type_name("Expected struct/trait name after 'impl' keyword".to_string()) {
                 Some(tok) => name = tok,
                 _ => return None,
         }
@@ -243,8 +599,7 @@ impl Parser {
             trait_name = Some(name);
             
 
-            match self.consume(TokenType::IDENTIFIER, 
-                "Expected struct name for which trait is being implemented".to_string()) {
+            match self.type_name("Expected struct name for which trait is being implemented".to_string()) {
                     Some(tok) => name = tok,
                     _ => return None
             }
@@ -269,19 +624,19 @@ impl Parser {
                         
                             match *decl {
                         
-                                Decl::FuncDef{prototype, block} 
-                                    => funcs.push(Box::new(Decl::FuncDef{prototype, block})),
+                                Decl::FuncDef{prototype, block, span} 
+                                    => funcs.push(Box::new(Decl::FuncDef{prototype, block, span})),
                         
                                 _ => {
-                                    self.has_errors = true;
-                                    log_message(logger::LogLevel::ERROR, 
-                                        self.tokens[self.current].col, self.tokens[self.current].line, 
+                                    self.error(self.tokens[self.current].span, 
                                         "Expected function definition inside impl declaration".to_string());
                                     return None;
                                 }
                     
                             }
                     
+                        } else {
+                            self.recover_method();
                         }
 
                     },
@@ -290,7 +645,7 @@ impl Parser {
             }
         }
 
-        return Some(Box::new(Decl::ImplDecl{name, trait_name, funcs}));
+        return Some(Box::new(Decl::ImplDecl{name, trait_name, funcs, span: self.span_from(start)}));
 
     }
 
@@ -298,6 +653,7 @@ impl Parser {
 
     fn trait_declaration(&mut self) -> Option<Box<Decl>>    { 
         eprintln!("In trait_declaration()");
+        let start = self.previous_span();
         let name: Token;
         match self.consume(TokenType::IDENTIFIER, 
             "Expected trait name after 'trait' keyword".to_string()) {
@@ -320,7 +676,7 @@ impl Parser {
 
                         match self.func() {
                             Some(decl) => funcs.push(decl),
-                            _ => continue,
+                            _ => self.recover_method(),
                         }
 
                     },
@@ -329,7 +685,7 @@ impl Parser {
             }
         }
 
-        return Some(Box::new(Decl::TraitDecl{name, funcs}));
+        return Some(Box::new(Decl::TraitDecl{name, funcs, span: self.span_from(start)}));
 
 
     }
@@ -337,6 +693,7 @@ impl Parser {
     // func -> prototype (";" | block)
     fn func(&mut self) -> Option<Box<Decl>>  { 
         eprintln!("In block()");
+        let start = self.previous_span();
         let prototype: Box<Decl>;
         match self.prototype() {
             Some(proto) => prototype = proto,
@@ -349,11 +706,16 @@ impl Parser {
             None => return Some(prototype),
         }
 
-        return Some(Box::new(Decl::FuncDef{prototype, block}));
+        let span = self.span_from(start);
+        if let Decl::Template{type_params, decl, ..} = *prototype {
+            return Some(Box::new(Decl::Template{type_params, decl: Box::new(Decl::FuncDef{prototype: decl, block, span}), span}));
+        }
+        return Some(Box::new(Decl::FuncDef{prototype, block, span}));
     }
 
     fn prototype(&mut self) -> Option<Box<Decl>> { 
         eprintln!("In prototype()");
+        let start = self.previous_span();
         let name: Token;
         match self.consume(TokenType::IDENTIFIER, 
             "Expected function name after 'func' keyword".to_string()) {
@@ -361,19 +723,69 @@ impl Parser {
                 _ => return None, 
         }
 
+        let mut type_params = vec![];
+        if self.match_(TokenType::LESS_THAN) {
+            match self.type_parameters() {
+                Some(params) => type_params = params,
+                _ => return None,
+            }
+        }
+
         match self.consume(TokenType::BRACKET_OPEN, 
             "Expected '(' after function name".to_string()) {
             Some(_) => (),
             _ => return None,
         }
 
-        let mut params: Vec<(Token, Token)> = Vec::new();
+        let params: Vec<(Token, Datatype)>;
+        match self.parameters() {
+            Some(parameters) => params = parameters,
+            _ => return None,
+        }
+
+        // reading return types
+        // let mut ret_types: Vec<Token> = vec![];
+        let /*mut*/ returntype: Datatype;// = Token { tok_type: TokenType::ERROR, value: String::new(), line: usize::MAX, col: usize::MAX };
+        if self.match_(TokenType::RIGHT_ARROW) {
+            
+            match self.datatype("Expected a return type".to_string()) {
+                Some(dtype) => returntype = dtype,//ret_types.push(tok),
+                _ => return None,
+            }
+
+            let span = self.span_from(start);
+            let prototype = Box::new(Decl::Prototype{name, parameters: params, returntype/*ret_types*/, span});
+            if !type_params.is_empty() {
+                return Some(Box::new(Decl::Template{type_params, decl: prototype, span}));
+            }
+            return Some(prototype);
+
+            // while self.match_(TokenType::COMMA) {
+            //     match self.consume_multi(TokenType::get_datatypes(), 
+            //     "Expected a return type".to_string()) {
+            //         Some(tok) => ret_types.push(tok),
+            //         _ => return None,
+            //     }
+            // }
+
+        } else {
+            // For now generating error. In future, should default to '()' type.
+            self.error(name.span, 
+                "A return type needs to be provided in prototype of function".to_string());
+            // self.synchronize();
+            return None;
+        }
+    }
+
+    // parameters -> (IDENTIFIER ("," IDENTIFIER)* ":" DATATYPE ","?)* ")"
+    fn parameters(&mut self) -> Option<Vec<(Token, Datatype)>> {
+        let mut params: Vec<(Token, Datatype)> = Vec::new();
 
         // reading params
         while !self.match_(TokenType::BRACKET_CLOSE) {
 
             let mut param_names: Vec<Token> = Vec::new();
-            let params_type: Token;
+            let params_type: Datatype;
             match self.consume(TokenType::IDENTIFIER, 
                 "Expected parameter name".to_string()) {
                     Some(tok) => param_names.push(tok),
@@ -394,9 +806,8 @@ impl Parser {
             }
 
             
-            match self.consume_multi(TokenType::get_datatypes(), 
-            "Expected typename for parameters".to_string()) {
-                Some(tok) => params_type = tok,
+            match self.datatype("Expected typename for parameters".to_string()) {
+                Some(dtype) => params_type = dtype,
                 _ => return None,
             }
 
@@ -407,57 +818,268 @@ impl Parser {
             if self.match_(TokenType::COMMA) {}
         }
 
-        // reading return types
-        // let mut ret_types: Vec<Token> = vec![];
-        let /*mut*/ returntype: Token;// = Token { tok_type: TokenType::ERROR, value: String::new(), line: usize::MAX, col: usize::MAX };
-        if self.match_(TokenType::RIGHT_ARROW) {
-            
-            match self.consume_multi(TokenType::get_datatypes(), 
-            "Expected a return type".to_string()) {
-                Some(tok) => returntype = tok,//ret_types.push(tok),
+        return Some(params);
+    }
+
+    // type -> TYPES | "[" type ";" INT_LITERAL "]"
+    fn datatype(&mut self, error_msg: String) -> Option<Datatype> {
+        if self.match_(TokenType::SQUARE_OPEN) {
+            let bracket_token = self.curr().unwrap();
+            let elem_type: Datatype;
+            match self.datatype("Expected element type of array".to_string()) {
+                Some(dtype) => elem_type = dtype,
                 _ => return None,
             }
 
-            return Some(Box::new(Decl::Prototype{name, parameters: params, returntype/*ret_types*/}));
+            match self.consume(TokenType::SEMICOLON, 
+                "Expected ';' after element type of array".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
 
-            // while self.match_(TokenType::COMMA) {
-            //     match self.consume_multi(TokenType::get_datatypes(), 
-            //     "Expected a return type".to_string()) {
-            //         Some(tok) => ret_types.push(tok),
-            //         _ => return None,
-            //     }
-            // }
+            let size: u64;
+            match self.consume(TokenType::INT_LITERAL, "Expected array size".to_string()) {
+                Some(tok) => match tok.value.parse::<u64>() {
+                    Ok(val) => size = val,
+                    Err(_) => {
+                        self.error(tok.span, 
+                            "Array size is not a valid unsigned integer".to_string());
+                        return None;
+                    }
+                },
+                _ => return None,
+            }
 
+            if size == 0 {
+                self.error(bracket_token.span, 
+                    "Array size should be greater than zero".to_string());
+                return None;
+            }
+
+            match self.consume(TokenType::SQUARE_CLOSE, 
+                "Expected ']' after array size".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+
+            return Some(Datatype::array{elem_type: Box::new(elem_type), size});
+        }
+
+        if self.match_(TokenType::K_FUNC) {
+            match self.consume(TokenType::BRACKET_OPEN, "Expected '(' after 'func' in function type".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+
+            let mut param_types = vec![];
+            while !self.match_(TokenType::BRACKET_CLOSE) {
+                match self.datatype("Expected parameter type of function type".to_string()) {
+                    Some(dtype) => param_types.push(dtype),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) && !self.check(TokenType::BRACKET_CLOSE) {
+                    if let Some(peek) = self.peek() {
+                        self.error(peek.span, 
+                            "Expected ',' or ')' after parameter type".to_string());
+                    }
+                    return None;
+                }
+            }
+
+            match self.consume(TokenType::RIGHT_ARROW, "Expected '->' and a return type in function type".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+            match self.datatype("Expected a return type".to_string()) {
+                Some(returntype) => return Some(Datatype::function_value(returntype, param_types)),
+                _ => return None,
+            }
+        }
+
+        if self.match_(TokenType::BRACKET_OPEN) {
+            if self.match_(TokenType::BRACKET_CLOSE) {
+                return Some(Datatype::unit());
+            }
+            let mut elem_types = vec![];
+            loop {
+                match self.datatype("Expected a type in tuple type".to_string()) {
+                    Some(dtype) => elem_types.push(dtype),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) { break; }
+            }
+            match self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after types of tuple".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+            if elem_types.len() == 1 {
+                return elem_types.pop();
+            }
+            return Some(Datatype::tuple{elem_types});
+        }
+
+        if self.match_(TokenType::K_CHAN) {
+            match self.consume(TokenType::LESS_THAN, "Expected '<' after 'chan'".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+            let elem_type: Datatype;
+            match self.datatype("Expected element type of channel".to_string()) {
+                Some(dtype) => elem_type = dtype,
+                _ => return None,
+            }
+            match self.consume_closing_angle("Expected '>' after element type of channel".to_string()) {
+                Some(_) => return Some(Datatype::channel{elem_type: Box::new(elem_type)}),
+                _ => return None,
+            }
+        }
+
+        if self.match_(TokenType::K_RC) {
+            match self.consume(TokenType::LESS_THAN, "Expected '<' after 'rc'".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+            let elem_type: Datatype;
+            match self.datatype("Expected the type an rc points to".to_string()) {
+                Some(dtype) => elem_type = dtype,
+                _ => return None,
+            }
+            match self.consume_closing_angle("Expected '>' after the type an rc points to".to_string()) {
+                Some(_) => return Some(Datatype::rc{elem_type: Box::new(elem_type)}),
+                _ => return None,
+            }
+        }
+
+        if self.match_(TokenType::BITWISE_AND) {
+            let mutable = self.match_(TokenType::K_MUT);
+            match self.datatype("Expected the type a reference points to after '&'".to_string()) {
+                Some(dtype) => return Some(Datatype::reference{elem_type: Box::new(dtype), mutable}),
+                _ => return None,
+            }
+        }
+
+        if self.match_(TokenType::K_DYN) {
+            match self.type_name("Expected a trait name after 'dyn'".to_string()) {
+                Some(tok) => return Some(Datatype::dyn_trait{name: tok.value}),
+                _ => return None,
+            }
+        }
+
+        let tok: Token;
+        if self.check(TokenType::IDENTIFIER) {
+            match self.type_name(error_msg) {
+                Some(t) => tok = t,
+                _ => return None,
+            }
         } else {
-            // For now generating error. In future, should default to '()' type.
-            self.has_errors = true;
-            log_message(logger::LogLevel::ERROR, name.col, name.line, 
-                "A return type needs to be provided in prototype of function".to_string());
-            // self.synchronize();
-            return None;
+            match self.consume_multi(TokenType::get_datatypes(), error_msg) {
+                Some(t) => tok = t,
+                _ => return None,
+            }
+        }
+
+        if tok.tok_type == TokenType::IDENTIFIER && self.match_(TokenType::LESS_THAN) {
+            let mut type_args = vec![];
+            loop {
+                match self.datatype("Expected a type argument".to_string()) {
+                    Some(dtype) => type_args.push(dtype),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) { break; }
+            }
+
+            match self.consume_closing_angle("Expected '>' after type arguments".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+            return Some(Datatype::generic{name: tok.value, type_args});
+        }
+
+        return Some(Datatype::get_tok_datatype(&tok));
+    }
+
+    // typeParams -> "<" IDENTIFIER (":" IDENTIFIER)? ("," IDENTIFIER (":" IDENTIFIER)?)* ">"
+    fn type_parameters(&mut self) -> Option<Vec<(Token, Option<Token>)>> {
+        let mut type_params = vec![];
+        loop {
+            let name: Token;
+            match self.consume(TokenType::IDENTIFIER, "Expected type parameter name".to_string()) {
+                Some(tok) => name = tok,
+                _ => return None,
+            }
+
+            let mut bound = None;
+            if self.match_(TokenType::COLON) {
+                match self.type_name("Expected a trait name as bound of type parameter".to_string()) {
+                    Some(tok) => bound = Some(tok),
+                    _ => return None,
+                }
+            }
+
+            type_params.push((name, bound));
+            if !self.match_(TokenType::COMMA) { break; }
         }
 
+        match self.consume(TokenType::GREAT_THAN, "Expected '>' after type parameters".to_string()) {
+            Some(_) => return Some(type_params),
+            _ => return None,
+        }
+    }
 
-        //return Some(Box::new(Decl::Prototype{name, parameters: params, returntype/*ret_types*/}));
+    fn consume_closing_angle(&mut self, error_msg: String) -> Option<Token> {
+        if self.check(TokenType::RIGHT_SHIFT) {
+            // `>>` closes two nested type argument lists. Leave the second '>' for the outer list.
+            let mut tok = self.peek().unwrap();
+            let mut second = tok.span;
+            second.start.offset += 1;
+            second.start.col += 1;
+            self.tokens[self.current] = Token{
+                tok_type: TokenType::GREAT_THAN, 
+                value: ">".to_string(), 
+                span: second
+            };
+            tok.span.end = second.start;
+            return Some(tok);
+        }
+        return self.consume(TokenType::GREAT_THAN, error_msg);
     }
 
     fn block(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In block()");
+        let start = self.start_span();
+        let curly_token: Token;
         match self.consume(TokenType::CURLY_OPEN, 
             "Expected '{' at starting of a block".to_string()) {
-                Some(_) => (),
+                Some(tok) => curly_token = tok,
                 _ => return None
         }
 
         let mut statements: Vec<Box<Stmt>> = vec![];
-        while !self.match_(TokenType::CURLY_CLOSE) {
-            match self.statement() {
+        while !self.check(TokenType::CURLY_CLOSE) && !self.is_end() {
+            let stmt_start = self.start_span();
+            let before = self.current;
+            let restrictions = self.restrictions.len();
+            let stmt = self.statement();
+
+            if !self.panic_mode {
+                if let Some(stmt) = stmt { statements.push(stmt); }
+                continue;
+            }
+            self.recover_statement(before);
+            self.restrictions.truncate(restrictions);
+            self.panic_mode = false;
+            match stmt {
                 Some(stmt) => statements.push(stmt),
-                _ => continue,
+                _ => statements.push(Box::new(Stmt::Error{span: self.span_from(stmt_start)}))
             }
         }
+        if self.is_end() {
+            self.error(curly_token.span, "This block is never closed, expected '}' before the end of the file".to_string());
+        } else {
+            self.advance();
+        }
 
-        return Some(Box::new(Stmt::Block{statements}));
+        return Some(Box::new(Stmt::Block{statements, span: self.span_from(start)}));
     }
 
     fn statement(&mut self) -> Option<Box<Stmt>> { 
@@ -469,9 +1091,20 @@ impl Parser {
                     TokenType::K_FOR    => self.for_stmt(),
                     TokenType::K_RETURN => self.return_stmt(),
                     TokenType::K_WHILE  => self.while_stmt(),
+                    TokenType::K_LOOP   => self.loop_stmt(),
                     TokenType::K_VAR    => self.var_stmt(),
-                    TokenType::K_STRUCT|TokenType::K_IMPL|
-                    TokenType::K_TRAIT|TokenType::K_FUNC => self.decl_stmt(),
+                    TokenType::K_BREAK  => self.break_stmt(),
+                    TokenType::K_CONTINUE   => self.continue_stmt(),
+                    TokenType::K_SPAWN  => self.spawn_stmt(),
+                    TokenType::K_DELETE => self.delete_stmt(),
+                    TokenType::K_SELECT => self.select_stmt(),
+                    TokenType::K_STRUCT|TokenType::K_IMPL|TokenType::K_ENUM|
+                    TokenType::K_TRAIT => self.decl_stmt(),
+                    // `func(` starts a closure expression
+                    TokenType::K_FUNC   => match self.peek_next() {
+                        Some(Token{tok_type: TokenType::BRACKET_OPEN, ..}) => self.expr_stmt(),
+                        _ => self.decl_stmt()
+                    },
                     TokenType::SEMICOLON    => {self.match_(TokenType::SEMICOLON); None},
                     TokenType::FILE_EOF     => {eprintln!("Peek:{:?}", self.peek());self.advance(); None},
                     _ => self.expr_stmt()
@@ -484,13 +1117,17 @@ impl Parser {
     fn decl_stmt(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In decl_stmt()");
         match self.declaration() {
-            Some(decl) => Some(Box::new(Stmt::Decl{decl})),
+            Some(decl) => {
+                let span = decl.span();
+                Some(Box::new(Stmt::Decl{decl, span}))
+            },
             _ => None,
         }
     }
 
     fn var_stmt(&mut self) -> Option<Box<Stmt>> {
         eprintln!("In var_stmt()");
+        let start = self.start_span();
         self.consume(TokenType::K_VAR, "Expected 'var' keyword".to_string());
 
         let name: Token;
@@ -500,31 +1137,71 @@ impl Parser {
             _ => return None
         }
 
+        if self.check(TokenType::COMMA) {
+            return self.destructure_stmt(start, name);
+        }
+
 
-        let mut typename: Option<Token> = None;
+        let mut typename: Option<Datatype> = None;
         if self.match_(TokenType::COLON) {
-            typename = self.consume_multi(TokenType::get_datatypes(), "Expected type name".to_string());
+            typename = self.datatype("Expected type name".to_string());
         }
 
         let mut initialization_value: Option<Box<Expr>> = None;
         if self.match_(TokenType::EQUAL) {
             // initialization_value = self.expression();
-            initialization_value = self.logical_OR_expr();
+            initialization_value = match self.logical_OR_expr() {
+                Some(expr) => Some(expr),
+                _ => Some(self.missing_expr("Expected an initialization value after '='".to_string()))
+            };
         }
 
         if typename.is_none() && initialization_value.is_none() {
-            self.has_errors = true;
-            log_message(logger::LogLevel::ERROR, 
-                name.col, name.line, "A variable declaration requires either a datatype or an initialization value".to_string());
+            self.error(name.span, "A variable declaration requires either a datatype or an initialization value".to_string());
             return None;
         }
 
         self.consume(TokenType::SEMICOLON, "Expected ';' after variable declaration".to_string());
-        return Some(Box::new(Stmt::Var{name, datatype: typename, initialization_value}));
+        return Some(Box::new(Stmt::Var{name, datatype: typename, initialization_value, span: self.span_from(start)}));
+    }
+
+    // "var" IDENTIFIER ("," IDENTIFIER)+ "=" logORexpr ("," logORexpr)* ";"
+    fn destructure_stmt(&mut self, start: Span, first_name: Token) -> Option<Box<Stmt>> {
+        let mut names = vec![first_name];
+        while self.match_(TokenType::COMMA) {
+            match self.consume(TokenType::IDENTIFIER, "Expected variable name after ','".to_string()) {
+                Some(tok) => names.push(tok),
+                _ => return None
+            }
+        }
+
+        match self.consume(TokenType::EQUAL, 
+            "Expected '=' and values after the names of variables being declared together".to_string()) {
+            Some(_) => (),
+            _ => return None
+        }
+        let mut values = vec![];
+        loop {
+            match self.logical_OR_expr() {
+                Some(expr) => values.push(expr),
+                _ => return None
+            }
+            if !self.match_(TokenType::COMMA) { break; }
+        }
+        let initialization_value = if values.len() == 1 {
+            values.pop().unwrap()
+        } else {
+            let span = Parser::list_span(&values);
+            Box::new(Expr::ExprList{expr_list: values, datatype: Datatype::yet_to_infer, span})
+        };
+
+        self.consume(TokenType::SEMICOLON, "Expected ';' after variable declaration".to_string());
+        return Some(Box::new(Stmt::Destructure{names, initialization_value, span: self.span_from(start)}));
     }
 
     fn if_stmt(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In if_stmt()");
+        let start = self.start_span();
         let if_token:Token;
         match self.consume(TokenType::K_IF, "Expected `if` statement".to_string()) {
             Some(tok) => if_token = tok,
@@ -535,7 +1212,7 @@ impl Parser {
         self.restrictions.push(Restriction::STRUCT_EXPR);
         match self.expression() {
             Some(expr) => condition = expr,
-            _ => return None,
+            _ => condition = self.missing_expr("Expected a condition after 'if'".to_string()),
         }
         self.restrictions.pop();
 
@@ -555,19 +1232,26 @@ impl Parser {
             else_block = None;
         }
 
-        return Some(Box::new(Stmt::If{if_token, condition, then_block, else_block}));
+        return Some(Box::new(Stmt::If{if_token, condition, then_block, else_block, span: self.span_from(start)}));
 
     }
 
     fn while_stmt(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In while_stmt()");
-        self.consume(TokenType::K_WHILE, "Expected 'while' keyword".to_string());
+        let start = self.start_span();
+        let while_token: Token;
+        match self.consume(TokenType::K_WHILE, "Expected 'while' keyword".to_string()) {
+            Some(tok) => while_token = tok,
+            _ => return None
+        }
 
         let condition: Box<Expr>;
+        self.restrictions.push(Restriction::STRUCT_EXPR);
         match self.expression() {
             Some(expr) => condition = expr,
-            _ => return None,
+            _ => condition = self.missing_expr("Expected a condition after 'while'".to_string()),
         }
+        self.restrictions.pop();
 
         let block: Box<Stmt>;
         match self.block() {
@@ -575,12 +1259,45 @@ impl Parser {
             _ => return None
         }
 
-        return Some(Box::new(Stmt::While{condition, block}));
+        return Some(Box::new(Stmt::While{while_token, condition, block, span: self.span_from(start)}));
+    }
+
+    // loopStmt -> "loop" expression? block
+    fn loop_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let loop_token: Token;
+        match self.consume(TokenType::K_LOOP, "Expected 'loop' keyword".to_string()) {
+            Some(tok) => loop_token = tok,
+            _ => return None
+        }
+
+        // `loop { }` runs forever, `loop n { }` runs the block n times.
+        let mut count: Option<Box<Expr>> = None;
+        if !self.check(TokenType::CURLY_OPEN) {
+            self.restrictions.push(Restriction::STRUCT_EXPR);
+            match self.expression() {
+                Some(expr) => count = Some(expr),
+                _ => {
+                    self.restrictions.pop();
+                    return None;
+                }
+            }
+            self.restrictions.pop();
+        }
+
+        let block: Box<Stmt>;
+        match self.block() {
+            Some(blk) => block = blk,
+            _ => return None
+        }
+
+        return Some(Box::new(Stmt::Loop{loop_token, count, block, span: self.span_from(start)}));
     }
 
     
     fn for_stmt(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In for stmt()");
+        let start = self.start_span();
         let for_token: Token;
         match self.consume(TokenType::K_FOR, "Expected 'for' keyword".to_string()) {
             Some(tok) => for_token = tok,
@@ -595,7 +1312,8 @@ impl Parser {
             _ => {
                 self.restrictions.pop();
                 if let Some(blk) = self.block() {
-                    return Some(Box::new(Stmt::For{for_token, initialization:None, condition: None, updation: None, block: blk}));
+                    return Some(Box::new(Stmt::For{for_token, initialization:None, condition: None, updation: None, block: blk, 
+                        span: self.span_from(start)}));
                 } 
                 return None;
             }
@@ -628,7 +1346,8 @@ impl Parser {
                                         initialization:Some(initialization),
                                         condition: if has_condition { Some(condition) } else {None},
                                         updation: Some(updation),
-                                        block: blk
+                                        block: blk,
+                                        span: self.span_from(start)
                                     }
                                 )
                             );
@@ -638,8 +1357,7 @@ impl Parser {
                         
                     },
                     _ => {
-                        self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, self.tokens[self.current].col, self.tokens[self.current].line, 
+                        self.error(self.tokens[self.current].span, 
                             "Expected expression after second ';' in `for` loop".to_string());
                         // self.synchronize();
                         return None;
@@ -647,29 +1365,188 @@ impl Parser {
                 }
             }
         }
-        self.restrictions.pop();
-        if let Some(blk) = self.block() {
-            return Some(Box::new(Stmt::For{
-                for_token,
-                initialization: None,
-                condition: Some(condition),
-                updation: None,
-                block: blk
-            }));
-        }
-        return None;    
+        self.restrictions.pop();
+        if let Some(blk) = self.block() {
+            return Some(Box::new(Stmt::For{
+                for_token,
+                initialization: None,
+                condition: Some(condition),
+                updation: None,
+                block: blk,
+                span: self.span_from(start)
+            }));
+        }
+        return None;    
+    }
+
+
+    fn break_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let break_token: Token;
+        match self.consume(TokenType::K_BREAK, "Expected 'break' keyword".to_string()) {
+            Some(tok) => break_token = tok,
+            _ => return None
+        }
+
+        self.consume(TokenType::SEMICOLON, "Expected ';' after 'break'".to_string());
+        return Some(Box::new(Stmt::Break{break_token, span: self.span_from(start)}));
+    }
+
+    fn continue_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let continue_token: Token;
+        match self.consume(TokenType::K_CONTINUE, "Expected 'continue' keyword".to_string()) {
+            Some(tok) => continue_token = tok,
+            _ => return None
+        }
+
+        self.consume(TokenType::SEMICOLON, "Expected ';' after 'continue'".to_string());
+        return Some(Box::new(Stmt::Continue{continue_token, span: self.span_from(start)}));
+    }
+
+
+    // spawnStmt -> "spawn" call ";"
+    fn spawn_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let spawn_token: Token;
+        match self.consume(TokenType::K_SPAWN, "Expected 'spawn' keyword".to_string()) {
+            Some(tok) => spawn_token = tok,
+            _ => return None
+        }
+
+        let call: Box<Expr>;
+        match self.logical_OR_expr() {
+            Some(expr) => match *expr {
+                Expr::Call{..} => call = expr,
+                _ => {
+                    self.error(spawn_token.span, 
+                        "Expected a function call after 'spawn'".to_string());
+                    return None;
+                }
+            },
+            _ => return None
+        }
+
+        self.consume(TokenType::SEMICOLON, "Expected ';' after spawned call".to_string());
+        return Some(Box::new(Stmt::Spawn{spawn_token, call, span: self.span_from(start)}));
+    }
+
+    // deleteStmt -> "delete" logORexpr ";"
+    fn delete_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let delete_token: Token;
+        match self.consume(TokenType::K_DELETE, "Expected 'delete' keyword".to_string()) {
+            Some(tok) => delete_token = tok,
+            _ => return None
+        }
+
+        let expr: Box<Expr>;
+        match self.logical_OR_expr() {
+            Some(e) => expr = e,
+            _ => {
+                self.error(delete_token.span, 
+                    "Expected a reference to delete after 'delete'".to_string());
+                return None;
+            }
+        }
+
+        self.consume(TokenType::SEMICOLON, "Expected ';' after deleted reference".to_string());
+        return Some(Box::new(Stmt::Delete{delete_token, expr, span: self.span_from(start)}));
+    }
+
+    // selectStmt -> "select" "{" (("case" selectOp | "default") ":" statement*)* "}"
+    fn select_stmt(&mut self) -> Option<Box<Stmt>> {
+        let start = self.start_span();
+        let select_token: Token;
+        match self.consume(TokenType::K_SELECT, "Expected 'select' keyword".to_string()) {
+            Some(tok) => select_token = tok,
+            _ => return None
+        }
+        match self.consume(TokenType::CURLY_OPEN, "Expected '{' after 'select'".to_string()) {
+            Some(_) => (),
+            _ => return None
+        }
+
+        let mut cases = vec![];
+        while !self.match_(TokenType::CURLY_CLOSE) {
+            if self.is_end() {
+                self.error(select_token.span, 
+                    "Expected '}' at the end of 'select'".to_string());
+                return None;
+            }
+
+            let case_start = self.start_span();
+            let case: SelectCase;
+            if self.match_(TokenType::K_DEFAULT) {
+                case = SelectCase::Default{default_token: self.curr().unwrap()};
+            } else {
+                match self.consume(TokenType::K_CASE, "Expected 'case' or 'default' in 'select'".to_string()) {
+                    Some(_) => (),
+                    _ => return None
+                }
+                match self.select_op() {
+                    Some(op) => case = op,
+                    _ => return None
+                }
+            }
+            match self.consume(TokenType::COLON, "Expected ':' after case of 'select'".to_string()) {
+                Some(_) => (),
+                _ => return None
+            }
+
+            // a case runs until the next one
+            let mut statements: Vec<Box<Stmt>> = vec![];
+            while !self.check(TokenType::K_CASE) && !self.check(TokenType::K_DEFAULT) 
+                && !self.check(TokenType::CURLY_CLOSE) && !self.is_end() {
+                match self.statement() {
+                    Some(stmt) => statements.push(stmt),
+                    _ => continue,
+                }
+            }
+            cases.push((case, Box::new(Stmt::Block{statements, span: self.span_from(case_start)})));
+        }
+
+        return Some(Box::new(Stmt::Select{select_token, cases, span: self.span_from(start)}));
     }
 
+    // selectOp -> (target "=")? "<-" logORexpr | logORexpr "<-" logORexpr
+    fn select_op(&mut self) -> Option<SelectCase> {
+        let start_token = self.peek().unwrap();
+        let op: Box<Expr>;
+        match self.assignment() {
+            Some(expr) => op = expr,
+            _ => return None
+        }
+
+        match *op {
+            Expr::Receive{arrow_token, channel, ..}
+                => return Some(SelectCase::Receive{target: None, channel, arrow_token, elem_type: Datatype::yet_to_infer}),
+            Expr::Send{channel, arrow_token, value, ..}
+                => return Some(SelectCase::Send{channel, arrow_token, value}),
+            Expr::Assignment{target, operator, expr, ..} if operator.tok_type == TokenType::EQUAL => {
+                if let Expr::Receive{arrow_token, channel, ..} = *expr {
+                    return Some(SelectCase::Receive{
+                        target: Some(target), channel, arrow_token, elem_type: Datatype::yet_to_infer});
+                }
+            },
+            _ => ()
+        }
+
+        self.error(start_token.span, 
+            "Expected a send or a receive on a channel after 'case'".to_string());
+        return None;
+    }
 
     fn return_stmt(&mut self) -> Option<Box<Stmt>> { 
         eprintln!("In return_stmt()");
+        let start = self.start_span();
         match self.consume(TokenType::K_RETURN, "Expected 'return' keyword".to_string()) {
             Some(_) => (),
             _ => return None,
         }
 
         match self.expression() {
-            Some(expr) => Some(Box::new(Stmt::Return{expr})),
+            Some(expr) => Some(Box::new(Stmt::Return{expr, span: self.span_from(start)})),
             _ => None
         }
     
@@ -692,12 +1569,12 @@ impl Parser {
         //     }
         // }
         match self.expression() {
-            Some(expr) => Some(Box::new(Stmt::Expression{expr})),
+            Some(expr) => {
+                let span = expr.span();
+                Some(Box::new(Stmt::Expression{expr, span}))
+            },
             _ => {
-                self.has_errors = true;
-                logger::log_message(
-                    logger::LogLevel::ERROR, 
-                    self.tokens[self.current].col, self.tokens[self.start].line, 
+                self.error(self.tokens[self.current].span, 
                     "Unexpected token in expression".to_string()
                 );
                 // self.synchronize();
@@ -723,18 +1600,45 @@ impl Parser {
             match self.assignment() {
                 Some(expr) => expr_list.push(expr),
                 _ => {
-                    self.has_errors = true;
-                    logger::log_message(logger::LogLevel::ERROR, 
-                        self.tokens[self.current].col, self.tokens[self.current].line, 
-                        "Expected expression after comma".to_string()
-                    );
-                    self.synchronize();
+                    expr_list.push(self.missing_expr("Expected expression after comma".to_string()));
                     break;
                 }
             }
         } 
+
+        // `a, b = f()` and `a, b = b, a` are parsed as `a, (b = f())` and `a, (b = b), a`.
+        let assign_index = expr_list.iter().position(|expr| match &**expr {
+            Expr::Assignment{operator, ..} => operator.tok_type == TokenType::EQUAL,
+            _ => false
+        });
+        if let Some(index) = assign_index {
+            if index > 0 && self.is_target_list_valid(&expr_list[..index].to_vec()) {
+                let mut values = expr_list.split_off(index);
+                if let Expr::Assignment{target, operator, expr, ..} = *values.remove(0) {
+                    expr_list.push(target);
+                    values.insert(0, expr);
+                    let value = if values.len() == 1 {
+                        values.pop().unwrap()
+                    } else {
+                        let span = Parser::list_span(&values);
+                        Box::new(Expr::ExprList{expr_list: values, datatype: Datatype::yet_to_infer, span})
+                    };
+                    let target_span = Parser::list_span(&expr_list);
+                    let span = target_span.to(value.span());
+                    let assignment = Box::new(Expr::Assignment{
+                        target: Box::new(Expr::ExprList{expr_list, datatype: Datatype::yet_to_infer, span: target_span}),
+                        operator, 
+                        expr: value, 
+                        datatype: Datatype::yet_to_infer,
+                        span
+                    });
+                    return Some(Box::new(Expr::ExprList{expr_list: vec![assignment], datatype: Datatype::yet_to_infer, span}));
+                }
+            }
+        }
         
-        return Some(Box::new(Expr::ExprList{expr_list, datatype: Datatype::yet_to_infer}));
+        let span = Parser::list_span(&expr_list);
+        return Some(Box::new(Expr::ExprList{expr_list, datatype: Datatype::yet_to_infer, span}));
         
         // return self.logical_OR_expr();
     }
@@ -763,19 +1667,25 @@ impl Parser {
         let mut atom: Box<Expr>;
         match self.consume(TokenType::IDENTIFIER, 
             "L-value needs to be either variable or attribute reference".to_string()) {
-                Some(tok) => atom = Box::new(Expr::Variable{name: tok, datatype: Datatype::yet_to_infer, struct_name: None}),
+                Some(tok) => {
+                    let span = tok.span;
+                    atom = Box::new(Expr::Variable{name: tok, datatype: Datatype::yet_to_infer, struct_name: None, span});
+                },
                 _ => return None,
         }
 
         while self.match_(TokenType::DOT) {
             match self.consume(TokenType::IDENTIFIER, 
                 "L-value needs to be either variable or attribute references".to_string()) {
-                    Some(tok) => 
+                    Some(tok) => {
+                        let span = atom.span().to(tok.span);
                         atom = Box::new(Expr::AttributeRef{
                             object: atom, 
                             name: tok, 
                             object_dtype: Datatype::yet_to_infer,
-                            datatype: Datatype::yet_to_infer}),
+                            datatype: Datatype::yet_to_infer,
+                            span});
+                    },
                     _ => return None,
             }
         }
@@ -808,7 +1718,9 @@ impl Parser {
             match **target {
                 Expr::AttributeRef{..} => result &= true,
                 Expr::Variable{..}      => result &= true,
-                // in future - subscription and slicing can also be added
+                Expr::Index{..}         => result &= true,
+                Expr::Deref{..}         => result &= true,
+                // in future - slicing can also be added
                 _ => return false
             }
             
@@ -820,7 +1732,9 @@ impl Parser {
         match **target {
             Expr::AttributeRef{..} => return true,
             Expr::Variable{..} => return true,
-            // in future - subscription and slicing can also be added
+            Expr::Index{..} => return true,
+            Expr::Deref{..} => return true,
+            // in future - slicing can also be added
             _ => return false
         }
     }
@@ -830,6 +1744,23 @@ impl Parser {
         let lhs = self.logical_OR_expr();
         if lhs.is_none() { return None; }
 
+        if self.match_(TokenType::LEFT_ARROW) {
+            let arrow_token = self.curr().unwrap();
+            match self.logical_OR_expr() {
+                Some(value) => {
+                    let channel = lhs.unwrap();
+                    let span = channel.span().to(value.span());
+                    return Some(Box::new(Expr::Send{channel, arrow_token, value, span}));
+                },
+                _ => {
+                    let channel = lhs.unwrap();
+                    let value = self.missing_expr("Expected a value to send after '<-'".to_string());
+                    let span = channel.span().to(value.span());
+                    return Some(Box::new(Expr::Send{channel, arrow_token, value, span}));
+                }
+            }
+        }
+
         if let Some(peek) = self.peek() {
             if TokenType::get_assignment_ops().contains(&peek.tok_type) {
                 // IF Borrow checking is supported in future, then a separate branch for `=` needs to be created.
@@ -837,18 +1768,21 @@ impl Parser {
                 // and Expr::Assignment can get rid of operator attribute
                 self.advance();
                 let operator = peek;
-                if let Some(expr) = self.assignment() {
+                let expr = match self.assignment() {
+                    Some(expr) => Some(expr),
+                    _ => Some(self.missing_expr(format!("Expected an expression after '{}'", operator.value)))
+                };
+                if let Some(expr) = expr {
                     match lhs {
                         Some(target) 
                             => {
                                 if !self.is_target_valid(&target) {
-                                    self.has_errors = true;
-                                    log_message(logger::LogLevel::ERROR, 
-                                        self.tokens[self.current].col, self.tokens[self.current].line, 
-                                        "L-value incorrect. Only variables and attribute refs allowed".to_string());
+                                    self.error(self.tokens[self.current].span, 
+                                        "L-value incorrect. Only variables, attribute refs, array elements and dereferences allowed".to_string());
                                     return None;
                                 }
-                                return Some(Box::new(Expr::Assignment{target, operator, expr, datatype: Datatype::yet_to_infer}));
+                                let span = target.span().to(expr.span());
+                                return Some(Box::new(Expr::Assignment{target, operator, expr, datatype: Datatype::yet_to_infer, span}));
                             }
                         _ => {
                             return None;
@@ -887,10 +1821,11 @@ impl Parser {
 
             match self.logical_AND_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool, span});
         } 
         
         return Some(lhs);
@@ -917,10 +1852,11 @@ impl Parser {
 
             match self.inclusive_OR_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool, span});
         }
 
         return Some(lhs);
@@ -947,10 +1883,11 @@ impl Parser {
 
             match self.exclusive_OR_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -977,10 +1914,11 @@ impl Parser {
 
             match self.AND_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -1007,10 +1945,11 @@ impl Parser {
 
             match self.equality_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -1036,10 +1975,11 @@ impl Parser {
 
             match self.relational_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool, span});
         }
 
         return Some(lhs);
@@ -1065,10 +2005,11 @@ impl Parser {
 
             match self.shift_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::bool, span});
         }
 
         return Some(lhs);
@@ -1094,10 +2035,11 @@ impl Parser {
 
             match self.additive_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -1123,10 +2065,11 @@ impl Parser {
 
             match self.multiplicative_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -1152,10 +2095,11 @@ impl Parser {
 
             match self.unary_expr() {
                 Some(expr) => rhs = expr,
-                _ => return None,
+                _ => rhs = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer});
+            let span = lhs.span().to(rhs.span());
+            lhs = Box::new(Expr::Binary{lhs, operator, rhs, datatype: Datatype::yet_to_infer, span});
         }
 
         return Some(lhs);
@@ -1163,6 +2107,63 @@ impl Parser {
 
     fn unary_expr(&mut self) -> Option<Box<Expr>> { 
         eprintln!("In unary_expr()");
+        if self.match_(TokenType::LEFT_ARROW) {
+            let arrow_token = self.curr().unwrap();
+            match self.unary_expr() {
+                Some(channel) => {
+                    let span = arrow_token.span.to(channel.span());
+                    return Some(Box::new(Expr::Receive{arrow_token, channel, datatype: Datatype::yet_to_infer, span}));
+                },
+                _ => return None,
+            }
+        }
+
+        if self.match_(TokenType::BITWISE_AND) {
+            let amp_token = self.curr().unwrap();
+            let mutable = self.match_(TokenType::K_MUT);
+            match self.unary_expr() {
+                Some(operand) => {
+                    let span = amp_token.span.to(operand.span());
+                    return Some(Box::new(Expr::AddressOf{amp_token, mutable, operand, datatype: Datatype::yet_to_infer, span}));
+                },
+                _ => {
+                    self.error(amp_token.span, 
+                        "Expected a variable, field or array element after '&'".to_string());
+                    return None;
+                }
+            }
+        }
+
+        if self.match_(TokenType::K_NEW) {
+            let new_token = self.curr().unwrap();
+            match self.unary_expr() {
+                Some(value) => {
+                    let span = new_token.span.to(value.span());
+                    return Some(Box::new(Expr::New{new_token, value, datatype: Datatype::yet_to_infer, span}));
+                },
+                _ => {
+                    self.error(new_token.span, 
+                        "Expected a value to allocate after 'new'".to_string());
+                    return None;
+                }
+            }
+        }
+
+        if self.match_(TokenType::ASTERISK) {
+            let star_token = self.curr().unwrap();
+            match self.unary_expr() {
+                Some(operand) => {
+                    let span = star_token.span.to(operand.span());
+                    return Some(Box::new(Expr::Deref{star_token, operand, datatype: Datatype::yet_to_infer, span}));
+                },
+                _ => {
+                    self.error(star_token.span, 
+                        "Expected a reference to dereference after '*'".to_string());
+                    return None;
+                }
+            }
+        }
+
         if self.match_multi(TokenType::get_unary_ops()) {
             let operator: Token;
             let operand: Box<Expr>;
@@ -1174,10 +2175,11 @@ impl Parser {
 
             match self.unary_expr() {
                 Some(expr) => operand = expr,
-                _ => return None,
+                _ => operand = self.missing_expr(format!("Expected an expression after '{}'", operator.value)),
             }
 
-            return Some(Box::new(Expr::Unary{operator, operand, datatype: Datatype::yet_to_infer}));
+            let span = operator.span.to(operand.span());
+            return Some(Box::new(Expr::Unary{operator, operand, datatype: Datatype::yet_to_infer, span}));
         }
 
         return self.primary();
@@ -1198,14 +2200,17 @@ impl Parser {
                 // attributeref
                 match self.consume(TokenType::IDENTIFIER, 
                     "Expected an identifier after '.'".to_string()) {
-                        Some(tok) => 
+                        Some(tok) => {
+                            let span = atom.span().to(tok.span);
                             atom = Box::new(
                                 Expr::AttributeRef{
                                     object: atom, 
                                     name: tok, 
                                     object_dtype: Datatype::yet_to_infer,
-                                    datatype: Datatype::yet_to_infer
-                                }),
+                                    datatype: Datatype::yet_to_infer,
+                                    span
+                                })
+                        },
                         _ => return Some(atom)
                 }
 
@@ -1213,7 +2218,8 @@ impl Parser {
                 eprintln!("In primary loop: call");
                 let arguments = self.expression_list();
                 self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after expressions list".to_string());
-                atom = Box::new(Expr::Call{callee: atom, arguments, datatype: Datatype::yet_to_infer});
+                let span = self.span_from(atom.span());
+                atom = Box::new(Expr::Call{callee: atom, arguments, datatype: Datatype::yet_to_infer, span});
                 // if let Some(arguments) = self.expression() {
                 //     atom = Box::new(Expr::Call{callee: atom, arguments, datatype: Datatype::yet_to_infer});
                 //     self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after expressions list".to_string());
@@ -1222,19 +2228,60 @@ impl Parser {
                 //     self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after expressions list".to_string());
                 //     return Some(atom);
                 // }
+            } else if self.match_(TokenType::SQUARE_OPEN) {
+                let bracket_token = self.curr().unwrap();
+                let index: Box<Expr>;
+                match self.logical_OR_expr() {
+                    Some(expr) => index = expr,
+                    _ => {
+                        self.error(bracket_token.span, 
+                            "Expected an index expression after '['".to_string());
+                        return None;
+                    }
+                }
+                self.consume(TokenType::SQUARE_CLOSE, "Expected ']' after index expression".to_string());
+                let span = self.span_from(atom.span());
+                atom = Box::new(Expr::Index{array: atom, index, bracket_token, datatype: Datatype::yet_to_infer, span});
             } else if self.match_(TokenType::K_AS) {
                 eprintln!("In primary loop: casting");
-                match self.consume_multi(TokenType::get_datatypes(), 
-                "Expected a datatype after 'as' keyword for type casting".to_string()) {
-                    Some(tok) => 
+                if self.match_(TokenType::K_DYN) {
+                    match self.type_name("Expected a trait name after 'dyn' for type casting".to_string()) {
+                        Some(tok) => {
+                            let to_dtype = Datatype::dyn_trait{name: tok.value.clone()};
+                            let span = atom.span().to(tok.span);
+                            atom = Box::new(
+                                Expr::Cast{
+                                    variable: atom, 
+                                    cast_type: tok, 
+                                    from_dtype: Datatype::yet_to_infer, 
+                                    to_dtype,
+                                    span
+                                }
+                            );
+                            continue;
+                        },
+                        _ => return Some(atom)
+                    }
+                }
+                let cast_type = if self.check(TokenType::IDENTIFIER) {
+                    self.type_name("Expected a datatype after 'as' keyword for type casting".to_string())
+                } else {
+                    self.consume_multi(TokenType::get_datatypes(), 
+                        "Expected a datatype after 'as' keyword for type casting".to_string())
+                };
+                match cast_type {
+                    Some(tok) => {
+                        let span = atom.span().to(tok.span);
                         atom = Box::new(
                             Expr::Cast{
                                 variable:atom, 
                                 cast_type: tok, 
                                 from_dtype: Datatype::yet_to_infer, 
-                                to_dtype: Datatype::yet_to_infer
+                                to_dtype: Datatype::yet_to_infer,
+                                span
                             }
-                        ),
+                        )
+                    },
                     _ => return Some(atom)
                 }
             } else {
@@ -1251,9 +2298,36 @@ impl Parser {
         if let Some(peek) = self.peek() {
             eprintln!("Peek: {:?}", peek);
             if peek.tok_type == TokenType::BRACKET_OPEN                          { return self.grouping(); }
+            else if peek.tok_type == TokenType::SQUARE_OPEN                      { return self.array_literal(); }
             else if TokenType::get_literal_types().contains(&peek.tok_type)      { return self.literal(); }
+            else if peek.tok_type == TokenType::K_MATCH                          { return self.match_expr(); }
+            else if peek.tok_type == TokenType::K_FUNC                           { return self.closure(); }
+            else if peek.tok_type == TokenType::K_CHAN                           { return self.make_chan_expr(); }
+            else if peek.tok_type == TokenType::K_RC                             { return self.make_rc_expr(); }
+            else if peek.tok_type == TokenType::IDENTIFIER && self.is_module_path() {
+                let name: Token;
+                match self.module_path() {
+                    Some(tok) => name = tok,
+                    _ => return None,
+                }
+
+                if self.check(TokenType::COLON_COLON) {
+                    return self.enum_variant_expr(name);
+                }
+                if !self.restrictions.contains(&Restriction::STRUCT_EXPR) && self.check(TokenType::CURLY_OPEN) {
+                    return self.struct_expr(name);
+                }
+                return Some(Box::new(Expr::Variable{span: name.span, name, datatype: Datatype::yet_to_infer, struct_name: None}));
+            }
             else if peek.tok_type == TokenType::IDENTIFIER {
 
+                if let Some(next_peek) = self.peek_next() {
+                    if next_peek.tok_type == TokenType::COLON_COLON {
+                        let enum_name = self.advance().unwrap();
+                        return self.enum_variant_expr(self.qualify_type(enum_name));
+                    }
+                }
+
                 if self.restrictions.contains(&Restriction::STRUCT_EXPR) {
                     return self.variable();
                 }
@@ -1261,7 +2335,8 @@ impl Parser {
                 // check if it could be a struct-expr
                 if let Some(next_peek) = self.peek_next() {
                    if next_peek.tok_type == TokenType::CURLY_OPEN {
-                        return self.struct_expr();
+                        let name = self.advance().unwrap();
+                        return self.struct_expr(self.qualify_type(name));
                     }
                 }
 
@@ -1274,27 +2349,47 @@ impl Parser {
 
     fn grouping(&mut self) -> Option<Box<Expr>> { 
         eprintln!("In grouping()");
-        let mut group: Option<Box<Expr>> = None;
+        let start = self.start_span();
         match self.consume(TokenType::BRACKET_OPEN, 
             "Expected '(' at the starting of paranthesized expression".to_string()) {
                 Some(_) => (),
                 _ => return None,
         }
 
-        match self.expression() {
-            Some(expr) => group = Some(Box::new(Expr::Grouping{expr, datatype: Datatype::yet_to_infer})),
-            _ => (),
-        }
+        let expr = match self.expression() {
+            Some(expr) => expr,
+            _ => self.missing_expr("Expected an expression inside '( )'".to_string())
+        };
 
         self.consume(TokenType::BRACKET_CLOSE, "Expected ')' at the end of paranthesized expression".to_string());
-        return group;
+        return Some(Box::new(Expr::Grouping{expr, datatype: Datatype::yet_to_infer, span: self.span_from(start)}));
+    }
+
+    fn array_literal(&mut self) -> Option<Box<Expr>> {
+        let bracket_token: Token;
+        match self.consume(TokenType::SQUARE_OPEN, 
+            "Expected '[' at the starting of array literal".to_string()) {
+                Some(tok) => bracket_token = tok,
+                _ => return None,
+        }
+
+        let elements = self.expression_list();
+        // allowing trailing comma
+        self.match_(TokenType::COMMA);
+
+        match self.consume(TokenType::SQUARE_CLOSE, "Expected ']' at the end of array literal".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+        let span = self.span_from(bracket_token.span);
+        return Some(Box::new(Expr::ArrayLiteral{bracket_token, elements, datatype: Datatype::yet_to_infer, span}));
     }
 
     fn variable(&mut self) -> Option<Box<Expr>> { 
         eprintln!("In variable()");
         match self.advance() {
             Some(tok) if tok.tok_type == TokenType::IDENTIFIER 
-                => Some(Box::new(Expr::Variable{name: tok, datatype: Datatype::yet_to_infer, struct_name: None})),
+                => Some(Box::new(Expr::Variable{span: tok.span, name: tok, datatype: Datatype::yet_to_infer, struct_name: None})),
             _ => None
         }
     }
@@ -1303,24 +2398,311 @@ impl Parser {
         eprintln!("In literal()");
         match self.advance() {
             Some(tok) if TokenType::get_literal_types().contains(&tok.tok_type)
-                => Some(Box::new(Expr::Literal{value: tok, datatype: Datatype::yet_to_infer})),
+                => Some(Box::new(Expr::Literal{span: tok.span, value: tok, datatype: Datatype::yet_to_infer})),
             _ => None
         }
     }
 
-    fn struct_expr(&mut self) -> Option<Box<Expr>> { 
-        eprintln!("In struct_expr()");
+    // enumVariant -> typeName "::" IDENTIFIER ( "(" expr_list ")" | "{" (IDENTIFIER ":" expression)* "}" )?
+    fn enum_variant_expr(&mut self, enum_name: Token) -> Option<Box<Expr>> {
+        self.advance(); // '::'
+        let variant_name: Token;
+        match self.consume(TokenType::IDENTIFIER, "Expected variant name after '::'".to_string()) {
+            Some(tok) => variant_name = tok,
+            _ => return None,
+        }
+
+        let mut values: Vec<(Option<Token>, Box<Expr>)> = vec![];
+        if self.match_(TokenType::BRACKET_OPEN) {
+            for value in self.expression_list() {
+                values.push((None, value));
+            }
+            match self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after variant values".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+        } else if !self.restrictions.contains(&Restriction::STRUCT_EXPR) && self.match_(TokenType::CURLY_OPEN) {
+            while !self.match_(TokenType::CURLY_CLOSE) {
+                let field_name: Token;
+                match self.consume(TokenType::IDENTIFIER, "Expected field name in variant expression".to_string()) {
+                    Some(tok) => field_name = tok,
+                    _ => return None,
+                }
+                match self.consume(TokenType::COLON, "Expected ':' after field name in variant expression".to_string()) {
+                    Some(_) => (),
+                    _ => return None,
+                }
+                match self.assignment() {
+                    Some(expr) => values.push((Some(field_name), expr)),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) && !self.check(TokenType::CURLY_CLOSE) {
+                    if let Some(peek) = self.peek() {
+                        self.error(peek.span, 
+                            "Expected ',' or '}' after field expression".to_string());
+                    }
+                    return None;
+                }
+            }
+        }
+
+        let span = self.span_from(enum_name.span);
+        return Some(Box::new(Expr::EnumVariant{enum_name, variant_name, values, datatype: Datatype::yet_to_infer, span}));
+    }
+
+    // closure  -> "func" "(" parameters ")" ("->" DATATYPE)? block
+    fn closure(&mut self) -> Option<Box<Expr>> {
+        let func_token = self.advance().unwrap();
+        match self.consume(TokenType::BRACKET_OPEN, "Expected '(' after 'func'".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+
+        let parameters: Vec<(Token, Datatype)>;
+        match self.parameters() {
+            Some(params) => parameters = params,
+            _ => return None,
+        }
+
+        // a closure without a return type returns no value
+        let mut returntype = Datatype::unit();
+        if self.match_(TokenType::RIGHT_ARROW) {
+            match self.datatype("Expected a return type".to_string()) {
+                Some(dtype) => returntype = dtype,
+                _ => return None,
+            }
+        }
+
+        // the body may contain struct expressions even when the closure is inside a condition.
+        let restrictions = std::mem::take(&mut self.restrictions);
+        let body = self.block();
+        self.restrictions = restrictions;
+        match body {
+            Some(body) => Some(Box::new(Expr::Closure{
+                span: self.span_from(func_token.span), 
+                func_token, parameters, returntype, body, captures: vec![], datatype: Datatype::yet_to_infer})),
+            _ => None,
+        }
+    }
+
+    // makeChan -> "chan" "<" DATATYPE ">" "(" assignment-expr? ")"
+    fn make_chan_expr(&mut self) -> Option<Box<Expr>> {
+        let chan_token = self.peek().unwrap();
+        let elem_type: Datatype;
+        match self.datatype("Expected a channel type".to_string()) {
+            Some(Datatype::channel{elem_type: dtype}) => elem_type = *dtype,
+            _ => return None,
+        }
+
+        match self.consume(TokenType::BRACKET_OPEN, "Expected '(' after channel type".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+        let mut capacity = None;
+        if !self.check(TokenType::BRACKET_CLOSE) {
+            match self.assignment() {
+                Some(expr) => capacity = Some(expr),
+                _ => {
+                    self.error(chan_token.span, 
+                        "Expected capacity of channel or ')'".to_string());
+                    return None;
+                }
+            }
+        }
+        match self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after capacity of channel".to_string()) {
+            Some(_) => Some(Box::new(Expr::MakeChan{
+                span: self.span_from(chan_token.span), chan_token, elem_type, capacity, datatype: Datatype::yet_to_infer})),
+            _ => None,
+        }
+    }
+
+    // makeRc -> "rc" "(" assignment-expr ")"
+    fn make_rc_expr(&mut self) -> Option<Box<Expr>> {
+        let rc_token = self.advance().unwrap();
+
+        match self.consume(TokenType::BRACKET_OPEN, "Expected '(' after 'rc'".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+        let value = match self.assignment() {
+            Some(expr) => expr,
+            _ => {
+                self.error(rc_token.span, 
+                    "Expected the value of an rc after '('".to_string());
+                return None;
+            }
+        };
+        match self.consume(TokenType::BRACKET_CLOSE, "Expected ')' after the value of an rc".to_string()) {
+            Some(_) => Some(Box::new(Expr::MakeRc{
+                span: self.span_from(rc_token.span), rc_token, value, datatype: Datatype::yet_to_infer})),
+            _ => None,
+        }
+    }
+
+    // match    -> "match" expression "{" (pattern "=>" (block ","? | assignment-expr ","))* "}"
+    fn match_expr(&mut self) -> Option<Box<Expr>> {
+        let match_token = self.advance().unwrap();
+
+        self.restrictions.push(Restriction::STRUCT_EXPR);
+        let scrutinee = self.logical_OR_expr();
+        self.restrictions.pop();
+        let scrutinee = match scrutinee {
+            Some(expr) => expr,
+            _ => {
+                self.error(match_token.span, 
+                    "Expected an expression after 'match'".to_string());
+                return None;
+            }
+        };
+
+        match self.consume(TokenType::CURLY_OPEN, "Expected '{' after match expression".to_string()) {
+            Some(_) => (),
+            _ => return None,
+        }
+
+        let mut arms: Vec<(Pattern, Box<Stmt>)> = vec![];
+        while !self.match_(TokenType::CURLY_CLOSE) {
+            let pattern: Pattern;
+            match self.pattern() {
+                Some(pat) => pattern = pat,
+                _ => return None,
+            }
+
+            match self.consume(TokenType::FAT_ARROW, "Expected '=>' after pattern".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+
+            if self.check(TokenType::CURLY_OPEN) {
+                match self.block() {
+                    Some(block) => arms.push((pattern, block)),
+                    _ => return None,
+                }
+                self.match_(TokenType::COMMA);
+            } else {
+                match self.assignment() {
+                    Some(expr) => arms.push((pattern, Box::new(Stmt::Expression{span: expr.span(), expr}))),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) && !self.check(TokenType::CURLY_CLOSE) {
+                    if let Some(peek) = self.peek() {
+                        self.error(peek.span, 
+                            "Expected ',' or '}' after match arm".to_string());
+                    }
+                    return None;
+                }
+            }
+        }
+
+        let span = self.span_from(match_token.span);
+        return Some(Box::new(Expr::Match{match_token, scrutinee, arms, datatype: Datatype::yet_to_infer, span}));
+    }
+
+    // pattern  -> "_" | IDENTIFIER | "-"? literal 
+    //           | IDENTIFIER "::" IDENTIFIER ( "(" pattern ("," pattern)* ")" | "{" IDENTIFIER (":" pattern)? ("," IDENTIFIER (":" pattern)?)* "}" )?
+    fn pattern(&mut self) -> Option<Pattern> {
+        if self.match_(TokenType::UNDERSCORE) {
+            return Some(Pattern::Wildcard{token: self.curr().unwrap()});
+        }
+
+        if self.match_(TokenType::MINUS) {
+            let minus = self.curr().unwrap();
+            match self.consume_multi(
+                vec![TokenType::INT_LITERAL, TokenType::FLOAT_LITERAL], 
+                "Expected a number after '-' in pattern".to_string()) {
+                Some(mut tok) => {
+                    tok.value = "-".to_string() + tok.value.as_str();
+                    tok.span = minus.span.to(tok.span);
+                    return Some(Pattern::Literal{value: tok});
+                },
+                _ => return None,
+            }
+        }
+
+        if self.match_multi(TokenType::get_literal_types()) {
+            return Some(Pattern::Literal{value: self.curr().unwrap()});
+        }
+
         let name: Token;
-        match self.consume(TokenType::IDENTIFIER, 
-            "Expected identifier in struct expression".to_string()) {
+        if self.is_module_path() {
+            match self.module_path() {
+                Some(tok) => name = tok,
+                _ => return None,
+            }
+            match self.consume(TokenType::COLON_COLON, 
+                "Expected '::' and a variant name after the enum name".to_string()) {
+                Some(_) => (),
+                _ => return None,
+            }
+        } else {
+            match self.consume(TokenType::IDENTIFIER, "Expected a pattern".to_string()) {
                 Some(tok) => name = tok,
-                _ => { self.synchronize(); return None;},
+                _ => return None,
+            }
+
+            if !self.match_(TokenType::COLON_COLON) {
+                return Some(Pattern::Binding{name});
+            }
+        }
+        let name = self.qualify_type(name);
+
+        let variant_name: Token;
+        match self.consume(TokenType::IDENTIFIER, "Expected variant name after '::'".to_string()) {
+            Some(tok) => variant_name = tok,
+            _ => return None,
+        }
+
+        let mut fields: Vec<(Option<Token>, Box<Pattern>)> = vec![];
+        if self.match_(TokenType::BRACKET_OPEN) {
+            while !self.match_(TokenType::BRACKET_CLOSE) {
+                match self.pattern() {
+                    Some(pat) => fields.push((None, Box::new(pat))),
+                    _ => return None,
+                }
+                if !self.match_(TokenType::COMMA) && !self.check(TokenType::BRACKET_CLOSE) {
+                    if let Some(peek) = self.peek() {
+                        self.error(peek.span, 
+                            "Expected ',' or ')' after pattern".to_string());
+                    }
+                    return None;
+                }
+            }
+        } else if self.match_(TokenType::CURLY_OPEN) {
+            while !self.match_(TokenType::CURLY_CLOSE) {
+                let field_name: Token;
+                match self.consume(TokenType::IDENTIFIER, "Expected field name in pattern".to_string()) {
+                    Some(tok) => field_name = tok,
+                    _ => return None,
+                }
+                // `{x}` is short for `{x: x}`
+                let field_pattern = if self.match_(TokenType::COLON) {
+                    match self.pattern() {
+                        Some(pat) => pat,
+                        _ => return None,
+                    }
+                } else {
+                    Pattern::Binding{name: field_name.clone()}
+                };
+                fields.push((Some(field_name), Box::new(field_pattern)));
+                if !self.match_(TokenType::COMMA) && !self.check(TokenType::CURLY_CLOSE) {
+                    if let Some(peek) = self.peek() {
+                        self.error(peek.span, 
+                            "Expected ',' or '}' after field pattern".to_string());
+                    }
+                    return None;
+                }
+            }
         }
 
+        return Some(Pattern::Variant{enum_name: name, variant_name, fields});
+    }
+
+    fn struct_expr(&mut self, name: Token) -> Option<Box<Expr>> { 
+        eprintln!("In struct_expr()");
         match self.consume(TokenType::CURLY_OPEN, 
             "Expected '{' after identifier in struct expression".to_string()) {
                 Some(_) => (),
-                _ => return Some(Box::new(Expr::Variable{name, datatype: Datatype::yet_to_infer, struct_name: None}))
+                _ => return Some(Box::new(Expr::Variable{span: name.span, name, datatype: Datatype::yet_to_infer, struct_name: None}))
                 // _ => return None,
         }
 
@@ -1333,7 +2715,7 @@ impl Parser {
             match self.consume(TokenType::IDENTIFIER, 
                 "Expected field name in struct expression".to_string()) {
                     Some(tok) => field_name = tok,
-                    _ => { self.synchronize(); return None;},
+                    _ => return None,
                     // _ => {
                     //     while self.curr() == Some(name) {
                     //         self.current-=1;
@@ -1346,17 +2728,13 @@ impl Parser {
                 "Expected ':' after field name in struct expression".to_string()) {
                     Some(_) => (),
                     // _ => return Some(Box::new(Expr::Variable{name, datatype: Datatype::yet_to_infer, struct_name: None}))
-                    _ => { self.synchronize(); return None;},
+                    _ => return None,
             }
 
             // match self.expression() {
             match self.assignment() {
                 Some(expr) => field_value = expr,
-                _ => {
-                    self.synchronize();
-                    // return None;
-                    return Some(Box::new(Expr::Variable{name, datatype: Datatype::yet_to_infer, struct_name: None}));
-                }
+                _ => field_value = self.missing_expr("Expected a value for the field in struct expression".to_string())
             }
 
             fields.push((field_name, field_value));
@@ -1364,8 +2742,7 @@ impl Parser {
             if !self.match_(TokenType::COMMA) {
                 if let Some(peek) = self.peek() {
                     if peek.tok_type != TokenType::CURLY_CLOSE {
-                        self.has_errors = true;
-                        logger::log_message(logger::LogLevel::ERROR, peek.col, peek.line, 
+                        self.error(peek.span, 
                             "Expected ',' or '}' after field expression".to_string());
                         return None;
                     }
@@ -1373,7 +2750,8 @@ impl Parser {
             }
         }
 
-        return Some(Box::new(Expr::StructExpr{struct_name: name, fields, datatype: Datatype::yet_to_infer}));
+        let span = self.span_from(name.span);
+        return Some(Box::new(Expr::StructExpr{struct_name: name, fields, datatype: Datatype::yet_to_infer, span}));
     }
 
     
@@ -1388,13 +2766,37 @@ impl Parser {
         self.current = 0;
         self.start = 0;
         
+        self.collect_local_types();
+
+        if self.match_(TokenType::K_MODULE) {
+            self.module_declaration();
+        }
+        while self.match_(TokenType::K_IMPORT) {
+            self.import_declaration();
+        }
+
         let mut declarations: Vec<Box<Decl>> = vec![];
         while !self.is_end() {
             eprintln!("Calling declaration");
+            let before = self.current;
+            let is_pub = self.match_(TokenType::K_PUB);
+            if is_pub && self.check(TokenType::K_IMPL) {
+                let span = self.previous_span();
+                self.error(span, "'pub' can't be used on an impl, its methods are visible wherever the type is".to_string());
+            }
             match self.declaration() {
-                Some(decl) => declarations.push(decl),
+                Some(mut decl) => {
+                    self.add_item(&mut decl, is_pub);
+                    declarations.push(decl)
+                },
                 _ => ()
             }
+            if self.panic_mode {
+                if self.current == before { self.advance(); }
+                self.synchronize();
+                self.restrictions.clear();
+                self.panic_mode = false;
+            }
         }
         
         return (declarations, self.has_errors);
@@ -1402,7 +2804,21 @@ impl Parser {
         // return vec![];
     }
 
-    pub fn new() -> Self {
-        Parser { current: 0, start: 0, tokens: vec![], restrictions: vec![], has_errors: false }
+    /* parses the file of module `name` ("" for the root file) */
+    pub fn parse_module(&mut self, tokens: Vec<Token>, name: String) -> (Module, bool) {
+        self.module = Module{name, ..Default::default()};
+        self.local_types.clear();
+        let (decls, has_errors) = self.parse(tokens);
+        let mut module = std::mem::take(&mut self.module);
+        module.decls = decls;
+        return (module, has_errors);
+    }
+
+    pub fn new(diagnostics: Diagnostics) -> Self {
+        Parser { 
+            current: 0, start: 0, tokens: vec![], restrictions: vec![], diagnostics, 
+            panic_mode: false, error_count: 0, module: Module::default(), local_types: HashSet::new(), 
+            has_errors: false 
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+/// A place in a source file. `offset` is in bytes, `line` and `col` are 1-based
+/// and `col` counts characters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize
+}
+
+/// A region of a source file, from `start` up to but not including `end`.
+/// `file` is the index the file got when it was added to the diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub file: usize,
+    pub start: Position,
+    pub end: Position
+}
+
+impl Span {
+    pub fn new(file: usize, start: Position, end: Position) -> Self {
+        Span{ file, start, end }
+    }
+
+    /// For code the compiler made up, which has no place in the source.
+    pub fn unknown() -> Self {
+        Span::default()
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.start.line != 0
+    }
+
+    /// From the start of `self` to the end of `other`.
+    pub fn to(&self, other: Span) -> Span {
+        if !self.is_known() { return other; }
+        if !other.is_known() { return *self; }
+        Span{ file: self.file, start: self.start, end: other.end }
+    }
+}
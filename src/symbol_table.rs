@@ -2,24 +2,110 @@ use std::collections::HashMap;
 use inkwell::values::PointerValue;
 
 use crate::ast::*;
+use crate::lexer::Token;
+
+#[derive(Debug, Clone)]
+struct ScopedVariable<T> {
+    value: T,
+    declared_at: Option<Token>, /* None for parameters, pattern bindings and captures, which are never reported as unused */
+    used: bool
+}
+
+/// Variables of the enclosing blocks, innermost scope last. Lookups walk
+/// outwards, so a declaration in an inner scope shadows an outer one.
+#[derive(Debug, Clone)]
+pub struct ScopeChain<T> {
+    scopes: Vec<HashMap<String, ScopedVariable<T>>>
+}
+
+impl<T> Default for ScopeChain<T> {
+    fn default() -> Self {
+        ScopeChain::new()
+    }
+}
+
+impl<T> ScopeChain<T> {
+    pub fn new() -> Self {
+        ScopeChain{ scopes: vec![HashMap::new()] }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leaves the innermost scope and returns the declarations in it that were never used.
+    pub fn pop_scope(&mut self) -> Vec<Token> {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return vec![]
+        };
+        if self.scopes.is_empty() {
+            self.scopes.push(HashMap::new());
+        }
+
+        let mut unused: Vec<Token> = scope.into_values()
+            .filter(|var| !var.used)
+            .filter_map(|var| var.declared_at)
+            .collect();
+        unused.sort_by_key(|token| token.span.start.offset);
+        unused
+    }
+
+    /// Declares `name` in the innermost scope. Returns the earlier declaration
+    /// if the scope already has one; the new value replaces it either way.
+    pub fn declare(&mut self, name: &Token, value: T) -> Option<Token> {
+        let scope = self.scopes.last_mut().unwrap();
+        let previous = scope.insert(name.value.clone(), ScopedVariable{ value, declared_at: Some(name.clone()), used: false });
+        previous.map(|var| var.declared_at.unwrap_or_else(|| name.clone()))
+    }
+
+    /// Adds a variable to the innermost scope without duplicate or usage tracking.
+    pub fn insert(&mut self, name: String, value: T) {
+        self.scopes.last_mut().unwrap().insert(name, ScopedVariable{ value, declared_at: None, used: true });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name))
+            .map(|var| &var.value)
+    }
+
+    /// Same as `get`, and marks the variable as used.
+    pub fn lookup(&mut self, name: &str) -> Option<&T> {
+        self.scopes.iter_mut().rev()
+            .find_map(|scope| scope.get_mut(name))
+            .map(|var| {
+                var.used = true;
+                &var.value
+            })
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
     // pub variable_table: HashMap<String, Token>, /* key: variable name, value: Datatype string */
-    pub variable_table: HashMap<String, Datatype>, /* key: variable name, value: Datatype string */
+    pub variable_table: ScopeChain<Datatype>, /* key: variable name, value: Datatype */
     pub struct_decls: HashMap<String, Decl>, /* key: struct name, value: Declaration */
     pub impl_decls: HashMap<String, Vec<Box<Decl>>>,      /* key: struct name, value: Declaration */
     pub trait_decls: HashMap<String, Vec<Box<Decl>>>,
+    pub trait_impls: HashMap<String, Vec<String>>, /* key: struct name, value: names of implemented traits */
+    pub templates: HashMap<String, Decl>, /* key: name of generic function/struct, value: Template declaration */
+    pub enum_decls: HashMap<String, Decl>, /* key: enum name, value: Declaration */
     pub func_table: HashMap<String, Decl> /* key: Function name, value: return type list */
 }
 
 #[derive(Debug, Clone)]
 pub struct IRSymbolTable<'ctx> {
     // pub variable_table: HashMap<String, BasicValueEnum<'ctx>>,
-    pub variable_table: HashMap<String, PointerValue<'ctx>>,
+    pub variable_table: ScopeChain<PointerValue<'ctx>>,
     // pub functions_table: HashMap<String, inkwell::values::FunctionValue<'ctx>>,
     pub struct_decls: HashMap<String, Decl>,
     pub impl_decls: HashMap<String, Vec<Box<Decl>>>,
     pub trait_decls: HashMap<String, Vec<Box<Decl>>>,
+    pub enum_decls: HashMap<String, Decl>,
     pub func_table: HashMap<String, Decl>
 }
\ No newline at end of file
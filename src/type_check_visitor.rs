@@ -1,45 +1,79 @@
 use std::collections::HashMap;
 
-use crate::logger;
 // mod visitor;
 use crate::visitor::MutableVisitor;
 use crate::ast::*;
 use crate::symbol_table::*;
 use crate::lexer::Token;
 use crate::globals::*;
-use crate::logger::*;
+use crate::diagnostics::*;
+use crate::span::Span;
+
+/* what `local_borrow` gives for values pointing to a temporary instead of a variable */
+const TEMPORARY: &str = "<temporary>";
 
 
 pub struct TypeChecker {
     pub symbol_table: SymbolTable,
     pub current_scope: Scope,
+    pub loop_depth: usize, /* number of enclosing loops. `break`/`continue` are valid only if > 0 */
+    pub instances: Vec<Box<Decl>>, /* specializations of templates requested by the declaration being checked */
+    pub instance_decls: HashMap<String, Decl>, /* key: specialization name, value: its Prototype or StructDecl */
+    /* closures being checked, innermost last. (variables of the enclosing body, variables captured so far) */
+    pub closures: Vec<(ScopeChain<Datatype>, Vec<(Token, Datatype)>)>,
+    pub returntypes: Vec<Datatype>, /* return types of the functions and closures being checked, innermost last */
+    /* key: variable of the function being checked holding a reference to one of its locals, value: that local */
+    pub local_refs: HashMap<String, String>,
+    pub current_module: String, /* module of the declarations being checked, "" for the root file */
+    pub diagnostics: Diagnostics,
     pub has_errors: bool
 }
 
 impl MutableVisitor<(), (), Datatype> for TypeChecker {
     fn visit_decl(&mut self, decl: &mut Decl) {
         match decl {
-            Decl::StructDecl{name, fields}
+            Decl::StructDecl{name, fields, span}
                 => {
+                    for field in fields.iter_mut() {
+                        field.1 = self.resolve_datatype(&field.1, &field.0);
+                    }
                     self.symbol_table.struct_decls.insert(
-                        name.value.clone(), Decl::StructDecl{name: name.clone(), fields: fields.clone()});
+                        name.value.clone(), Decl::StructDecl{name: name.clone(), fields: fields.clone(), span: *span});
                 },
-            Decl::FuncDef{prototype, block}
+            Decl::FuncDef{prototype, block, ..}
                 => {
+                    /* a named function only sees its own parameters and locals */
+                    let enclosing = std::mem::take(&mut self.symbol_table.variable_table);
                     self.visit_decl(prototype);
 
-                    /* storing environment so that local variables dont affect the original env */
-                    // let pre_environment = self.symbol_table.clone();
+                    /* loops enclosing a nested function don't apply to its body */
+                    let loop_depth = self.loop_depth;
+                    self.loop_depth = 0;
+                    /* named functions don't capture, even when declared inside a closure */
+                    let closures = std::mem::take(&mut self.closures);
+                    if let Decl::Prototype{returntype, ..} = &**prototype {
+                        self.returntypes.push(returntype.clone());
+                    }
+                    let local_refs = std::mem::take(&mut self.local_refs);
                     self.visit_stmt(block);
-                    // self.symbol_table = pre_environment;
+                    self.check_implicit_return(block);
+                    self.local_refs = local_refs;
+                    self.returntypes.pop();
+                    self.closures = closures;
+                    self.loop_depth = loop_depth;
+                    self.symbol_table.variable_table = enclosing;
                 },
-            Decl::Prototype{name, parameters, returntype}
+            Decl::Prototype{name, parameters, returntype, span}
                 => {
+                    for param in parameters.iter_mut() {
+                        param.1 = self.resolve_datatype(&param.1, &param.0);
+                    }
+                    *returntype = self.resolve_datatype(returntype, name);
                     self.symbol_table.func_table.insert(name.value.clone(), 
                         Decl::Prototype{name: name.clone(), parameters: parameters.clone(), 
-                                returntype: returntype.clone()});
+                                returntype: returntype.clone(), span: *span});
                     for param in parameters {
-                        self.symbol_table.variable_table.insert(param.0.value.clone(), Datatype::get_tok_datatype(&param.1));
+                        self.symbol_table.variable_table.insert(param.0.value.clone(), param.1.clone());
                     }
                     if let Scope::Impl{name, ..} = &self.current_scope {
                         self.symbol_table.variable_table.insert(
@@ -47,10 +81,17 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
                             Datatype::object{name: name.clone()}
                         );
                     }
+                    if let Scope::Trait{name} = &self.current_scope {
+                        // default methods only know that `self` implements the trait.
+                        self.symbol_table.variable_table.insert(
+                            "self".to_string(), 
+                            Datatype::dyn_trait{name: name.clone()}
+                        );
+                    }
                 },
             /////////////////////////////////////////////////////////////
             // Decl::ImplDecl{name, trait_name, funcs}
-            Decl::ImplDecl{name, funcs, trait_name}
+            Decl::ImplDecl{name, funcs, trait_name, ..}
                 => {
                     let scope = self.current_scope.clone();
                     self.current_scope = Scope::Impl{
@@ -61,58 +102,144 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
                             trait_name.as_ref().unwrap().value.clone()
                         }
                     };
+                    if let Some(trait_tok) = trait_name {
+                        self.check_trait_conformance(name, trait_tok, funcs);
+                    }
                     for func in funcs {
                         self.visit_decl(func);
                         (*self.symbol_table.impl_decls.entry(
                             name.value.clone()
                         ).or_insert(vec![])).push((*func).clone());
                     }
+                    if let Some(trait_tok) = trait_name {
+                        (*self.symbol_table.trait_impls.entry(
+                            name.value.clone()
+                        ).or_insert(vec![])).push(trait_tok.value.clone());
+                    }
                     self.current_scope = scope;
                 },
-            Decl::TraitDecl{name, funcs}
+            Decl::TraitDecl{name, funcs, ..}
                 => {
                     let scope = self.current_scope.clone();
                     self.current_scope = Scope::Trait{name: name.value.clone()};
                     // (*self.symbol_table.trait_decls.entry(name.value.clone()).or_insert(vec![])).append(funcs);
+                    /* all methods are known before checking default bodies, so that they can call each other */
+                    self.symbol_table.trait_decls.insert(name.value.clone(), funcs.clone());
                     for func in funcs {
                         self.visit_decl(func); 
-                        (*self.symbol_table.trait_decls.entry(
-                            name.value.clone()
-                        ).or_insert(vec![])).push((*func).clone());
                     }
                     self.current_scope = scope;
                 },
+            Decl::EnumDecl{name, variants, span}
+                => {
+                    /* registered first so that payloads referring to the enum itself resolve to it */
+                    self.symbol_table.enum_decls.insert(
+                        name.value.clone(), Decl::EnumDecl{name: name.clone(), variants: variants.clone(), span: *span});
+
+                    let mut variant_names: Vec<String> = vec![];
+                    for (variant_name, fields) in variants.iter_mut() {
+                        if variant_names.contains(&variant_name.value) {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, variant_name.span, 
+                                format!("Variant `{}` is declared more than once in enum `{}`", 
+                                    variant_name.value, name.value));
+                        }
+                        variant_names.push(variant_name.value.clone());
+
+                        let payload_types: Vec<&mut Datatype> = match fields {
+                            VariantFields::Unit => vec![],
+                            VariantFields::Tuple(types) => types.iter_mut().collect(),
+                            VariantFields::Struct(fields) => fields.iter_mut().map(|(_, dtype)| dtype).collect(),
+                        };
+                        for dtype in payload_types {
+                            *dtype = self.resolve_datatype(dtype, variant_name);
+                            if *dtype == (Datatype::enumeration{name: name.value.clone()}) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, variant_name.span, 
+                                    format!("Variant `{}` can't contain enum `{}` itself", 
+                                        variant_name.value, name.value));
+                            }
+                            if self.contains_rc(dtype) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, variant_name.span, 
+                                    format!("Variant `{}` can't hold `{}`. Enum payloads don't count rc copies", 
+                                        variant_name.value, dtype.type_name()));
+                            }
+                        }
+                    }
+
+                    self.symbol_table.enum_decls.insert(
+                        name.value.clone(), Decl::EnumDecl{name: name.clone(), variants: variants.clone(), span: *span});
+                },
+            Decl::Template{type_params, decl: template_decl, ..}
+                => {
+                    /* bodies are checked once per specialization */
+                    let name = match &**template_decl {
+                        Decl::StructDecl{name, ..} => name.clone(),
+                        other => match other.get_prototype() {
+                            Decl::Prototype{name, ..} => name.clone(),
+                            _ => return
+                        }
+                    };
+
+                    if self.current_scope != Scope::Global {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, name.span, 
+                            "Type parameters are only allowed on top level functions and structs".to_string());
+                        return;
+                    }
+
+                    for (_, bound) in type_params.iter() {
+                        if let Some(trait_tok) = bound {
+                            if !self.symbol_table.trait_decls.contains_key(&trait_tok.value) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, trait_tok.span, 
+                                    format!("Trait `{}` doesn't exist. Make sure to declare traits before using them as bounds.", 
+                                        trait_tok.value));
+                            }
+                        }
+                    }
+
+                    self.symbol_table.templates.insert(name.value.clone(), decl.clone());
+                },
             // _ => ()
         }
     }
     
     fn visit_stmt(&mut self, stmt: &mut Stmt) {
         match stmt {
-            Stmt::Block{statements} => {
-                let pre_environment = self.symbol_table.clone();
+            Stmt::Block{statements, ..} => {
+                self.symbol_table.variable_table.push_scope();
                 for statement in statements {
                     self.visit_stmt(statement);
                 }
-                self.symbol_table = pre_environment;
+                for name in self.symbol_table.variable_table.pop_scope() {
+                    if !name.value.starts_with('_') {
+                        self.diagnostics.emit(Diagnostic::warning(W_UNUSED_VARIABLE, name.span, 
+                                format!("Variable `{}` is never used", name.value))
+                            .with_suggestion(format!("if this is intentional, name it `_{}`", name.value)));
+                    }
+                }
             },
-            Stmt::Decl{decl} => self.visit_decl(decl),
-            Stmt::Expression{expr} => {self.visit_expr(expr);},
+            Stmt::Decl{decl, ..} => self.visit_decl(decl),
+            Stmt::Expression{expr, ..} => {self.visit_expr(expr);},
             Stmt::For{
                 for_token,
                 initialization, 
                 condition, 
                 updation, 
-                block}
+                block, ..}
                 => {
                     if let Some(init_expr) = initialization {
                         self.visit_expr(init_expr);
                     }
 
                     if let Some(cond) = condition {
-                        if self.visit_expr(cond) != Datatype::bool {
+                        // an unknown type was already reported
+                        let cond_type = self.visit_expr(cond);
+                        if cond_type != Datatype::bool && cond_type != Datatype::yet_to_infer {
                             self.has_errors = true;
-                            log_message(logger::LogLevel::ERROR, 
-                                for_token.col, for_token.line, 
+                            self.diagnostics.error(E_TYPE, for_token.span, 
                                 "Condition expression of `for` should be of type `bool`".to_string());
                         }
                     }
@@ -121,14 +248,134 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
                         self.visit_expr(update);
                     }
 
+                    self.loop_depth += 1;
+                    self.visit_stmt(block);
+                    self.loop_depth -= 1;
+                },
+            Stmt::While{while_token, condition, block, ..}
+                => {
+                    let cond_type = self.visit_expr(condition);
+                    if cond_type != Datatype::bool && cond_type != Datatype::yet_to_infer {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, while_token.span,
+                            "Condition expression of `while` should be of type `bool`".to_string());
+                    }
+
+                    self.loop_depth += 1;
+                    self.visit_stmt(block);
+                    self.loop_depth -= 1;
+                },
+            Stmt::Loop{loop_token, count, block, ..}
+                => {
+                    if let Some(cnt) = count {
+                        if !Datatype::is_int(&self.visit_expr(cnt)) {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, loop_token.span,
+                                "Iteration count of `loop` should be of an integer type".to_string());
+                        }
+                    }
+
+                    self.loop_depth += 1;
                     self.visit_stmt(block);
+                    self.loop_depth -= 1;
+                },
+            Stmt::Break{break_token, ..}
+                => {
+                    if self.loop_depth == 0 {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, break_token.span,
+                            "`break` can only be used inside a loop".to_string());
+                    }
+                },
+            Stmt::Continue{continue_token, ..}
+                => {
+                    if self.loop_depth == 0 {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, continue_token.span,
+                            "`continue` can only be used inside a loop".to_string());
+                    }
+                },
+            /* the parser already reported it */
+            Stmt::Error{..} => (),
+            Stmt::Select{cases, ..}
+                => {
+                    let mut has_default = false;
+                    for (case, body) in cases.iter_mut() {
+                        match case {
+                            SelectCase::Receive{target, channel, arrow_token, elem_type} => {
+                                self.visit_receive_expr(arrow_token, channel, elem_type);
+                                if let Some(target) = target {
+                                    let target_type = self.visit_expr(target);
+                                    if *elem_type != Datatype::yet_to_infer && target_type != Datatype::yet_to_infer 
+                                        && target_type != *elem_type {
+                                        self.has_errors = true;
+                                        self.diagnostics.error(E_TYPE, arrow_token.span,
+                                            format!("Received value of type `{}` can't be assigned to a target of type `{}`", 
+                                                elem_type.type_name(), target_type.type_name()));
+                                    }
+                                }
+                            },
+                            SelectCase::Send{channel, arrow_token, value} => {
+                                self.visit_send_expr(channel, arrow_token, value);
+                            },
+                            SelectCase::Default{default_token} => {
+                                if has_default {
+                                    self.has_errors = true;
+                                    self.diagnostics.error(E_TYPE, default_token.span,
+                                        "`select` can have only one `default` case".to_string());
+                                }
+                                has_default = true;
+                            }
+                        }
+                        self.visit_stmt(body);
+                    }
+                },
+            Stmt::Spawn{spawn_token, call, ..}
+                => {
+                    self.visit_expr(call);
+                    if let Expr::Call{callee, arguments, ..} = &**call {
+                        if let Expr::AttributeRef{object, object_dtype, ..} = &**callee {
+                            self.check_escaping_ref(object, spawn_token.span, "be the receiver of a spawned method call");
+                            // the task copies its receiver, which a trait object does not know the size of.
+                            if Datatype::is_dyn_trait(object_dtype.auto_deref()) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, spawn_token.span,
+                                    "Cannot spawn a method call on a `dyn` trait object. Wrap the call in a closure".to_string());
+                            }
+                        }
+                        // the task may run after the spawning function has returned
+                        for argument in arguments {
+                            self.check_escaping_ref(argument, spawn_token.span, "be passed to a spawned task");
+                        }
+                    }
+                },
+            Stmt::Delete{delete_token, expr, ..}
+                => {
+                    let dtype = self.visit_expr(expr);
+                    match dtype {
+                        Datatype::reference{..} => {
+                            if let Some(local) = self.local_borrow(expr) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, delete_token.span, 
+                                    format!("Cannot delete a reference to local variable `{}`. Only values allocated with `new` can be deleted", 
+                                        local));
+                            }
+                        },
+                        Datatype::yet_to_infer => (),
+                        _ => {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, delete_token.span, 
+                                format!("Only references returned by `new` can be deleted, not a value of type `{}`", 
+                                    dtype.type_name()));
+                        }
+                    }
                 },
-            Stmt::If{if_token, condition, then_block, else_block} 
+            Stmt::If{if_token, condition, then_block, else_block, ..} 
                 => {
-                    if self.visit_expr(condition) != Datatype::bool {
+                    let cond_type = self.visit_expr(condition);
+                    if cond_type != Datatype::bool && cond_type != Datatype::yet_to_infer {
                         self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, 
-                            if_token.col, if_token.line, 
+                        self.diagnostics.error(E_TYPE, if_token.span, 
                             "Condition expression of `if` should be of type `bool`".to_string());
                     }   
                     self.visit_stmt(then_block);
@@ -136,25 +383,98 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
                         self.visit_stmt(else_blk);
                     }
                 },
-            Stmt::Return{expr} => {self.visit_expr(expr);},
-            Stmt::Var{name, datatype, initialization_value}
+            Stmt::Return{expr, ..} 
+                => {
+                    let mut dtype = self.visit_expr(expr);
+                    if let Some(returntype) = self.returntypes.last().cloned() {
+                        if TypeChecker::coerce_null(expr, &dtype, &returntype) {
+                            dtype = returntype.clone();
+                        }
+                        if let Expr::ExprList{expr_list, datatype, ..} = &mut **expr {
+                            if expr_list.len() == 1 && self.coerce_to_dyn(&mut expr_list[0], &dtype, &returntype) {
+                                *datatype = returntype.clone();
+                                dtype = returntype.clone();
+                            }
+                        }
+                        self.check_returned_values(&returntype, &dtype, expr.span());
+                    }
+                    self.check_escaping_ref(expr, expr.span(), "be returned from its function");
+                },
+            Stmt::Destructure{names, initialization_value, ..}
+                => {
+                    let dtype = self.visit_expr(initialization_value);
+                    let mut elem_types = match &dtype {
+                        Datatype::tuple{elem_types} => elem_types.clone(),
+                        Datatype::yet_to_infer => vec![Datatype::yet_to_infer; names.len()],
+                        _ => vec![dtype.clone()]
+                    };
+                    if elem_types.len() != names.len() {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, names[0].span, 
+                            format!("{} variables are declared but the initialization value has {} of type `{}`", 
+                                names.len(), 
+                                if elem_types.len() == 1 { "1 value".to_string() } else { format!("{} values", elem_types.len()) },
+                                dtype.type_name()));
+                        elem_types = vec![Datatype::yet_to_infer; names.len()];
+                    }
+                    let local = self.local_borrow(initialization_value);
+                    for (name, dtype) in names.iter().zip(elem_types.into_iter()) {
+                        self.declare_variable(name, dtype);
+                        match &local {
+                            Some(local) => { self.local_refs.insert(name.value.clone(), local.clone()); },
+                            _ => { self.local_refs.remove(&name.value); }
+                        }
+                    }
+                },
+            Stmt::Var{name, datatype, initialization_value, ..}
                 => {
+                    if let Some(dttype) = datatype {
+                        *dttype = self.resolve_datatype(dttype, name);
+                    }
                     let mut dtype: Datatype = Datatype::yet_to_infer;
                     if let Some(init_value) = initialization_value {
                         dtype = self.visit_expr(init_value);
                     }
 
                     if let Some(dttype) = datatype {
-                        dtype = Datatype::get_tok_datatype(dttype);
+                        if Datatype::is_array(dttype) && dtype != Datatype::yet_to_infer && dtype != *dttype {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, name.span, 
+                                format!("Initialization value of type {:?} doesn't match declared array type {:?}", dtype, dttype));
+                        }
+                        if let Datatype::function{..} | Datatype::channel{..} | Datatype::reference{..} | Datatype::rc{..} = dttype {
+                            if dtype != Datatype::yet_to_infer && dtype != *dttype && !TypeChecker::ref_coerces(&dtype, dttype)
+                                && !TypeChecker::coerce_null(initialization_value.as_mut().unwrap(), &dtype, dttype) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, name.span, 
+                                    format!("Initialization value of type `{}` doesn't match declared type `{}`", 
+                                        dtype.type_name(), dttype.type_name()));
+                            }
+                        }
+                        if Datatype::is_dyn_trait(dttype) && dtype != Datatype::yet_to_infer && dtype != *dttype {
+                            if !self.coerce_to_dyn(initialization_value.as_mut().unwrap(), &dtype, dttype) {
+                                self.has_errors = true;
+                                self.diagnostics.error(E_TYPE, name.span, 
+                                    format!("Initialization value of type {:?} doesn't implement {:?}", dtype, dttype));
+                            }
+                        }
+                        dtype = dttype.clone();
+                    }
+                    if dtype == Datatype::null_ref() {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, name.span, 
+                            format!("Type of `null` can't be inferred. Declare `{}` with a reference type", name.value));
+                        dtype = Datatype::yet_to_infer;
                     }
 
                     // eprintln!("Variable declared: ")
-                    self.symbol_table.variable_table.insert(name.value.clone(), dtype);
+                    self.declare_variable(name, dtype);
+                    match initialization_value.as_ref().and_then(|init_value| self.local_borrow(init_value)) {
+                        Some(local) => { self.local_refs.insert(name.value.clone(), local); },
+                        _ => { self.local_refs.remove(&name.value); }
+                    }
                     // self.symbol_table.variable_table[&name.value] = dtype;
                 },
-            // Stmt::While{condition, block}
-            //     => (),
-            _ => ()
         }
     }
 
@@ -162,29 +482,56 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
     fn visit_expr(&mut self, expr: &mut Expr) -> Datatype {
         
         match expr {
-            Expr::Variable{name, datatype, struct_name}
+            Expr::Variable{name, datatype, struct_name, ..}
                 => self.visit_variable_expr(name, datatype, struct_name),
-            Expr::Literal{value, datatype}
+            Expr::Literal{value, datatype, ..}
                 => self.visit_literal_expr(value, datatype),
-            Expr::Call{callee, arguments, datatype}
+            Expr::Call{callee, arguments, datatype, ..}
                 => self.visit_call_expr(callee, arguments, datatype),
-            Expr::AttributeRef{object, name, object_dtype, datatype}
+            Expr::AttributeRef{object, name, object_dtype, datatype, ..}
                 => self.visit_attributeref_expr(object, name, object_dtype, datatype),
-            Expr::Binary{lhs, rhs, operator, datatype}
+            Expr::Binary{lhs, rhs, operator, datatype, ..}
                 => self.visit_binary_expr(lhs, rhs, operator, datatype),
-            Expr::Unary{operator, operand, datatype}
+            Expr::Unary{operator, operand, datatype, ..}
                 => self.visit_unary_expr(operator, operand, datatype),
-            Expr::StructExpr{struct_name, fields, datatype}
+            Expr::StructExpr{struct_name, fields, datatype, ..}
                 => self.visit_struct_expr(struct_name, fields, datatype),
-            Expr::Assignment{target, expr, operator, datatype}
+            Expr::Assignment{target, expr, operator, datatype, ..}
                 => self.visit_assignment_expr(target, expr, operator, datatype),
                 // => self.visit_assignment_expr(target_list, expr_list, datatype),
-            Expr::Grouping{expr, datatype}
+            Expr::Grouping{expr, datatype, ..}
                 => self.visit_grouping_expr(expr, datatype),
-            Expr::Cast{variable, cast_type, from_dtype, to_dtype} 
+            Expr::Cast{variable, cast_type, from_dtype, to_dtype, ..} 
                 => self.visit_cast_expr(variable, cast_type, from_dtype, to_dtype),
-            Expr::ExprList{expr_list, datatype}
+            Expr::ExprList{expr_list, datatype, ..}
                 => self.visit_exprlist_expr(expr_list, datatype),
+            Expr::ArrayLiteral{bracket_token, elements, datatype, ..}
+                => self.visit_array_literal_expr(bracket_token, elements, datatype),
+            Expr::Index{array, index, bracket_token, datatype, ..}
+                => self.visit_index_expr(array, index, bracket_token, datatype),
+            Expr::EnumVariant{enum_name, variant_name, values, datatype, ..}
+                => self.visit_enum_variant_expr(enum_name, variant_name, values, datatype),
+            Expr::Match{match_token, scrutinee, arms, datatype, ..}
+                => self.visit_match_expr(match_token, scrutinee, arms, datatype),
+            Expr::Closure{func_token, parameters, returntype, body, captures, datatype, ..}
+                => self.visit_closure_expr(func_token, parameters, returntype, body, captures, datatype),
+            Expr::MakeChan{chan_token, elem_type, capacity, datatype, ..}
+                => self.visit_make_chan_expr(chan_token, elem_type, capacity, datatype),
+            Expr::Send{channel, arrow_token, value, ..}
+                => self.visit_send_expr(channel, arrow_token, value),
+            Expr::Receive{arrow_token, channel, datatype, ..}
+                => self.visit_receive_expr(arrow_token, channel, datatype),
+            Expr::AddressOf{amp_token, mutable, operand, datatype, ..}
+                => self.visit_address_of_expr(amp_token, mutable, operand, datatype),
+            Expr::Deref{star_token, operand, datatype, ..}
+                => self.visit_deref_expr(star_token, operand, datatype),
+            Expr::New{new_token, value, datatype, ..}
+                => self.visit_new_expr(new_token, value, datatype),
+            Expr::MakeRc{rc_token, value, datatype, ..}
+                => self.visit_make_rc_expr(rc_token, value, datatype),
+            /* the parser already reported it */
+            Expr::Error{..}
+                => Datatype::yet_to_infer,
             // _ => {return Datatype::yet_to_infer;}
         }
     }
@@ -193,6 +540,450 @@ impl MutableVisitor<(), (), Datatype> for TypeChecker {
 #[allow(dead_code, unused)]
 impl TypeChecker {
 
+    fn implements_trait(&self, struct_name: &String, trait_name: &String) -> bool {
+        match self.symbol_table.trait_impls.get(struct_name) {
+            Some(traits) => traits.contains(trait_name),
+            _ => false
+        }
+    }
+
+    /* type of method `method_name` of `obj_name` if `prototype` declares it */
+    fn method_type(obj_name: &String, prototype: &Decl, method_name: &String) -> Option<Datatype> {
+        if let Decl::Prototype{name: func_name, parameters, returntype, ..} = prototype {
+            if func_name.value == *method_name {
+                let mut param_types = vec![];
+                for (_, param_type) in parameters {
+                    param_types.push(Box::new(param_type.clone()));
+                }
+
+                return Some(Datatype::function{
+                    name: func_name.value.clone(),
+                    obj_name: Some(obj_name.clone()),
+                    returntype: Box::new(returntype.clone()),
+                    param_types
+                });
+            }
+        }
+        return None;
+    }
+
+    /* checks methods of `impl Trait for Struct` against the trait and adds inherited default methods to `funcs` */
+    fn check_trait_conformance(&mut self, struct_name: &Token, trait_tok: &Token, funcs: &mut Vec<Box<Decl>>) {
+        let trait_funcs = match self.symbol_table.trait_decls.get(&trait_tok.value) {
+            Some(trait_funcs) => trait_funcs.clone(),
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, trait_tok.span, 
+                    format!("Trait `{}` doesn't exist. Make sure to declare traits before implementing them.", 
+                        trait_tok.value));
+                return;
+            }
+        };
+
+        let mut implemented = vec![];
+        for func in funcs.iter() {
+            if let Decl::Prototype{name, parameters, returntype, ..} = func.get_prototype() {
+                implemented.push(name.value.clone());
+
+                let trait_proto = trait_funcs.iter().map(|f| f.get_prototype()).find(|proto| {
+                    if let Decl::Prototype{name: trait_method, ..} = proto {
+                        return trait_method.value == name.value;
+                    }
+                    return false;
+                });
+
+                match trait_proto {
+                    Some(Decl::Prototype{parameters: trait_params, returntype: trait_returntype, ..}) => {
+                        if parameters.len() != trait_params.len() {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, name.span, 
+                                format!("Method `{}` has {} parameters but trait `{}` declares {}", 
+                                    name.value, parameters.len(), trait_tok.value, trait_params.len()));
+                        } else {
+                            for (i, (param, trait_param)) in parameters.iter().zip(trait_params.iter()).enumerate() {
+                                if param.1 != trait_param.1 {
+                                    self.has_errors = true;
+                                    self.diagnostics.error(E_TYPE, param.0.span, 
+                                        format!("Parameter {} of method `{}` is of type {:?} but trait `{}` declares {:?}", 
+                                            i+1, name.value, param.1, trait_tok.value, trait_param.1));
+                                }
+                            }
+                        }
+
+                        if returntype != trait_returntype {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, name.span, 
+                                format!("Method `{}` returns {:?} but trait `{}` declares {:?}", 
+                                    name.value, returntype, trait_tok.value, trait_returntype));
+                        }
+                    },
+                    _ => {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, name.span, 
+                            format!("Method `{}` is not a member of trait `{}`", name.value, trait_tok.value));
+                    }
+                }
+            }
+        }
+
+        for trait_func in trait_funcs {
+            if let Decl::Prototype{name, ..} = trait_func.get_prototype() {
+                if implemented.contains(&name.value) {
+                    continue;
+                }
+
+                if let Decl::FuncDef{..} = *trait_func {
+                    // inherit the default body. It is checked and generated as a method of the struct.
+                    funcs.push(trait_func.clone());
+                } else {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, struct_name.span, 
+                        format!("Missing method `{}` of trait `{}` in impl for `{}`", 
+                            name.value, trait_tok.value, struct_name.value));
+                }
+            }
+        }
+    }
+
+    /* specializations outlive the block they were requested in, unlike the symbol table entries */
+    fn get_struct_decl(&self, name: &String) -> Option<Decl> {
+        if let Some(decl) = self.symbol_table.struct_decls.get(name) {
+            return Some(decl.clone());
+        }
+        match self.instance_decls.get(name) {
+            Some(decl @ Decl::StructDecl{..}) => Some(decl.clone()),
+            _ => None
+        }
+    }
+
+    /* replaces `Name<T1, T2>` in a type annotation with the object type of the specialization */
+    fn resolve_datatype(&mut self, dtype: &Datatype, token: &Token) -> Datatype {
+        match dtype {
+            Datatype::generic{name, type_args} => {
+                let mut resolved_args = vec![];
+                for type_arg in type_args {
+                    let resolved = self.resolve_datatype(type_arg, token);
+                    if resolved == Datatype::yet_to_infer {
+                        return Datatype::yet_to_infer;
+                    }
+                    resolved_args.push(resolved);
+                }
+                match self.instantiate_struct(name, &resolved_args, token) {
+                    Some(dtype) => dtype,
+                    _ => Datatype::yet_to_infer
+                }
+            },
+            Datatype::array{elem_type, size}
+                => Datatype::array{elem_type: Box::new(self.resolve_datatype(elem_type, token)), size: *size},
+            Datatype::channel{elem_type}
+                => Datatype::channel{elem_type: Box::new(self.resolve_datatype(elem_type, token))},
+            Datatype::reference{elem_type, mutable}
+                => Datatype::reference{elem_type: Box::new(self.resolve_datatype(elem_type, token)), mutable: *mutable},
+            Datatype::rc{elem_type}
+                => Datatype::rc{elem_type: Box::new(self.resolve_datatype(elem_type, token))},
+            Datatype::tuple{elem_types}
+                => Datatype::tuple{elem_types: elem_types.iter().map(|dtype| self.resolve_datatype(dtype, token)).collect()},
+            Datatype::function{name, obj_name, returntype, param_types}
+                => Datatype::function{
+                    name: name.clone(),
+                    obj_name: obj_name.clone(),
+                    returntype: Box::new(self.resolve_datatype(returntype, token)),
+                    param_types: param_types.iter().map(|param| Box::new(self.resolve_datatype(param, token))).collect()
+                },
+            /* the parser can't tell enum names from struct names */
+            Datatype::object{name} if self.symbol_table.enum_decls.contains_key(name)
+                => Datatype::enumeration{name: name.clone()},
+            _ => dtype.clone()
+        }
+    }
+
+    /* binds type parameters occurring in `param_type` by matching it against `arg_type`. false on conflicting bindings */
+    fn unify(param_type: &Datatype, arg_type: &Datatype, 
+        type_params: &Vec<String>, bindings: &mut HashMap<String, Datatype>) -> bool {
+        if *arg_type == Datatype::yet_to_infer {
+            return true;
+        }
+        match (param_type, arg_type) {
+            (Datatype::object{name}, _) if type_params.contains(name) => {
+                match bindings.get(name) {
+                    Some(bound) => *bound == *arg_type,
+                    _ => {
+                        bindings.insert(name.clone(), arg_type.clone());
+                        true
+                    }
+                }
+            },
+            (Datatype::array{elem_type, size}, Datatype::array{elem_type: arg_elem_type, size: arg_size}) 
+                if size == arg_size => TypeChecker::unify(elem_type, arg_elem_type, type_params, bindings),
+            (Datatype::channel{elem_type}, Datatype::channel{elem_type: arg_elem_type})
+                => TypeChecker::unify(elem_type, arg_elem_type, type_params, bindings),
+            (Datatype::reference{elem_type, ..}, Datatype::reference{elem_type: arg_elem_type, ..})
+            | (Datatype::rc{elem_type}, Datatype::rc{elem_type: arg_elem_type})
+                => TypeChecker::unify(elem_type, arg_elem_type, type_params, bindings),
+            (Datatype::tuple{elem_types}, Datatype::tuple{elem_types: arg_elem_types})
+                if elem_types.len() == arg_elem_types.len()
+                => elem_types.iter().zip(arg_elem_types.iter()).all(|(elem_type, arg_elem_type)| 
+                        TypeChecker::unify(elem_type, arg_elem_type, type_params, bindings)),
+            (Datatype::function{returntype, param_types, ..}, 
+                Datatype::function{returntype: arg_returntype, param_types: arg_param_types, ..})
+                if param_types.len() == arg_param_types.len() 
+                => param_types.iter().zip(arg_param_types.iter()).all(|(param, arg_param)| 
+                        TypeChecker::unify(param, arg_param, type_params, bindings))
+                    && TypeChecker::unify(returntype, arg_returntype, type_params, bindings),
+            _ => true
+        }
+    }
+
+    /* checks trait bounds of type parameters and returns the bindings */
+    fn bind_type_params(&mut self, type_params: &Vec<(Token, Option<Token>)>, type_args: &Vec<Datatype>, token: &Token) 
+    -> Option<HashMap<String, Datatype>> {
+        let mut bindings = HashMap::new();
+        let mut has_error = false;
+        for ((param, bound), type_arg) in type_params.iter().zip(type_args.iter()) {
+            if let Some(trait_tok) = bound {
+                let satisfies_bound = match type_arg {
+                    Datatype::object{name} => self.implements_trait(name, &trait_tok.value),
+                    Datatype::dyn_trait{name} => *name == trait_tok.value,
+                    _ => false
+                };
+                if !satisfies_bound {
+                    self.has_errors = true;
+                    has_error = true;
+                    self.diagnostics.error(E_TYPE, token.span, 
+                        format!("Type `{}` doesn't implement trait `{}` required by type parameter `{}`", 
+                            type_arg.type_name(), trait_tok.value, param.value));
+                }
+            }
+            bindings.insert(param.value.clone(), type_arg.clone());
+        }
+        if has_error { return None; }
+        return Some(bindings);
+    }
+
+    fn instantiate_struct(&mut self, name: &String, type_args: &Vec<Datatype>, token: &Token) -> Option<Datatype> {
+        let (type_params, fields) = match self.symbol_table.templates.get(name) {
+            Some(Decl::Template{type_params, decl, ..}) => match &**decl {
+                Decl::StructDecl{fields, ..} => (type_params.clone(), fields.clone()),
+                _ => {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, token.span, 
+                        format!("`{}` is a generic function, not a struct", name));
+                    return None;
+                }
+            },
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, token.span, 
+                    format!("Generic struct `{}` doesn't exist. Make sure to declare structs before using.", name));
+                return None;
+            }
+        };
+
+        if type_params.len() != type_args.len() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, token.span, 
+                format!("`{}` expects {} type arguments but {} were given", name, type_params.len(), type_args.len()));
+            return None;
+        }
+
+        let bindings = self.bind_type_params(&type_params, type_args, token)?;
+        let instance_name = Datatype::generic{name: name.clone(), type_args: type_args.clone()}.type_name();
+
+        if !self.instance_decls.contains_key(&instance_name) {
+            let mut struct_decl = Decl::StructDecl{
+                name: Token{
+                    tok_type: TokenType::IDENTIFIER, 
+                    value: instance_name.clone(), 
+                    span: token.span
+                },
+                fields: fields.iter().map(|(field_name, field_type)| 
+                    (field_name.clone(), field_type.substitute(&bindings))).collect(),
+                span: token.span
+            };
+            self.instance_decls.insert(instance_name.clone(), struct_decl.clone());
+            self.visit_decl(&mut struct_decl);
+            self.instance_decls.insert(instance_name.clone(), struct_decl.clone());
+            self.instances.push(Box::new(struct_decl));
+        }
+
+        return Some(Datatype::object{name: instance_name});
+    }
+
+    /* infers type arguments of a generic struct expression from its field values */
+    fn infer_struct_instance(&mut self, struct_name: &mut Token, fields: &mut Vec<(Token, Box<Expr>)>) -> bool {
+        let (type_params, field_decls) = match self.symbol_table.templates.get(&struct_name.value) {
+            Some(Decl::Template{type_params, decl, ..}) => match &**decl {
+                Decl::StructDecl{fields, ..} => (type_params.clone(), fields.clone()),
+                _ => (vec![], vec![])
+            },
+            _ => (vec![], vec![])
+        };
+        let param_names: Vec<String> = type_params.iter().map(|(param, _)| param.value.clone()).collect();
+
+        let mut bindings = HashMap::new();
+        for (field_name, field_expr) in fields.iter_mut() {
+            let field_type = self.visit_expr(field_expr);
+            for (decl_name, decl_type) in field_decls.iter() {
+                if decl_name.value == field_name.value 
+                    && !TypeChecker::unify(decl_type, &field_type, &param_names, &mut bindings) {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, field_name.span, 
+                        format!("Conflicting types inferred for type parameters of `{}`", struct_name.value));
+                    return false;
+                }
+            }
+        }
+
+        let mut type_args = vec![];
+        for param in param_names.iter() {
+            match bindings.get(param) {
+                Some(dtype) => type_args.push(dtype.clone()),
+                _ => {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, struct_name.span, 
+                        format!("Can't infer type parameter `{}` of `{}`", param, struct_name.value));
+                    return false;
+                }
+            }
+        }
+
+        match self.instantiate_struct(&struct_name.value.clone(), &type_args, struct_name) {
+            Some(Datatype::object{name}) => {
+                struct_name.value = name;
+                return true;
+            },
+            _ => return false
+        }
+    }
+
+    /* functions of the module being checked are used by their bare name inside it, 
+       the name is replaced by the qualified one they are declared with */
+    fn resolve_module_function(&mut self, name: &mut Token) {
+        if self.current_module.is_empty() || self.symbol_table.variable_table.lookup(&name.value).is_some() {
+            return;
+        }
+        let qualified = format!("{}::{}", self.current_module, name.value);
+        if self.symbol_table.func_table.contains_key(&qualified) || self.symbol_table.templates.contains_key(&qualified) {
+            name.value = qualified;
+        }
+    }
+
+    /* infers type arguments of a generic function from the arguments of a call, 
+       specializes it if needed and renames the callee to the specialization */
+    fn instantiate_function(&mut self, name: &mut Token, arg_types: &Vec<Datatype>) -> bool {
+        let (type_params, funcdef) = match self.symbol_table.templates.get(&name.value) {
+            Some(Decl::Template{type_params, decl, ..}) => match &**decl {
+                Decl::FuncDef{..} => (type_params.clone(), (**decl).clone()),
+                _ => {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, name.span, 
+                        format!("Generic struct `{}` can't be called", name.value));
+                    return false;
+                }
+            },
+            _ => return false
+        };
+        let parameters = match funcdef.get_prototype() {
+            Decl::Prototype{parameters, ..} => parameters.clone(),
+            _ => vec![]
+        };
+
+        if parameters.len() != arg_types.len() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, name.span, 
+                format!("Arity not right when calling {:?}: ({:?}) Parameters in definition but {:?} arguments provided", 
+                    name.value, parameters.len(), arg_types.len()));
+            return false;
+        }
+
+        let param_names: Vec<String> = type_params.iter().map(|(param, _)| param.value.clone()).collect();
+        let mut bindings = HashMap::new();
+        for ((_, param_type), arg_type) in parameters.iter().zip(arg_types.iter()) {
+            if !TypeChecker::unify(param_type, arg_type, &param_names, &mut bindings) {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, name.span, 
+                    format!("Conflicting types inferred for type parameters of `{}`", name.value));
+                return false;
+            }
+        }
+
+        let mut type_args = vec![];
+        for param in param_names.iter() {
+            match bindings.get(param) {
+                Some(dtype) => type_args.push(dtype.clone()),
+                _ => {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, name.span, 
+                        format!("Can't infer type parameter `{}` of `{}`", param, name.value));
+                    return false;
+                }
+            }
+        }
+
+        let bindings = match self.bind_type_params(&type_params, &type_args, name) {
+            Some(bindings) => bindings,
+            _ => return false
+        };
+        let instance_name = Datatype::generic{name: name.value.clone(), type_args}.type_name();
+
+        if !self.instance_decls.contains_key(&instance_name) {
+            let mut instance = funcdef;
+            if let Decl::FuncDef{prototype, block, ..} = &mut instance {
+                if let Decl::Prototype{name: proto_name, parameters, returntype, ..} = &mut **prototype {
+                    proto_name.value = instance_name.clone();
+                    for param in parameters.iter_mut() {
+                        param.1 = self.resolve_datatype(&param.1.substitute(&bindings), &param.0);
+                    }
+                    *returntype = self.resolve_datatype(&returntype.substitute(&bindings), proto_name);
+                }
+                // known before checking the body so that recursive calls find it.
+                self.instance_decls.insert(instance_name.clone(), (**prototype).clone());
+                block.substitute_types(&bindings);
+            }
+
+            /* the specialization is checked as a top level function of the module declaring the template */
+            let scope = self.current_scope.clone();
+            self.current_scope = Scope::Global;
+            let module = match name.value.rsplit_once("::") {
+                Some((module, _)) => module.to_string(),
+                None => String::new()
+            };
+            let current_module = std::mem::replace(&mut self.current_module, module);
+            self.visit_decl(&mut instance);
+            self.current_module = current_module;
+            self.current_scope = scope;
+
+            self.instances.push(Box::new(instance));
+        } else {
+            let prototype = self.instance_decls.get(&instance_name).unwrap().clone();
+            self.symbol_table.func_table.insert(instance_name.clone(), prototype);
+        }
+
+        name.value = instance_name;
+        return true;
+    }
+
+    /* wraps `expr` in an implicit `as dyn Trait` cast if a struct is used where a trait object is expected */
+    fn coerce_to_dyn(&mut self, expr: &mut Box<Expr>, from: &Datatype, to: &Datatype) -> bool {
+        if let (Datatype::object{name: struct_name}, Datatype::dyn_trait{name: trait_name}) = (from, to) {
+            if self.implements_trait(struct_name, trait_name) {
+                *expr = Box::new(Expr::Cast{
+                    variable: expr.clone(),
+                    cast_type: Token{
+                        tok_type: TokenType::IDENTIFIER,
+                        value: trait_name.clone(),
+                        span: Span::unknown()
+                    },
+                    from_dtype: from.clone(),
+                    to_dtype: to.clone(),
+                    span: expr.span()
+                });
+                return true;
+            }
+        }
+        return false;
+    }
+
     fn get_runtime_function_type(&self, func_name: String) -> Datatype {
         match func_name.as_str() {
             "scani8" => Datatype::function{
@@ -291,77 +1082,1013 @@ impl TypeChecker {
                 returntype: Box::new(Datatype::yet_to_infer),
                 param_types: vec![Box::new(Datatype::uint16)]
             },
-            "printu32" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::yet_to_infer),
-                param_types: vec![Box::new(Datatype::uint32)]
+            "printu32" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::uint32)]
+            },
+            "printu64" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::uint64)]
+            },
+            "printf32" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::float32)]
+            },
+            "printf64" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::float64)]
+            },
+            "scanbool" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::bool),
+                param_types: vec![]
+            },
+            "printbool" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::bool)]
+            },
+            "printc" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::char)]
+            },
+            "scanc" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::char),
+                param_types: vec![]
+            },
+            "printstr" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![Box::new(Datatype::string)]
+            },
+            "lenstr" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::int64),
+                param_types: vec![Box::new(Datatype::string)]
+            },
+            "eqstr" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::bool),
+                param_types: vec![Box::new(Datatype::string), Box::new(Datatype::string)]
+            },
+            "waitall" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::yet_to_infer),
+                param_types: vec![]
+            },
+            "rclive" => Datatype::function {
+                name: func_name,
+                obj_name: None,
+                returntype: Box::new(Datatype::int64),
+                param_types: vec![]
+            },
+            _ => Datatype::yet_to_infer
+        }
+    }
+
+    fn visit_make_chan_expr(&mut self, 
+        chan_token: &mut Token, 
+        elem_type: &mut Datatype, 
+        capacity: &mut Option<Box<Expr>>, 
+        datatype: &mut Datatype) -> Datatype {
+        *elem_type = self.resolve_datatype(elem_type, chan_token);
+        if *elem_type == Datatype::yet_to_infer {
+            *datatype = Datatype::yet_to_infer;
+            return Datatype::yet_to_infer;
+        }
+        if self.contains_rc(elem_type) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, chan_token.span, 
+                format!("Channels can't carry `{}`, as rc counts aren't shared between tasks", elem_type.type_name()));
+        }
+
+        if let Some(capacity) = capacity {
+            let capacity_type = self.visit_expr(capacity);
+            if !Datatype::is_int(&capacity_type) {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, chan_token.span, 
+                    format!("Capacity of channel should be of an integer type, found `{}`", capacity_type.type_name()));
+            }
+        }
+
+        *datatype = Datatype::channel{elem_type: Box::new(elem_type.clone())};
+        return (*datatype).clone();
+    }
+
+    fn visit_send_expr(&mut self, channel: &mut Box<Expr>, arrow_token: &mut Token, value: &mut Box<Expr>) -> Datatype {
+        let channel_type = self.visit_expr(channel);
+        let value_type = self.visit_expr(value);
+        match &channel_type {
+            Datatype::channel{elem_type} => {
+                if value_type != Datatype::yet_to_infer && value_type != **elem_type 
+                    && !TypeChecker::ref_coerces(&value_type, elem_type)
+                    && !TypeChecker::coerce_null(value, &value_type, elem_type)
+                    && !self.coerce_to_dyn(value, &value_type, elem_type) {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, arrow_token.span, 
+                        format!("Cannot send a value of type `{}` on a channel of type `{}`", 
+                            value_type.type_name(), channel_type.type_name()));
+                }
+            },
+            Datatype::yet_to_infer => (),
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, arrow_token.span, 
+                    format!("Cannot send on a value of type `{}`, which is not a channel", channel_type.type_name()));
+            }
+        }
+        // the receiver may be running after the sending function has returned
+        self.check_escaping_ref(value, arrow_token.span, "be sent on a channel");
+        return Datatype::yet_to_infer;
+    }
+
+    fn visit_receive_expr(&mut self, arrow_token: &mut Token, channel: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
+        let channel_type = self.visit_expr(channel);
+        match channel_type {
+            Datatype::channel{elem_type} => *datatype = *elem_type,
+            Datatype::yet_to_infer => *datatype = Datatype::yet_to_infer,
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, arrow_token.span, 
+                    format!("Cannot receive from a value of type `{}`, which is not a channel", channel_type.type_name()));
+                *datatype = Datatype::yet_to_infer;
+            }
+        }
+        return (*datatype).clone();
+    }
+
+    fn visit_cast_expr(&mut self, 
+        variable: &mut Box<Expr>, 
+        casttype: &mut Token, 
+        from_dtype: &mut Datatype,
+        to_dtype: &mut Datatype) -> Datatype {
+        let var_type = self.visit_expr(variable);
+        *from_dtype = var_type.clone();
+
+        // `as dyn Trait`. Target type is already filled by the parser.
+        if let Datatype::dyn_trait{name: trait_name} = to_dtype.clone() {
+            if !self.symbol_table.trait_decls.contains_key(&trait_name) {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, casttype.span, 
+                    "Given trait doesn't exist. Make sure to declare traits before using.".to_string());
+                *to_dtype = Datatype::yet_to_infer;
+                return Datatype::yet_to_infer;
+            }
+
+            let is_castable = match &var_type {
+                Datatype::yet_to_infer => {
+                    *to_dtype = Datatype::yet_to_infer;
+                    return Datatype::yet_to_infer;
+                },
+                Datatype::dyn_trait{name} => *name == trait_name,
+                Datatype::object{name} => self.implements_trait(name, &trait_name),
+                _ => false
+            };
+            if !is_castable {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, casttype.span, 
+                    format!("Can't cast {:?} to `dyn {}`. Only structs implementing the trait can be cast.", 
+                        var_type, trait_name));
+                *to_dtype = Datatype::yet_to_infer;
+                return Datatype::yet_to_infer;
+            }
+            return to_dtype.clone();
+        }
+
+        let mut cast_type = Datatype::get_datatype(&casttype.tok_type);
+        if let Datatype::object{..} = cast_type {
+            // check if a struct of given casttype exists
+            if self.symbol_table.struct_decls.contains_key(&casttype.value) {
+                cast_type = Datatype::object{name: casttype.value.clone()};
+            } else {
+                // casttype doesnt exist.
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, casttype.span, 
+                    "Given cast type doesn't exist. Make sure to declare structs before using.".to_string());
+                *to_dtype = var_type.clone();
+                return var_type;
+            }
+        }
+
+        // if both are objects, check if they are castable while converting to LLVM IR by matching 
+
+
+        if var_type == Datatype::yet_to_infer { 
+            *to_dtype = var_type;
+            return Datatype::yet_to_infer; 
+        }
+
+        // char only converts to and from integers.
+        if (var_type == Datatype::char || cast_type == Datatype::char) && var_type != cast_type {
+            let other = if var_type == Datatype::char { &cast_type } else { &var_type };
+            if !Datatype::is_int(other) {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, casttype.span, 
+                    format!("Can't cast between `char` and {:?}. Only integer types are allowed.", other));
+                *to_dtype = Datatype::yet_to_infer;
+                return Datatype::yet_to_infer;
+            }
+        }
+
+        *to_dtype = cast_type.clone();
+        return cast_type.clone();
+    }
+
+    fn visit_array_literal_expr(&mut self, 
+        bracket_token: &mut Token, 
+        elements: &mut Vec<Box<Expr>>, 
+        datatype: &mut Datatype) -> Datatype {
+        
+        if elements.is_empty() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, bracket_token.span, 
+                "Array literal needs at least one element".to_string());
+            *datatype = Datatype::yet_to_infer;
+            return Datatype::yet_to_infer;
+        }
+
+        let mut has_error = false;
+        let mut elem_type = Datatype::yet_to_infer;
+        for (i, element) in elements.iter_mut().enumerate() {
+            let dtype = self.visit_expr(element);
+            if dtype == Datatype::yet_to_infer {
+                has_error = true;
+            } else if i == 0 {
+                elem_type = dtype;
+            } else if dtype != elem_type {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, bracket_token.span, 
+                    format!("Element {} of array literal is of type {:?} but expected {:?}", i, dtype, elem_type));
+                has_error = true;
+            }
+        }
+
+        *datatype = if has_error {
+            Datatype::yet_to_infer
+        } else {
+            Datatype::array{elem_type: Box::new(elem_type), size: elements.len() as u64}
+        };
+        return (*datatype).clone();
+    }
+
+    fn visit_index_expr(&mut self, 
+        array: &mut Box<Expr>, 
+        index: &mut Box<Expr>, 
+        bracket_token: &mut Token, 
+        datatype: &mut Datatype) -> Datatype {
+        
+        let array_type = self.visit_expr(array);
+        let index_type = self.visit_expr(index);
+        let mut has_error = false;
+
+        if index_type != Datatype::yet_to_infer && !Datatype::is_int(&index_type) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, bracket_token.span, 
+                "Array index should be of an integer type".to_string());
+            has_error = true;
+        }
+
+        /* arrays are indexed through references as well */
+        match array_type.auto_deref().clone() {
+            Datatype::array{elem_type, ..} => {
+                *datatype = if has_error {Datatype::yet_to_infer} else {*elem_type};
+            },
+            Datatype::yet_to_infer => *datatype = Datatype::yet_to_infer,
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, bracket_token.span, 
+                    format!("Can't index into a value of type {:?}", array_type));
+                *datatype = Datatype::yet_to_infer;
+            }
+        }
+        return (*datatype).clone();
+    }
+
+    fn get_enum_variant(&self, enum_name: &String, variant_name: &String) -> Option<VariantFields> {
+        if let Some(Decl::EnumDecl{variants, ..}) = self.symbol_table.enum_decls.get(enum_name) {
+            for (name, fields) in variants {
+                if name.value == *variant_name {
+                    return Some(fields.clone());
+                }
+            }
+        }
+        return None;
+    }
+
+    fn visit_enum_variant_expr(&mut self, 
+        enum_name: &mut Token, 
+        variant_name: &mut Token, 
+        values: &mut Vec<(Option<Token>, Box<Expr>)>, 
+        datatype: &mut Datatype) -> Datatype {
+
+        *datatype = Datatype::yet_to_infer;
+        if !self.symbol_table.enum_decls.contains_key(&enum_name.value) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, enum_name.span, 
+                "Couldn't find enum declaration of given name. Make sure to declare enum before using it.".to_string());
+            return Datatype::yet_to_infer;
+        }
+
+        let fields = match self.get_enum_variant(&enum_name.value, &variant_name.value) {
+            Some(fields) => fields,
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, variant_name.span, 
+                    format!("Enum `{}` has no variant `{}`", enum_name.value, variant_name.value));
+                return Datatype::yet_to_infer;
+            }
+        };
+
+        let mut has_error = false;
+        let field_types = fields.types();
+        match &fields {
+            VariantFields::Struct(field_decls) => {
+                // values are reordered to declaration order so codegen can rely on positions.
+                let mut ordered: Vec<Option<(Option<Token>, Box<Expr>)>> = vec![None; field_decls.len()];
+                for (field_name, value) in values.drain(..) {
+                    let index = match &field_name {
+                        Some(_) => fields.index_of(&field_name, 0),
+                        _ => None
+                    };
+                    match index {
+                        Some(i) if ordered[i].is_none() => ordered[i] = Some((field_name, value)),
+                        Some(_) => {
+                            let field_tok = field_name.unwrap();
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, field_tok.span, 
+                                format!("Field `{}` is given more than once", field_tok.value));
+                            has_error = true;
+                        },
+                        _ => {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, variant_name.span, 
+                                format!("Variant `{}::{}` has no field {}", enum_name.value, variant_name.value,
+                                    match field_name { Some(tok) => format!("`{}`", tok.value), _ => "given by position".to_string() }));
+                            has_error = true;
+                        }
+                    }
+                }
+
+                let mut missing_fields: Vec<String> = vec![];
+                for (i, value) in ordered.into_iter().enumerate() {
+                    match value {
+                        Some(value) => values.push(value),
+                        _ => missing_fields.push(field_decls[i].0.value.clone())
+                    }
+                }
+                if !missing_fields.is_empty() {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, variant_name.span, 
+                        format!("Some fields were missing from expression: {:?}", missing_fields));
+                    has_error = true;
+                }
+            },
+            _ => {
+                if values.len() != field_types.len() || values.iter().any(|(field_name, _)| field_name.is_some()) {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, variant_name.span, 
+                        format!("Variant `{}::{}` expects {} values given in parentheses", 
+                            enum_name.value, variant_name.value, field_types.len()));
+                    has_error = true;
+                }
+            }
+        }
+
+        if !has_error {
+            for ((_, value), field_type) in values.iter_mut().zip(field_types.iter()) {
+                let dtype = self.visit_expr(value);
+                if dtype == Datatype::yet_to_infer {
+                    has_error = true;
+                } else if dtype != *field_type && !self.coerce_to_dyn(value, &dtype, field_type) {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, variant_name.span, 
+                        format!("Value of type `{}` given for field of type `{}` in variant `{}::{}`", 
+                            dtype.type_name(), field_type.type_name(), enum_name.value, variant_name.value));
+                    has_error = true;
+                }
+            }
+        }
+
+        if !has_error {
+            *datatype = Datatype::enumeration{name: enum_name.value.clone()};
+        }
+        return (*datatype).clone();
+    }
+
+    /* checks that `pattern` can match values of `dtype` and declares its bindings in the current scope */
+    fn check_pattern(&mut self, pattern: &Pattern, dtype: &Datatype) -> bool {
+        match pattern {
+            Pattern::Wildcard{..} => true,
+            Pattern::Binding{name} => {
+                self.symbol_table.variable_table.insert(name.value.clone(), dtype.clone());
+                true
+            },
+            Pattern::Literal{value} => {
+                if *dtype == Datatype::yet_to_infer { return true; }
+                let matches = match value.tok_type {
+                    TokenType::INT_LITERAL | TokenType::HEX_LITERAL | TokenType::OCTAL_LITERAL
+                        => Datatype::is_int(dtype),
+                    TokenType::FLOAT_LITERAL    => Datatype::is_float(dtype),
+                    TokenType::K_TRUE | TokenType::K_FALSE
+                                                => *dtype == Datatype::bool,
+                    _                           => Datatype::get_datatype(&value.tok_type) == *dtype
+                };
+                if !matches {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, value.span, 
+                        format!("Pattern `{}` can't match a value of type `{}`", value.value, dtype.type_name()));
+                }
+                matches
+            },
+            Pattern::Variant{enum_name, variant_name, fields} => {
+                if *dtype == Datatype::yet_to_infer { 
+                    for (_, field_pattern) in fields {
+                        self.check_pattern(field_pattern, &Datatype::yet_to_infer);
+                    }
+                    return true; 
+                }
+                if *dtype != (Datatype::enumeration{name: enum_name.value.clone()}) {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, enum_name.span, 
+                        format!("Pattern of enum `{}` can't match a value of type `{}`", enum_name.value, dtype.type_name()));
+                    return false;
+                }
+
+                let variant_fields = match self.get_enum_variant(&enum_name.value, &variant_name.value) {
+                    Some(variant_fields) => variant_fields,
+                    _ => {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, variant_name.span, 
+                            format!("Enum `{}` has no variant `{}`", enum_name.value, variant_name.value));
+                        return false;
+                    }
+                };
+
+                if let VariantFields::Tuple(types) = &variant_fields {
+                    if fields.len() != types.len() {
+                        self.has_errors = true;
+                        self.diagnostics.error(E_TYPE, variant_name.span, 
+                            format!("Variant `{}::{}` has {} fields but the pattern has {}", 
+                                enum_name.value, variant_name.value, types.len(), fields.len()));
+                        return false;
+                    }
+                }
+
+                let field_types = variant_fields.types();
+                let mut is_valid = true;
+                for (i, (field_name, field_pattern)) in fields.iter().enumerate() {
+                    match variant_fields.index_of(field_name, i) {
+                        Some(index) => {
+                            is_valid = self.check_pattern(field_pattern, &field_types[index]) && is_valid;
+                        },
+                        _ => {
+                            self.has_errors = true;
+                            self.diagnostics.error(E_TYPE, variant_name.span, 
+                                format!("Variant `{}::{}` has no field {}", enum_name.value, variant_name.value,
+                                    match field_name { Some(tok) => format!("`{}`", tok.value), _ => "given by position".to_string() }));
+                            is_valid = false;
+                        }
+                    }
+                }
+                is_valid
+            }
+        }
+    }
+
+    /* values of `dtype` that none of `patterns` match, in a printable form. Empty if the patterns are exhaustive */
+    fn missing_patterns(&self, patterns: &Vec<&Pattern>, dtype: &Datatype) -> Vec<String> {
+        let rows = patterns.iter().map(|pattern| vec![Some(*pattern)]).collect();
+        self.missing_rows(&rows, &[dtype.clone()]).into_iter().map(|mut row| row.remove(0)).collect()
+    }
+
+    /* rows of values, one per type of `types`, that no row of patterns matches. None matches anything.
+       The first column is split by value: the rows that can match a `bool` value or an enum variant 
+       are checked against the fields of the variant followed by the remaining columns */
+    fn missing_rows(&self, rows: &Vec<Vec<Option<&Pattern>>>, types: &[Datatype]) -> Vec<Vec<String>> {
+        if types.is_empty() {
+            return if rows.is_empty() { vec![vec![]] } else { vec![] };
+        }
+        let matches_all = |pattern: &Option<&Pattern>| pattern.map_or(true, |pattern| pattern.is_irrefutable());
+
+        // name of each value of the first column, the types of its fields and the rows matching it
+        let values: Vec<(String, Vec<Datatype>, Vec<Vec<Option<&Pattern>>>)> = match &types[0] {
+            Datatype::yet_to_infer => return vec![],
+            Datatype::bool => ["true", "false"].iter().map(|value| {
+                let value_rows = rows.iter()
+                    .filter(|row| matches_all(&row[0]) || matches!(row[0], Some(Pattern::Literal{value: tok}) if tok.value == *value))
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                (value.to_string(), vec![], value_rows)
+            }).collect(),
+            Datatype::enumeration{name} => {
+                let variants = match self.symbol_table.enum_decls.get(name) {
+                    Some(Decl::EnumDecl{variants, ..}) => variants.clone(),
+                    _ => return vec![]
+                };
+                variants.iter().map(|(variant_name, variant_fields)| {
+                    let field_types = variant_fields.types();
+                    let value_rows = rows.iter().filter_map(|row| {
+                        // fields left out of a struct-like pattern match anything.
+                        let mut fields = vec![None; field_types.len()];
+                        match row[0] {
+                            Some(Pattern::Variant{variant_name: pattern_name, fields: field_patterns, ..}) => {
+                                if pattern_name.value != variant_name.value {
+                                    return None;
+                                }
+                                for (i, (field_name, field)) in field_patterns.iter().enumerate() {
+                                    if let Some(index) = variant_fields.index_of(field_name, i) {
+                                        fields[index] = Some(&**field);
+                                    }
+                                }
+                            },
+                            pattern if matches_all(&pattern) => (),
+                            _ => return None
+                        }
+                        fields.extend(row[1..].iter().cloned());
+                        Some(fields)
+                    }).collect();
+                    (format!("{}::{}", name, variant_name.value), field_types, value_rows)
+                }).collect()
+            },
+            _ => {
+                // values of other types can't be listed, only patterns matching anything cover them
+                let rest_rows = rows.iter().filter(|row| matches_all(&row[0])).map(|row| row[1..].to_vec()).collect();
+                return self.missing_rows(&rest_rows, &types[1..]).into_iter().map(|mut row| {
+                    row.insert(0, "_".to_string());
+                    row
+                }).collect();
+            }
+        };
+
+        let mut missing = vec![];
+        for (value_name, field_types, value_rows) in values {
+            if value_rows.is_empty() {
+                let mut row = vec![if field_types.is_empty() { value_name } else { format!("{}(..)", value_name) }];
+                row.extend(vec!["_".to_string(); types.len() - 1]);
+                missing.push(row);
+                continue;
+            }
+            let mut column_types = field_types.clone();
+            column_types.extend_from_slice(&types[1..]);
+            for mut fields in self.missing_rows(&value_rows, &column_types) {
+                let rest = fields.split_off(field_types.len());
+                let mut row = vec![if field_types.is_empty() { value_name.clone() } else { format!("{}({})", value_name, fields.join(", ")) }];
+                row.extend(rest);
+                missing.push(row);
+            }
+        }
+        missing
+    }
+
+    fn visit_match_expr(&mut self, 
+        match_token: &mut Token, 
+        scrutinee: &mut Box<Expr>, 
+        arms: &mut Vec<(Pattern, Box<Stmt>)>, 
+        datatype: &mut Datatype) -> Datatype {
+
+        let scrutinee_type = self.visit_expr(scrutinee);
+
+        let mut arm_types: Vec<Datatype> = vec![];
+        let mut is_value = true; /* every arm is an expression */
+        for (pattern, body) in arms.iter_mut() {
+            /* bindings of an arm are visible only in its body */
+            self.symbol_table.variable_table.push_scope();
+            self.check_pattern(pattern, &scrutinee_type);
+            match &mut **body {
+                Stmt::Expression{expr, ..} => arm_types.push(self.visit_expr(expr)),
+                _ => {
+                    is_value = false;
+                    self.visit_stmt(body);
+                }
+            }
+            self.symbol_table.variable_table.pop_scope();
+        }
+
+        for (i, (pattern, _)) in arms.iter().enumerate() {
+            if pattern.is_irrefutable() && i + 1 < arms.len() {
+                let (next_pattern, _) = &arms[i + 1];
+                let token = match next_pattern {
+                    Pattern::Wildcard{token} => token,
+                    Pattern::Binding{name} => name,
+                    Pattern::Literal{value} => value,
+                    Pattern::Variant{enum_name, ..} => enum_name
+                };
+                self.diagnostics.warning(W_UNREACHABLE_ARM, token.span, 
+                    "Unreachable match arm. A previous arm matches every value.".to_string());
+                break;
+            }
+        }
+
+        let patterns: Vec<&Pattern> = arms.iter().map(|(pattern, _)| pattern).collect();
+        let missing = self.missing_patterns(&patterns, &scrutinee_type);
+        if missing == vec!["_".to_string()] {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, match_token.span, 
+                format!("Match on `{}` is not exhaustive. Add a `_` arm.", scrutinee_type.type_name()));
+        } else if !missing.is_empty() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, match_token.span, 
+                format!("Match is not exhaustive. Patterns not covered: {}", missing.join(", ")));
+        }
+
+        *datatype = Datatype::yet_to_infer;
+        if is_value && !arm_types.is_empty() && !arm_types.contains(&Datatype::yet_to_infer) {
+            if arm_types.iter().all(|dtype| *dtype == arm_types[0]) {
+                *datatype = arm_types[0].clone();
+            } else {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, match_token.span, 
+                    format!("Arms of match have different types: {}", 
+                        arm_types.iter().map(|dtype| format!("`{}`", dtype.type_name())).collect::<Vec<String>>().join(", ")));
+            }
+        }
+        return (*datatype).clone();
+    }
+
+    /* declares a local in the innermost scope. Shadowing a variable of an enclosing scope is
+       allowed, declaring the same name twice in one scope is not */
+    fn declare_variable(&mut self, name: &Token, dtype: Datatype) {
+        if let Some(previous) = self.symbol_table.variable_table.declare(name, dtype) {
+            self.has_errors = true;
+            self.diagnostics.emit(Diagnostic::error(E_DUPLICATE_VARIABLE, name.span, 
+                    format!("Variable `{}` is already declared in this scope", name.value))
+                .with_label(previous.span, "first declared here".to_string())
+                .with_suggestion("declare it in an inner block to shadow it, or assign to it instead".to_string()));
+        }
+    }
+
+    /* looks up `name` in the bodies enclosing the innermost `depth` closures and records it 
+       as captured by every closure between its declaration and the use */
+    fn capture_variable(&mut self, name: &Token, depth: usize) -> Option<Datatype> {
+        if depth == 0 { return None; }
+        let frame = depth - 1;
+        let dtype = match self.closures[frame].0.lookup(&name.value) {
+            Some(dtype) => dtype.clone(),
+            _ => {
+                let dtype = self.capture_variable(name, frame)?;
+                self.closures[frame].0.insert(name.value.clone(), dtype.clone());
+                dtype
+            }
+        };
+
+        if !self.closures[frame].1.iter().any(|(captured, _)| captured.value == name.value) {
+            self.closures[frame].1.push((name.clone(), dtype.clone()));
+        }
+        return Some(dtype);
+    }
+
+    fn visit_closure_expr(&mut self, 
+        func_token: &mut Token, 
+        parameters: &mut Vec<(Token, Datatype)>, 
+        returntype: &mut Datatype, 
+        body: &mut Box<Stmt>, 
+        captures: &mut Vec<(Token, Datatype)>, 
+        datatype: &mut Datatype) -> Datatype {
+        for param in parameters.iter_mut() {
+            param.1 = self.resolve_datatype(&param.1, &param.0);
+        }
+        *returntype = self.resolve_datatype(returntype, func_token);
+
+        /* variables of the enclosing body are only reachable by capturing them */
+        let enclosing = std::mem::take(&mut self.symbol_table.variable_table);
+        self.closures.push((enclosing, vec![]));
+        for param in parameters.iter() {
+            self.symbol_table.variable_table.insert(param.0.value.clone(), param.1.clone());
+        }
+
+        let loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        let local_refs = std::mem::take(&mut self.local_refs);
+        self.returntypes.push(returntype.clone());
+        self.visit_stmt(body);
+        self.check_implicit_return(body);
+        self.check_closure_result(func_token, returntype, body);
+        self.returntypes.pop();
+        self.local_refs = local_refs;
+        self.loop_depth = loop_depth;
+
+        let (enclosing, closure_captures) = self.closures.pop().unwrap();
+        self.symbol_table.variable_table = enclosing;
+        // the closure keeps its copies after the enclosing function returns
+        for (captured, _) in closure_captures.iter() {
+            if let Some(local) = self.local_refs.get(&captured.value) {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, captured.span, 
+                    format!("Closure can't capture `{}`, which holds a reference to local variable `{}`", 
+                        captured.value, local));
+            }
+        }
+        *captures = closure_captures;
+
+        *datatype = Datatype::function_value(
+            returntype.clone(), 
+            parameters.iter().map(|(_, dtype)| dtype.clone()).collect()
+        );
+        return (*datatype).clone();
+    }
+
+    /* `&mut T` can be used where `&T` is expected */
+    fn ref_coerces(from: &Datatype, to: &Datatype) -> bool {
+        match (from, to) {
+            (Datatype::reference{elem_type: from_elem, mutable: true}, Datatype::reference{elem_type: to_elem, mutable: false})
+                => from_elem == to_elem,
+            _ => false
+        }
+    }
+
+    /* expressions denoting a storage location, which can be referenced and assigned to */
+    fn is_place(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Variable{name, ..} => self.symbol_table.variable_table.contains_key(&name.value),
+            Expr::AttributeRef{datatype: Datatype::function{obj_name: Some(_), ..}, ..} => false,
+            Expr::AttributeRef{..} | Expr::Index{..} | Expr::Deref{..} => true,
+            Expr::Grouping{expr, ..} => self.is_place(expr),
+            Expr::ExprList{expr_list, ..} if expr_list.len() == 1 => self.is_place(&expr_list[0]),
+            _ => false
+        }
+    }
+
+    /* whether a place is reached through a `&` reference, which doesn't allow modifying it */
+    fn is_behind_shared_ref(&self, place: &Expr) -> bool {
+        match place {
+            Expr::AttributeRef{object_dtype: Datatype::reference{mutable, ..}, ..} => !*mutable,
+            Expr::Index{array, ..} if Datatype::is_reference(&array.get_datatype()) => match array.get_datatype() {
+                Datatype::reference{mutable, ..} => !mutable,
+                _ => false
+            },
+            Expr::AttributeRef{object, ..} | Expr::Index{array: object, ..} => self.is_behind_shared_ref(object),
+            Expr::Deref{operand, ..} => match operand.get_datatype() {
+                Datatype::reference{mutable, ..} => !mutable,
+                _ => false
             },
-            "printu64" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::yet_to_infer),
-                param_types: vec![Box::new(Datatype::uint64)]
+            Expr::Grouping{expr, ..} => self.is_behind_shared_ref(expr),
+            Expr::ExprList{expr_list, ..} if expr_list.len() == 1 => self.is_behind_shared_ref(&expr_list[0]),
+            _ => false
+        }
+    }
+
+    fn check_assignable(&mut self, target: &Expr, operator: &Token) {
+        if self.is_behind_shared_ref(target) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, operator.span, 
+                "Cannot assign through a `&` reference. Use a `&mut` reference to modify the value".to_string());
+        }
+    }
+
+    fn visit_address_of_expr(&mut self, amp_token: &mut Token, mutable: &mut bool, 
+        operand: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
+        let dtype = self.visit_expr(operand);
+        *datatype = Datatype::yet_to_infer;
+        if !self.is_place(operand) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, amp_token.span, 
+                "Only variables, fields, array elements and dereferenced values can be referenced with '&'".to_string());
+            return Datatype::yet_to_infer;
+        }
+        if *mutable && self.is_behind_shared_ref(operand) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, amp_token.span, 
+                "Cannot take a `&mut` reference to a value behind a `&` reference".to_string());
+        }
+
+        if dtype != Datatype::yet_to_infer {
+            *datatype = Datatype::reference{elem_type: Box::new(dtype), mutable: *mutable};
+        }
+        return (*datatype).clone();
+    }
+
+    fn visit_deref_expr(&mut self, star_token: &mut Token, operand: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
+        let dtype = self.visit_expr(operand);
+        *datatype = match dtype {
+            Datatype::reference{elem_type, ..} | Datatype::rc{elem_type} => *elem_type,
+            Datatype::yet_to_infer => Datatype::yet_to_infer,
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_TYPE, star_token.span, 
+                    format!("Cannot dereference a value of type `{}`, which is not a reference or an rc", dtype.type_name()));
+                Datatype::yet_to_infer
+            }
+        };
+        return (*datatype).clone();
+    }
+
+    fn visit_new_expr(&mut self, new_token: &mut Token, value: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
+        let dtype = self.visit_expr(value);
+        *datatype = Datatype::yet_to_infer;
+        if dtype == Datatype::null_ref() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, new_token.span, 
+                "Cannot allocate `null` with 'new'".to_string());
+        } else if dtype != Datatype::yet_to_infer {
+            *datatype = Datatype::reference{elem_type: Box::new(dtype), mutable: true};
+        }
+        return (*datatype).clone();
+    }
+
+    fn visit_make_rc_expr(&mut self, rc_token: &mut Token, value: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
+        let dtype = self.visit_expr(value);
+        *datatype = Datatype::yet_to_infer;
+        if dtype == Datatype::null_ref() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, rc_token.span, 
+                "Cannot make an rc of `null`".to_string());
+        } else if dtype != Datatype::yet_to_infer {
+            *datatype = Datatype::rc{elem_type: Box::new(dtype)};
+        }
+        return (*datatype).clone();
+    }
+
+    /* whether values of the type hold rcs, whose counts change when the values are copied */
+    fn contains_rc(&self, dtype: &Datatype) -> bool {
+        match dtype {
+            Datatype::rc{..} => true,
+            Datatype::array{elem_type, ..} => self.contains_rc(elem_type),
+            Datatype::tuple{elem_types} => elem_types.iter().any(|elem_type| self.contains_rc(elem_type)),
+            Datatype::object{name} => match self.symbol_table.struct_decls.get(name) {
+                Some(Decl::StructDecl{fields, ..}) => fields.iter().any(|(_, field_type)| self.contains_rc(field_type)),
+                _ => false
             },
-            "printf32" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::yet_to_infer),
-                param_types: vec![Box::new(Datatype::float32)]
+            _ => false
+        }
+    }
+
+    /* `null` takes the reference or rc type it is used as */
+    fn coerce_null(expr: &mut Expr, from: &Datatype, to: &Datatype) -> bool {
+        if *from != Datatype::null_ref() || !(Datatype::is_reference(to) || Datatype::is_rc(to)) || *to == Datatype::null_ref() {
+            return false;
+        }
+        match expr {
+            Expr::Literal{datatype, ..} => {
+                *datatype = to.clone();
+                true
             },
-            "printf64" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::yet_to_infer),
-                param_types: vec![Box::new(Datatype::float64)]
+            Expr::Grouping{expr, datatype, ..} => {
+                *datatype = to.clone();
+                TypeChecker::coerce_null(expr, from, to)
             },
-            "scanbool" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::bool),
-                param_types: vec![]
+            Expr::ExprList{expr_list, datatype, ..} if expr_list.len() == 1 => {
+                *datatype = to.clone();
+                TypeChecker::coerce_null(&mut expr_list[0], from, to)
             },
-            "printbool" => Datatype::function {
-                name: func_name,
-                obj_name: None,
-                returntype: Box::new(Datatype::yet_to_infer),
-                param_types: vec![Box::new(Datatype::bool)]
+            _ => false
+        }
+    }
+
+    /* local variable whose storage a place refers to. 
+       None for storage reached through `self` or a reference not pointing to a local, which belongs to a caller */
+    fn place_root(&self, place: &Expr) -> Option<String> {
+        match place {
+            Expr::Variable{name, ..} if name.value != "self" => Some(name.value.clone()),
+            Expr::AttributeRef{object, object_dtype: Datatype::reference{..}, ..} => self.local_borrow(object),
+            Expr::Index{array, ..} if Datatype::is_reference(&array.get_datatype()) => self.local_borrow(array),
+            Expr::AttributeRef{object, ..} | Expr::Index{array: object, ..} => self.place_root(object),
+            Expr::Deref{operand, ..} => self.local_borrow(operand),
+            Expr::Grouping{expr, ..} => self.place_root(expr),
+            Expr::ExprList{expr_list, ..} if expr_list.len() == 1 => self.place_root(&expr_list[0]),
+            _ => None
+        }
+    }
+
+    fn describe_local(local: &String) -> String {
+        if local == TEMPORARY { "a temporary value".to_string() } else { format!("local variable `{}`", local) }
+    }
+
+    /* local variable of the function being checked that a value refers to, if any. 
+       Values built from references (structs, arrays, several values) refer to what those references do */
+    fn local_borrow(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::AddressOf{operand, ..} => self.place_root(operand),
+            Expr::Variable{name, ..} => self.local_refs.get(&name.value).cloned(),
+            Expr::AttributeRef{..} | Expr::Index{..}
+                => self.place_root(expr).and_then(|root| self.local_refs.get(&root).cloned()),
+            Expr::Grouping{expr, ..} => self.local_borrow(expr),
+            Expr::ExprList{expr_list: values, ..} | Expr::ArrayLiteral{elements: values, ..}
+                => values.iter().find_map(|value| self.local_borrow(value)),
+            Expr::StructExpr{fields, ..} => fields.iter().find_map(|(_, value)| self.local_borrow(value)),
+            Expr::New{value, ..} | Expr::MakeRc{value, ..} => self.local_borrow(value),
+            /* a trait object points to the struct it is made from, 
+               a struct that isn't stored in a variable is copied to a temporary of the function first */
+            Expr::Cast{variable, from_dtype: Datatype::object{..}, to_dtype: Datatype::dyn_trait{..}, ..}
+                => if self.is_place(variable) { 
+                    self.place_root(variable) 
+                } else { 
+                    Some(TEMPORARY.to_string()) 
+                },
+            Expr::Cast{variable, to_dtype: Datatype::dyn_trait{..}, ..} => self.local_borrow(variable),
+            /* a function can return a reference it was given */
+            Expr::Call{arguments, datatype: Datatype::reference{..}, ..}
+                => arguments.iter().find_map(|argument| self.local_borrow(argument)),
+            _ => None
+        }
+    }
+
+    /* remembers the variables holding references to locals. Storing such a reference anywhere else 
+       would let it outlive the function */
+    fn track_local_ref(&mut self, target: &Expr, value: &Expr, operator: &Token) {
+        match self.local_borrow(value) {
+            Some(local) => match self.place_root(target) {
+                Some(root) => { self.local_refs.insert(root, local); },
+                _ => {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, operator.span, 
+                        format!("Reference to {} can't be stored outside of its function", TypeChecker::describe_local(&local)));
+                }
             },
-            _ => Datatype::yet_to_infer
+            _ => if let Expr::Variable{name, ..} = target {
+                self.local_refs.remove(&name.value);
+            }
         }
     }
 
-    fn visit_cast_expr(&mut self, 
-        variable: &mut Box<Expr>, 
-        casttype: &mut Token, 
-        from_dtype: &mut Datatype,
-        to_dtype: &mut Datatype) -> Datatype {
-        let var_type = self.visit_expr(variable);
-        *from_dtype = var_type.clone();
-        let mut cast_type = Datatype::get_datatype(&casttype.tok_type);
-        if let Datatype::object{..} = cast_type {
-            // check if a struct of given casttype exists
-            if self.symbol_table.struct_decls.contains_key(&casttype.value) {
-                cast_type = Datatype::object{name: casttype.value.clone()};
-            } else {
-                // casttype doesnt exist.
-                self.has_errors = true;
-                log_message(logger::LogLevel::ERROR, casttype.col, casttype.line, 
-                    "Given cast type doesn't exist. Make sure to declare structs before using.".to_string());
-                *to_dtype = var_type.clone();
-                return var_type;
+    fn check_escaping_ref(&mut self, expr: &Expr, span: Span, context: &str) {
+        if let Some(local) = self.local_borrow(expr) {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, span, 
+                format!("Reference to {} can't {}", TypeChecker::describe_local(&local), context));
+        }
+    }
+
+    /* the value of the last expression statement of a body is returned as well */
+    fn check_implicit_return(&mut self, body: &Stmt) {
+        if let Stmt::Block{statements, ..} = body {
+            if let Some(last) = statements.last() {
+                if let Stmt::Expression{expr, ..} = &**last {
+                    self.check_escaping_ref(expr, expr.span(), "be returned from its function");
+                }
             }
         }
+    }
 
-        // if both are objects, check if they are castable while converting to LLVM IR by matching 
+    /* a closure that doesn't end in `return` gives its trailing value, which needs to be of its return type */
+    fn check_closure_result(&mut self, func_token: &Token, returntype: &Datatype, body: &mut Stmt) {
+        if *returntype == Datatype::unit() || *returntype == Datatype::yet_to_infer || TypeChecker::always_returns(body) {
+            return;
+        }
+        let trailing = match body {
+            Stmt::Block{statements, ..} => statements.last_mut(),
+            _ => None
+        };
+        match trailing.map(|stmt| &mut **stmt) {
+            Some(Stmt::Expression{expr, ..}) => {
+                let dtype = expr.get_datatype();
+                if dtype == *returntype || dtype == Datatype::yet_to_infer || TypeChecker::coerce_null(expr, &dtype, returntype) {
+                    return;
+                }
+                self.has_errors = true;
+                self.diagnostics.error(E_RETURN_TYPE, expr.span(), 
+                    format!("Closure returns `{}` but its trailing value is of type `{}`", 
+                        returntype.type_name(), dtype.type_name()));
+            },
+            _ => {
+                self.has_errors = true;
+                self.diagnostics.error(E_RETURN_TYPE, func_token.span, 
+                    format!("Closure returning `{}` can reach the end of its body without returning a value", 
+                        returntype.type_name()));
+            }
+        }
+    }
 
+    /* whether every path through the statement ends in `return` */
+    fn always_returns(stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Return{..} | Stmt::Error{..} => true,
+            Stmt::Block{statements, ..} => statements.iter().any(|stmt| TypeChecker::always_returns(stmt)),
+            Stmt::If{then_block, else_block: Some(else_block), ..}
+                => TypeChecker::always_returns(then_block) && TypeChecker::always_returns(else_block),
+            Stmt::Loop{count: None, block, ..} => !TypeChecker::breaks_out(block),
+            Stmt::Select{cases, ..} => !cases.is_empty() && cases.iter().all(|(_, block)| TypeChecker::always_returns(block)),
+            _ => false
+        }
+    }
 
-        if var_type == Datatype::yet_to_infer { 
-            *to_dtype = var_type;
-            return Datatype::yet_to_infer; 
+    /* whether a `break` in the statement leaves the loop whose body it is */
+    fn breaks_out(stmt: &Stmt) -> bool {
+        match stmt {
+            Stmt::Break{..} => true,
+            Stmt::Block{statements, ..} => statements.iter().any(|stmt| TypeChecker::breaks_out(stmt)),
+            Stmt::If{then_block, else_block, ..}
+                => TypeChecker::breaks_out(then_block) || else_block.as_ref().map_or(false, |stmt| TypeChecker::breaks_out(stmt)),
+            Stmt::Select{cases, ..} => cases.iter().any(|(_, block)| TypeChecker::breaks_out(block)),
+            _ => false
         }
-        *to_dtype = cast_type.clone();
-        return cast_type.clone();
     }
 
     fn visit_grouping_expr(&mut self, expr: &mut Box<Expr>, datatype: &mut Datatype) -> Datatype {
@@ -370,11 +2097,93 @@ impl TypeChecker {
     }
 
 
+    /* only checked when several values are involved. Mismatches of single values are left to codegen, as before */
+    fn check_returned_values(&mut self, returntype: &Datatype, dtype: &Datatype, span: Span) {
+        if *dtype == Datatype::yet_to_infer || *dtype == *returntype {
+            return;
+        }
+        let value_count = |dtype: &Datatype| match dtype {
+            Datatype::tuple{elem_types} => elem_types.len(),
+            _ => 1
+        };
+        if value_count(returntype) == 1 && value_count(dtype) == 1 {
+            return;
+        }
+
+        self.has_errors = true;
+        if value_count(returntype) != value_count(dtype) {
+            self.diagnostics.error(E_RETURN_TYPE, span, 
+                format!("Function returns {} values of type `{}` but {} are returned", 
+                    value_count(returntype), returntype.type_name(), value_count(dtype)));
+        } else {
+            self.diagnostics.error(E_RETURN_TYPE, span, 
+                format!("Returned values of type `{}` don't match return type `{}`", 
+                    dtype.type_name(), returntype.type_name()));
+        }
+    }
+
+    /* `a, b = f()`. Values are matched to targets by position. */
+    fn visit_multi_assignment_expr(&mut self, targets: &mut Vec<Box<Expr>>, 
+                expr: &mut Box<Expr>, operator: &mut Token, datatype: &mut Datatype) -> Datatype {
+        let mut target_types = vec![];
+        for target in targets.iter_mut() {
+            target_types.push(self.visit_expr(target));
+            self.check_assignable(target, operator);
+        }
+        let value_type = self.visit_expr(expr);
+        if value_type == Datatype::yet_to_infer {
+            *datatype = Datatype::yet_to_infer;
+            return Datatype::yet_to_infer;
+        }
+
+        let value_types = match &value_type {
+            Datatype::tuple{elem_types} => elem_types.clone(),
+            _ => vec![value_type.clone()]
+        };
+        if value_types.len() != target_types.len() {
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, operator.span, 
+                format!("Assignment to {} targets from {} value(s) of type `{}`", 
+                    target_types.len(), value_types.len(), value_type.type_name()));
+            *datatype = Datatype::yet_to_infer;
+            return Datatype::yet_to_infer;
+        }
+
+        let mut has_error = false;
+        for (i, (target_type, value_type)) in target_types.iter().zip(value_types.iter()).enumerate() {
+            if *target_type != Datatype::yet_to_infer && target_type != value_type 
+                && !TypeChecker::ref_coerces(value_type, target_type) {
+                self.has_errors = true;
+                has_error = true;
+                self.diagnostics.error(E_TYPE, operator.span, 
+                    format!("Value {} of type `{}` can't be assigned to target of type `{}`", 
+                        i+1, value_type.type_name(), target_type.type_name()));
+            }
+        }
+
+        if let Expr::ExprList{expr_list: values, ..} = &**expr {
+            for (target, value) in targets.iter().zip(values.iter()) {
+                self.track_local_ref(target, value, operator);
+            }
+        } else {
+            for target in targets.iter() {
+                self.track_local_ref(target, expr, operator);
+            }
+        }
+
+        *datatype = if has_error { Datatype::yet_to_infer } else { value_type };
+        return (*datatype).clone();
+    }
+
     fn visit_assignment_expr(&mut self, target: &mut Box<Expr>, 
                 expr: &mut Box<Expr>, operator: &mut Token, datatype: &mut Datatype) -> Datatype {
+        if let Expr::ExprList{expr_list: targets, ..} = &mut **target {
+            return self.visit_multi_assignment_expr(targets, expr, operator, datatype);
+        }
                     
         let lhs_datatype: Datatype = self.visit_expr(target);
         let rhs_datatype: Datatype = self.visit_expr(expr);
+        self.check_assignable(target, operator);
             
         if lhs_datatype == Datatype::yet_to_infer || rhs_datatype == Datatype::yet_to_infer {
             return Datatype::yet_to_infer;
@@ -382,12 +2191,14 @@ impl TypeChecker {
                     
         let mut has_error: bool = false;
                     
-        if lhs_datatype != rhs_datatype {
+        if lhs_datatype != rhs_datatype && !TypeChecker::ref_coerces(&rhs_datatype, &lhs_datatype)
+            && !TypeChecker::coerce_null(expr, &rhs_datatype, &lhs_datatype)
+            && !self.coerce_to_dyn(expr, &rhs_datatype, &lhs_datatype) {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, 
-            operator.col, operator.line, "Operand types mismatch".to_string());
+            self.diagnostics.error(E_TYPE, operator.span, "Operand types mismatch".to_string());
             has_error = true;
         }
+        self.track_local_ref(target, expr, operator);
 
         *datatype = if has_error {Datatype::yet_to_infer} else {lhs_datatype.clone()};
         return (*datatype).clone();
@@ -398,12 +2209,18 @@ impl TypeChecker {
         let mut has_error = false;
         
         
-        if let Some(decl) = self.symbol_table.struct_decls.get(&struct_name.value)/* .as_mut()*/ {
+        if self.symbol_table.templates.contains_key(&struct_name.value) 
+            && !self.infer_struct_instance(struct_name, field_vals) {
+            *datatype = Datatype::yet_to_infer;
+            return Datatype::yet_to_infer;
+        }
+
+        if let Some(decl) = self.get_struct_decl(&struct_name.value)/* .as_mut()*/ {
             match decl {
-                Decl::StructDecl{name, fields} => {
+                Decl::StructDecl{name, fields, ..} => {
 
                     // creating a hashmap for fields declaration
-                    let mut field_decl_map: HashMap<String, Token> = HashMap::new();
+                    let mut field_decl_map: HashMap<String, Datatype> = HashMap::new();
                     for field_decl in fields {
                         field_decl_map.insert(field_decl.0.value.clone(), field_decl.1.clone());
                     }
@@ -420,9 +2237,11 @@ impl TypeChecker {
 
                                 let dtype = self.visit_expr(field_expr);
 
-                                if dtype != Datatype::get_datatype(&field_type.tok_type) {
+                                if dtype != *field_type && !TypeChecker::ref_coerces(&dtype, field_type)
+                                    && !TypeChecker::coerce_null(field_expr, &dtype, field_type)
+                                    && !self.coerce_to_dyn(field_expr, &dtype, field_type) {
                                     self.has_errors = true;
-                                    log_message(logger::LogLevel::ERROR, field_val.col, field_val.line, 
+                                    self.diagnostics.error(E_TYPE, field_val.span, 
                                         "Datatype of expression being assigned doesn't match type declaration in struct".to_string());
                                     has_error = true;
                                 }
@@ -430,7 +2249,7 @@ impl TypeChecker {
                             },
                             _ => {
                                 self.has_errors = true;
-                                log_message(logger::LogLevel::ERROR, field_val.col, field_val.line, 
+                                self.diagnostics.error(E_TYPE, field_val.span, 
                                     "Couldn't find field name in struct declaration".to_string());
                                 has_error = true;
                             }
@@ -449,7 +2268,7 @@ impl TypeChecker {
                         }
 
                         self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, struct_name.col, struct_name.line, 
+                        self.diagnostics.error(E_TYPE, struct_name.span, 
                             format!("Some fields were missing from expression: {:?}", missing_fields));
                         has_error = true;
                     }
@@ -461,28 +2280,35 @@ impl TypeChecker {
             return (*datatype).clone();
         } else {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, struct_name.col, struct_name.line, 
+            self.diagnostics.error(E_TYPE, struct_name.span, 
                 "Couldn't find struct declaration of given name. Make sure to declare struct before using it.".to_string());
             return Datatype::yet_to_infer;
         }
     }
 
     fn visit_binary_expr(&mut self, lhs:&mut Box<Expr>, rhs: &mut Box<Expr>, operator: &mut Token, datatype: &mut Datatype) -> Datatype {
-        let lhs_datatype: Datatype = self.visit_expr(lhs);
-        let rhs_datatype: Datatype = self.visit_expr(rhs);
+        let mut lhs_datatype: Datatype = self.visit_expr(lhs);
+        let mut rhs_datatype: Datatype = self.visit_expr(rhs);
         
 
         eprintln!("Binary expr :::: Lhs type: {:?}, Rhs type: {:?}", lhs_datatype, rhs_datatype);
         if lhs_datatype == Datatype::yet_to_infer || rhs_datatype == Datatype::yet_to_infer {
             return Datatype::yet_to_infer;
         }
+
+        // `p == null`
+        if TypeChecker::coerce_null(rhs, &rhs_datatype, &lhs_datatype) {
+            rhs_datatype = lhs_datatype.clone();
+        } else if TypeChecker::coerce_null(lhs, &lhs_datatype, &rhs_datatype) {
+            lhs_datatype = rhs_datatype.clone();
+        }
         
         let mut has_error: bool = false;
         
-        if lhs_datatype != rhs_datatype {
+        if lhs_datatype != rhs_datatype && !TypeChecker::ref_coerces(&lhs_datatype, &rhs_datatype)
+            && !TypeChecker::ref_coerces(&rhs_datatype, &lhs_datatype) {
             self.has_errors = true;
-            logger::log_message(logger::LogLevel::ERROR, 
-                operator.col, operator.line, "Operand types mismatch".to_string());
+            self.diagnostics.error(E_TYPE, operator.span, "Operand types mismatch".to_string());
                 has_error = true;
         }
         
@@ -495,10 +2321,10 @@ impl TypeChecker {
                 //     has_error = true;
                 // }
                 match lhs_datatype {
-                    Datatype::bool|Datatype::object{..} => {
+                    Datatype::bool|Datatype::object{..}|Datatype::string|Datatype::char => {
                         self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
-                            "LHS of operator is either an object or bool. Operation can't be performed".to_string());
+                        self.diagnostics.error(E_TYPE, operator.span, 
+                            "LHS of operator is either an object, string, char or bool. Operation can't be performed".to_string());
                         has_error = true;
                     },
                     _ => ()
@@ -510,10 +2336,10 @@ impl TypeChecker {
                 //     has_error = true;
                 // }
                 match rhs_datatype {
-                    Datatype::bool|Datatype::object{..} => {
+                    Datatype::bool|Datatype::object{..}|Datatype::string|Datatype::char => {
                         self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
-                            "RHS of operator is either an object or bool. Operation can't be performed.".to_string());
+                        self.diagnostics.error(E_TYPE, operator.span, 
+                            "RHS of operator is either an object, string, char or bool. Operation can't be performed.".to_string());
                         has_error = true;
                     },
                     _ => ()
@@ -523,14 +2349,14 @@ impl TypeChecker {
             TokenType::BITWISE_AND|TokenType::BITWISE_OR|TokenType::BITWISE_XOR => {
                 if !Datatype::get_int_types().contains(&lhs_datatype) && lhs_datatype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "LHS of operator is neither integer type nor boolean type. Operation can't be performed".to_string());
                     has_error = true;
                 }
 
                 if !Datatype::get_int_types().contains(&rhs_datatype) && rhs_datatype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "RHS of operator is neither integer type nor boolean type. Operation can't be performed".to_string());
                     has_error = true;
                 }
@@ -539,7 +2365,7 @@ impl TypeChecker {
             TokenType::LEFT_SHIFT | TokenType::RIGHT_SHIFT => {
                 if !Datatype::get_int_types().contains(&lhs_datatype) || !Datatype::get_int_types().contains(&rhs_datatype) {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "One of the operands is not integer. Operation can't be performed".to_string());
                     has_error = true;
                 }
@@ -551,20 +2377,34 @@ impl TypeChecker {
                 // if lhs_datatype == Datatype::object {
                 //     // find a way to match object types. Or make this error check after converting to llvm ir.
                 // }
+                if lhs_datatype == Datatype::string 
+                    && operator.tok_type != TokenType::EQUAL_EQUAL && operator.tok_type != TokenType::BANG_EQUAL {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, operator.span, 
+                        "Strings can only be compared using '==' and '!='".to_string());
+                    return Datatype::yet_to_infer;
+                }
+                if (Datatype::is_reference(&lhs_datatype) || Datatype::is_rc(&lhs_datatype))
+                    && operator.tok_type != TokenType::EQUAL_EQUAL && operator.tok_type != TokenType::BANG_EQUAL {
+                    self.has_errors = true;
+                    self.diagnostics.error(E_TYPE, operator.span, 
+                        "References and rcs can only be compared using '==' and '!='".to_string());
+                    return Datatype::yet_to_infer;
+                }
                 return Datatype::bool;
             },
             
             TokenType::K_AND|TokenType::K_OR => {
                 if lhs_datatype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                                 "LHS of logical operations needs to be of type 'bool'".to_string());
                     has_error = true;
                 }
                 
                 if rhs_datatype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                                 "RHS of logical operations needs to be of type 'bool'".to_string());
                     has_error = true;
                 }
@@ -584,7 +2424,7 @@ impl TypeChecker {
             TokenType::BANG => {
                 if dtype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "Operand for '!' needs to be of boolean type".to_string());
                     has_error = true; 
                 }
@@ -593,7 +2433,7 @@ impl TypeChecker {
             TokenType::PLUS => {
                 if !Datatype::get_int_types().contains(&dtype) || !Datatype::get_float_types().contains(&dtype) {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "Can't use unary '+' on types other than integers or floats".to_string());
                         has_error = true;
                 }
@@ -602,7 +2442,7 @@ impl TypeChecker {
             TokenType::MINUS => {
                 if !Datatype::get_signed_types().contains(&dtype) || !Datatype::get_float_types().contains(&dtype) {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "Can't use unary '-' on types other than signed integers and floats".to_string());
                     has_error = true;
                 }
@@ -611,7 +2451,7 @@ impl TypeChecker {
             TokenType::BITWISE_NOT => {
                 if !Datatype::get_int_types().contains(&dtype) || dtype != Datatype::bool {
                     self.has_errors = true;
-                    log_message(logger::LogLevel::ERROR, operator.col, operator.line, 
+                    self.diagnostics.error(E_TYPE, operator.span, 
                         "Bitwise NOT can't be performed on types other than integers and boolean".to_string());
                     has_error;
                 }
@@ -626,7 +2466,10 @@ impl TypeChecker {
 
     fn visit_literal_expr(&mut self, value: &mut Token, datatype: &mut Datatype) -> Datatype {
         // unimplemented!()
-        *datatype = Datatype::get_datatype(&value.tok_type);
+        *datatype = match value.tok_type {
+            TokenType::K_TRUE | TokenType::K_FALSE => Datatype::bool,
+            _ => Datatype::get_datatype(&value.tok_type)
+        };
         eprintln!("Literal expr type: {:?}", *datatype);
         return (*datatype).clone();
     }
@@ -634,6 +2477,24 @@ impl TypeChecker {
     fn visit_call_expr(&mut self, callee: &mut Box<Expr>, arguments: &mut Vec<Box<Expr>>/*&mut Box<Expr>*/,
                          datatype: &mut Datatype) -> Datatype {
 
+        if let Expr::Variable{name, ..} = &mut **callee {
+            self.resolve_module_function(name);
+            if self.symbol_table.templates.contains_key(&name.value) {
+                let mut arg_types = vec![];
+                for argument in arguments.iter_mut() {
+                    arg_types.push(self.visit_expr(argument));
+                }
+                if !self.instantiate_function(name, &arg_types) {
+                    *datatype = Datatype::yet_to_infer;
+                    return Datatype::yet_to_infer;
+                }
+            }
+        }
+
+        let (callee_name, callee_span) = match &**callee {
+            Expr::Variable{name, ..} | Expr::AttributeRef{name, ..} => (name.value.clone(), name.span),
+            other => ("closure".to_string(), other.span())
+        };
         let func_type = self.visit_expr(callee);
 
         // let func_name;
@@ -644,20 +2505,25 @@ impl TypeChecker {
 
             if arguments.len() != param_types.len() {
                 self.has_errors = true;
-                logger::log_message(logger::LogLevel::ERROR, usize::MAX, usize::MAX, 
-                    format!("Arity not right when calling {:?}: ({:?}) Parameters in definition but {:?} arguments provided",
-                        if obj_name.is_some() { obj_name.clone().unwrap()+func_name.as_str()} else {func_name.clone()}, 
-                        param_types.len(), arguments.len())
+                self.diagnostics.emit(Diagnostic::error(E_ARITY, callee_span, 
+                        format!("Arity not right when calling {:?}: ({:?}) Parameters in definition but {:?} arguments provided",
+                            if obj_name.is_some() { obj_name.clone().unwrap()+"."+func_name.as_str()} else {callee_name.clone()}, 
+                            param_types.len(), arguments.len()))
+                    .with_note(format!("expected parameters: ({})", 
+                        param_types.iter().map(|dtype| dtype.type_name()).collect::<Vec<String>>().join(", ")))
                 );
                 return Datatype::yet_to_infer;
             }
             for (i, argument) in arguments.into_iter().enumerate() {
                 let arg_type = self.visit_expr(argument);
-                if arg_type != *param_types[i] {
+                if arg_type != *param_types[i] && !TypeChecker::ref_coerces(&arg_type, &param_types[i])
+                    && !TypeChecker::coerce_null(argument, &arg_type, &param_types[i])
+                    && !self.coerce_to_dyn(argument, &arg_type, &param_types[i]) {
                     self.has_errors = true;
-                    logger::log_message(logger::LogLevel::ERROR, usize::MAX, usize::MAX, 
-                        format!("Argument type not matching at param number {:?} for function {:?}",
-                            i+1, if obj_name.is_some() { obj_name.clone().unwrap()+func_name.as_str()} else {func_name.clone()})
+                    self.diagnostics.emit(Diagnostic::error(E_ARGUMENT_TYPE, callee_span, 
+                            format!("Argument type not matching at param number {:?} for function {:?}",
+                                i+1, if obj_name.is_some() { obj_name.clone().unwrap()+"."+func_name.as_str()} else {callee_name.clone()}))
+                        .with_note(format!("expected `{}`, found `{}`", param_types[i].type_name(), arg_type.type_name()))
                     );
                     return Datatype::yet_to_infer;
                 }
@@ -665,8 +2531,10 @@ impl TypeChecker {
 
             *datatype = *returntype;
             return (*datatype).clone();
-        } else {
-
+        } else if func_type != Datatype::yet_to_infer {
+            self.has_errors = true;
+            self.diagnostics.error(E_NOT_CALLABLE, callee_span, 
+                format!("`{}` of type `{}` can't be called", callee_name, func_type.type_name()));
         }
         return Datatype::yet_to_infer;
 
@@ -680,14 +2548,16 @@ impl TypeChecker {
     -> Datatype {
         let mut attr_name = name;
         *object_dtype = self.visit_expr(object);
+        /* fields and methods are reached through references as well */
+        let obj_type = object_dtype.auto_deref().clone();
 
-        if let Datatype::object{name: obj_name} = object_dtype {
-            if let Decl::StructDecl{name: struct_name, fields} 
-                = self.symbol_table.struct_decls.get(obj_name).unwrap() {
+        if let Datatype::object{name: obj_name} = &obj_type {
+            if let Some(Decl::StructDecl{name: struct_name, fields, ..}) 
+                = self.get_struct_decl(obj_name) {
                     
                     for (field_name, field_type) in fields {
                         if field_name.value == attr_name.value {
-                            *datatype = Datatype::get_tok_datatype(field_type);
+                            *datatype = field_type.clone();
                             return (*datatype).clone();
                         }
                     }
@@ -695,43 +2565,47 @@ impl TypeChecker {
             }
 
             
-            let impl_decls = self.symbol_table.impl_decls.get(obj_name).unwrap();
-            for impl_decl in impl_decls {
-                if let Decl::ImplDecl{name: struct_name, trait_name, funcs}
-                    = &**impl_decl {
-
-                        for func in funcs {
-
-                            if let Decl::FuncDef{prototype, block} = &**func {
-
-                                if let Decl::Prototype{name: func_name, parameters, returntype}
-                                    = &**prototype {
-
-                                    if func_name.value == attr_name.value {
-                                        // create Datattype::Function{}
-                                        let mut param_types = vec![];
-                                        let ret_type = Box::new(Datatype::get_tok_datatype(returntype));
-
-                                        for (param_name, param_type) in parameters {
-                                            param_types.push(Box::new(Datatype::get_tok_datatype(param_type)));
-                                        }
-
-                                        return Datatype::function{
-                                            name: func_name.value.clone(),
-                                            obj_name: Some(struct_name.value.clone()),
-                                            returntype: ret_type,
-                                            param_types
-                                        };
-                                    }
-
-                                }
+            if let Some(impl_decls) = self.symbol_table.impl_decls.get(obj_name) {
+                for func in impl_decls {
+                    if let Decl::FuncDef{prototype, ..} = &**func {
+                        if let Some(method_type) = TypeChecker::method_type(obj_name, prototype, &attr_name.value) {
+                            *datatype = method_type;
+                            return (*datatype).clone();
+                        }
+                    }
+                }
+            }
+        }
 
-                            }
+        if let Datatype::channel{..} = object_dtype {
+            if attr_name.value == "close" {
+                *datatype = Datatype::function{
+                    name: attr_name.value.clone(),
+                    obj_name: Some("chan".to_string()),
+                    returntype: Box::new(Datatype::yet_to_infer),
+                    param_types: vec![]
+                };
+                return (*datatype).clone();
+            }
 
-                        }
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, attr_name.span, 
+                format!("Channels don't have an attribute named `{}`. Only `close()` is available", attr_name.value));
+        }
 
+        if let Datatype::dyn_trait{name: trait_name} = &obj_type {
+            if let Some(trait_funcs) = self.symbol_table.trait_decls.get(trait_name) {
+                for func in trait_funcs {
+                    if let Some(method_type) = TypeChecker::method_type(trait_name, func.get_prototype(), &attr_name.value) {
+                        *datatype = method_type;
+                        return (*datatype).clone();
+                    }
                 }
             }
+
+            self.has_errors = true;
+            self.diagnostics.error(E_TYPE, attr_name.span, 
+                format!("Trait `{}` doesn't have a method named `{}`", trait_name, attr_name.value));
         }
 
         return Datatype::yet_to_infer;
@@ -739,7 +2613,7 @@ impl TypeChecker {
     }
 
     fn visit_variable_expr(&mut self, name: &mut Token, datatype: &mut Datatype, struct_name: &mut Option<String>) -> Datatype {
-        match self.symbol_table.variable_table.get(&name.value) {
+        match self.symbol_table.variable_table.lookup(&name.value) {
             Some(dtype) => {
                 *datatype = (*dtype).clone();
                 eprintln!("Variable datatype: {:?}", *datatype);
@@ -753,6 +2627,14 @@ impl TypeChecker {
             }
         }
 
+        if let Some(dtype) = self.capture_variable(name, self.closures.len()) {
+            self.symbol_table.variable_table.insert(name.value.clone(), dtype.clone());
+            *datatype = dtype;
+            return (*datatype).clone();
+        }
+
+        self.resolve_module_function(name);
+
         match *datatype {
             Datatype::object{..} => {
                 match struct_name {
@@ -763,7 +2645,7 @@ impl TypeChecker {
                     },
                     _ => {
                         self.has_errors = true;
-                        log_message(logger::LogLevel::ERROR, name.col, name.line, 
+                        self.diagnostics.error(E_TYPE, name.span, 
                             "Variable declared as object but unable to find its struct type".to_string());
                         *datatype = Datatype::yet_to_infer;
                         return Datatype::yet_to_infer;
@@ -776,24 +2658,25 @@ impl TypeChecker {
         if self.symbol_table.func_table.get(&name.value).is_some() {
             
 
-                if let Decl::Prototype{name: func_name, parameters, returntype}
+                if let Decl::Prototype{name: func_name, parameters, returntype, ..}
                     = self.symbol_table.func_table.get(&name.value).unwrap() {
 
                         if func_name.value == name.value {
                             // create Datattype::Function{}
                             let mut param_types = vec![];
-                            let ret_type = Box::new(Datatype::get_tok_datatype(returntype));
+                            let ret_type = Box::new(returntype.clone());
 
                             for (param_name, param_type) in parameters {
-                                param_types.push(Box::new(Datatype::get_tok_datatype(param_type)));
+                                param_types.push(Box::new(param_type.clone()));
                             }
 
-                            return Datatype::function{
-                                name: func_name.value.clone(),
+                            *datatype = Datatype::function{
+                                name: String::new(),
                                 obj_name: None,
                                 returntype: ret_type,
                                 param_types
                             };
+                            return (*datatype).clone();
                         }
 
                     
@@ -803,15 +2686,25 @@ impl TypeChecker {
             
         }
 
-        *datatype = self.get_runtime_function_type(name.value.clone());
+        *datatype = match self.get_runtime_function_type(name.value.clone()) {
+            Datatype::function{returntype, param_types, ..}
+                => Datatype::function{name: String::new(), obj_name: None, returntype, param_types},
+            dtype => dtype
+        };
         return (*datatype).clone();
         // return Datatype::yet_to_infer;
     }
 
     fn visit_exprlist_expr(&mut self, expr_list: &mut Vec<Box<Expr>>, datatype: &mut Datatype) -> Datatype {
+        let mut elem_types = vec![];
         for expr in expr_list {
             *datatype = self.visit_expr(expr);
             eprintln!("Exprlist type: {:?}", *datatype);
+            elem_types.push((*datatype).clone());
+        }
+        // several values make a tuple, as in `return a, b;`
+        if elem_types.len() > 1 {
+            *datatype = Datatype::tuple{elem_types};
         }
         return (*datatype).clone();
     }
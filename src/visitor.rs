@@ -60,12 +60,12 @@ impl Printer {
             s += " ";
             i += 1;
         }
-        print!("{}", s);
+        eprint!("{}", s);
     }
 
     fn print_data(&self, data: String) {
         self.print_space();
-        println!("{}", data);
+        eprintln!("{}", data);
     }
 
 }
@@ -79,7 +79,7 @@ impl Visitor<(), (), ()> for Printer {
         self.print_data("|".to_string());
 
         match expr {
-            Expr::Variable{name, datatype, struct_name}
+            Expr::Variable{name, datatype, struct_name, ..}
                 => {
                     match struct_name {
                     Some(str_name)=>self.print_data(format!("Identifier{{ Name:{:?}, Datatype:{:?}, Struct_name:{}}}"
@@ -88,7 +88,7 @@ impl Visitor<(), (), ()> for Printer {
                     }
                 },
 
-            Expr::AttributeRef {object, name, object_dtype, datatype}
+            Expr::AttributeRef {object, name, object_dtype, datatype, ..}
                 => {
                     self.print_data(format!(
                         "Get{{ object_type {:?}, Name:{:?}, Datatype: {:?} }}", 
@@ -101,7 +101,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 6;
                 },
 
-            Expr::Binary {lhs, rhs, operator, datatype}
+            Expr::Binary {lhs, rhs, operator, datatype, ..}
                 => {
                     self.print_data(format!("Binary{{ lhs, rhs, operator:{:?}, Datatype: {:?} }}", operator, datatype));
                     
@@ -116,12 +116,12 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Expr::Literal{value, datatype}
+            Expr::Literal{value, datatype, ..}
                 => {
                     self.print_data(format!("Primary{{ value:{:?}, datatype: {:?} }}", value, datatype));
                 },
 
-            Expr::Unary{operator, operand, datatype}
+            Expr::Unary{operator, operand, datatype, ..}
                 => {
                     self.print_data(format!("Unary{{ operand, operator: {:?}, datatype: {:?} }}",
                     operator, datatype));
@@ -133,7 +133,7 @@ impl Visitor<(), (), ()> for Printer {
                 },
 
             #[allow(dead_code, unused_variables)]
-            Expr::StructExpr{struct_name, fields, datatype}
+            Expr::StructExpr{struct_name, fields, datatype, ..}
                 => {
                     self.print_data(format!("StructExpr{{ struct_name: {:?} }}", struct_name));
                     self.space_width += 15;
@@ -150,7 +150,7 @@ impl Visitor<(), (), ()> for Printer {
 
             #[allow(unused_variables)]
             // Expr::Assignment{target_list, expr_list, datatype}
-            Expr::Assignment{target, expr, operator, datatype}
+            Expr::Assignment{target, expr, operator, datatype, ..}
                 => {
                     // let mut i: usize = 0;
                     self.print_data("Assignment{{ }}".to_string());
@@ -176,7 +176,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 13;
                 },
             
-            Expr::Grouping { expr, datatype} 
+            Expr::Grouping { expr, datatype, ..} 
                 => {
                     self.print_data(format!("Grouping{{ }}, Datatype: {:?}", datatype));
                     self.space_width += 10;
@@ -184,7 +184,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Expr::Cast { variable, cast_type, from_dtype,  to_dtype} 
+            Expr::Cast { variable, cast_type, from_dtype,  to_dtype, ..} 
                 => {
                     self.print_data(format!("Cast{{ cast_to: {:?} }}, From: {:?} To: {:?}", 
                                     cast_type, from_dtype, to_dtype));
@@ -192,7 +192,121 @@ impl Visitor<(), (), ()> for Printer {
                     self.visit_expr(&variable);
                     self.space_width -= 5;
                 },
-            Expr::ExprList{expr_list, datatype}
+            Expr::ArrayLiteral{elements, datatype, ..}
+                => {
+                    self.print_data(format!("ArrayLiteral{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 13;
+                    for (i, element) in elements.iter().enumerate() {
+                        self.print_data(format!("Element {}", i));
+                        self.visit_expr(element);
+                    }
+                    self.space_width -= 13;
+                },
+            Expr::EnumVariant{enum_name, variant_name, values, datatype, ..}
+                => {
+                    self.print_data(format!("EnumVariant{{ enum: {:?}, variant: {:?} }}, Datatype: {:?}", 
+                                    enum_name, variant_name, datatype));
+                    self.space_width += 13;
+                    for (field, value) in values {
+                        self.print_data(format!("Field {:?}", field));
+                        self.visit_expr(value);
+                    }
+                    self.space_width -= 13;
+                },
+            Expr::Match{scrutinee, arms, datatype, ..}
+                => {
+                    self.print_data(format!("Match{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 6;
+                    self.print_data("Scrutinee".to_string());
+                    self.visit_expr(scrutinee);
+                    for (pattern, body) in arms {
+                        self.print_data(format!("Arm{{ pattern: {:?} }}", pattern));
+                        self.visit_stmt(body);
+                    }
+                    self.space_width -= 6;
+                },
+            Expr::Closure{parameters, returntype, body, captures, datatype, ..}
+                => {
+                    self.print_data(format!("Closure{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 10;
+                    for param in parameters {
+                        self.print_data(format!("Param name: {:?}, Datatype: {:?}", param.0, param.1));
+                    }
+                    self.print_data(format!("ReturnType{{ {:?} }}", returntype));
+                    for capture in captures {
+                        self.print_data(format!("Capture name: {:?}, Datatype: {:?}", capture.0, capture.1));
+                    }
+                    self.visit_stmt(body);
+                    self.space_width -= 10;
+                },
+            Expr::MakeChan{elem_type, capacity, datatype, ..}
+                => {
+                    self.print_data(format!("MakeChan{{ elem_type: {:?} }}, Datatype: {:?}", elem_type, datatype));
+                    if let Some(capacity) = capacity {
+                        self.space_width += 10;
+                        self.print_data("Capacity".to_string());
+                        self.visit_expr(capacity);
+                        self.space_width -= 10;
+                    }
+                },
+            Expr::Send{channel, value, ..}
+                => {
+                    self.print_data("Send{{ }}".to_string());
+                    self.space_width += 6;
+                    self.print_data("Channel".to_string());
+                    self.visit_expr(channel);
+                    self.print_data("Value".to_string());
+                    self.visit_expr(value);
+                    self.space_width -= 6;
+                },
+            Expr::Receive{channel, datatype, ..}
+                => {
+                    self.print_data(format!("Receive{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 9;
+                    self.visit_expr(channel);
+                    self.space_width -= 9;
+                },
+            Expr::AddressOf{mutable, operand, datatype, ..}
+                => {
+                    self.print_data(format!("AddressOf{{ mutable: {:?} }}, Datatype: {:?}", mutable, datatype));
+                    self.space_width += 11;
+                    self.visit_expr(operand);
+                    self.space_width -= 11;
+                },
+            Expr::Deref{operand, datatype, ..}
+                => {
+                    self.print_data(format!("Deref{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 7;
+                    self.visit_expr(operand);
+                    self.space_width -= 7;
+                },
+            Expr::New{value, datatype, ..}
+                => {
+                    self.print_data(format!("New{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 5;
+                    self.visit_expr(value);
+                    self.space_width -= 5;
+                },
+            Expr::MakeRc{value, datatype, ..}
+                => {
+                    self.print_data(format!("MakeRc{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 8;
+                    self.visit_expr(value);
+                    self.space_width -= 8;
+                },
+            Expr::Error{..}
+                => self.print_data("Error{{ }}".to_string()),
+            Expr::Index{array, index, datatype, ..}
+                => {
+                    self.print_data(format!("Index{{ }}, Datatype: {:?}", datatype));
+                    self.space_width += 10;
+                    self.print_data("Array".to_string());
+                    self.visit_expr(array);
+                    self.print_data("Index".to_string());
+                    self.visit_expr(index);
+                    self.space_width -= 10;
+                },
+            Expr::ExprList{expr_list, datatype, ..}
                 => {
                     self.print_data(format!("Expression List{{ }}, Datatype: {:?}", datatype));
                     self.space_width += 13;
@@ -205,7 +319,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 13;
                 },
             #[allow(unused_variables)]
-            Expr::Call{callee, arguments, datatype}
+            Expr::Call{callee, arguments, datatype, ..}
                 => {
                     self.print_data("Function Call{{ }}".to_string());
                     self.space_width += 13;
@@ -235,7 +349,7 @@ impl Visitor<(), (), ()> for Printer {
         self.print_data("|".to_string());
         match stmt {
 
-            Stmt::Decl{decl} 
+            Stmt::Decl{decl, ..} 
                 => {
                     self.print_data("Declaration{{ }}".to_string());
                     self.space_width += 10;
@@ -243,7 +357,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Stmt::While{condition, block}
+            Stmt::While{condition, block, ..}
                 => {
                     self.print_data("While{{ }}".to_string());
                     self.space_width += 5;
@@ -252,7 +366,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 5;
                 },
 
-            Stmt::Block{statements}    
+            Stmt::Block{statements, ..}    
                 => {
                     self.print_data("Block{{ }}".to_string());
                     self.space_width += 7;
@@ -262,7 +376,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 7;
                 },
 
-            Stmt::Expression{expr}
+            Stmt::Expression{expr, ..}
                 => {
                     self.print_data("Expression{{ }}".to_string());
                     self.space_width += 13;
@@ -270,8 +384,15 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 13;
                 },
 
+            Stmt::Delete{expr, ..}
+                => {
+                    self.print_data("Delete{{ }}".to_string());
+                    self.space_width += 10;
+                    self.visit_expr(&expr);
+                    self.space_width -= 10;
+                },
             // Stmt::Return{expr_list}
-            Stmt::Return{expr}
+            Stmt::Return{expr, ..}
                 => {
                     self.print_data("Return{{ }}".to_string());
                     self.space_width += 10;
@@ -347,7 +468,7 @@ impl Visitor<(), (), ()> for Printer {
 
                 },
 
-            Stmt::Var{name, datatype, initialization_value}
+            Stmt::Var{name, datatype, initialization_value, ..}
                 => {
                     self.print_data(format!("Var Stmt{{ Name: {:?}, Datatype: {:?} }}",
                             name, datatype));
@@ -362,13 +483,79 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 }
 
-            // Stmt::Loop{condition} 
-            //     => {
-            //         self.print_data("Loop Condition{{ }}".to_string());
-            //         self.space_width += 15;
-            //         self.visit_expr(condition);
-            //         self.space_width -= 15;
-            //     },
+            Stmt::Destructure{names, initialization_value, ..}
+                => {
+                    self.print_data(format!("Destructure Stmt{{ Names: {:?} }}", names));
+                    self.space_width += 10;
+                    self.visit_expr(initialization_value);
+                    self.space_width -= 10;
+                },
+
+            Stmt::Break{..}
+                => self.print_data("Break{{ }}".to_string()),
+
+            Stmt::Continue{..}
+                => self.print_data("Continue{{ }}".to_string()),
+
+            Stmt::Spawn{call, ..}
+                => {
+                    self.print_data("Spawn{{ }}".to_string());
+                    self.space_width += 5;
+                    self.visit_expr(call);
+                    self.space_width -= 5;
+                },
+
+            Stmt::Select{cases, ..}
+                => {
+                    self.print_data("Select{{ }}".to_string());
+                    self.space_width += 5;
+                    for (case, body) in cases {
+                        match case {
+                            SelectCase::Receive{target, channel, elem_type, ..} => {
+                                self.print_data(format!("Receive case{{ }}, Datatype: {:?}", elem_type));
+                                if let Some(target) = target {
+                                    self.print_data("Target".to_string());
+                                    self.visit_expr(target);
+                                }
+                                self.print_data("Channel".to_string());
+                                self.visit_expr(channel);
+                            },
+                            SelectCase::Send{channel, value, ..} => {
+                                self.print_data("Send case{{ }}".to_string());
+                                self.print_data("Channel".to_string());
+                                self.visit_expr(channel);
+                                self.print_data("Value".to_string());
+                                self.visit_expr(value);
+                            },
+                            SelectCase::Default{..}
+                                => self.print_data("Default case{{ }}".to_string()),
+                        }
+                        self.visit_stmt(body);
+                    }
+                    self.space_width -= 5;
+                },
+
+            Stmt::Loop{count, block, ..}
+                => {
+                    self.print_data("Loop{{ }}".to_string());
+                    self.space_width += 5;
+
+                    if let Some(cnt) = count {
+                        self.print_data("Count{{ }}".to_string());
+                        self.space_width += 10;
+                        self.visit_expr(cnt);
+                        self.space_width -= 10;
+                    }
+
+                    self.print_data("Block{{ }}".to_string());
+                    self.space_width += 5;
+                    self.visit_stmt(block);
+                    self.space_width -= 5;
+
+                    self.space_width -= 5;
+                },
+            Stmt::Error{..}
+                => self.print_data("Error{{ }}".to_string()),
             // _ => unimplemented!()
         }
     }
@@ -378,7 +565,7 @@ impl Visitor<(), (), ()> for Printer {
         self.print_data("|".to_string());
         self.print_data("|".to_string());
         match decl {
-            Decl::FuncDef{prototype, block}
+            Decl::FuncDef{prototype, block, ..}
                 => {
                     self.print_data("FuncDef{{ }}".to_string());
 
@@ -388,7 +575,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Decl::ImplDecl{name, trait_name, funcs}
+            Decl::ImplDecl{name, trait_name, funcs, ..}
                 => {
                     if let Some(tr_name) = trait_name  {
                         self.print_data(format!("Impl{{ name: {:?}, trait_name: {:?} }}", name, tr_name));
@@ -404,7 +591,7 @@ impl Visitor<(), (), ()> for Printer {
                 },
 
             // Decl::Prototype{name, parameters, returntypes}
-            Decl::Prototype{name, parameters, returntype}
+            Decl::Prototype{name, parameters, returntype, ..}
                 => {
                     self.print_data(format!("Prototype{{ Name:{:?} }}", name));
 
@@ -423,7 +610,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Decl::StructDecl{name, fields}
+            Decl::StructDecl{name, fields, ..}
                 => {
                     self.print_data(format!("StructDecl{{ Name: {:?} }}", name));
 
@@ -435,7 +622,7 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
-            Decl::TraitDecl{name, funcs}
+            Decl::TraitDecl{name, funcs, ..}
                 => {
                     self.print_data(format!("Trait{{ Name: {:?} }}", name));
 
@@ -447,6 +634,30 @@ impl Visitor<(), (), ()> for Printer {
                     self.space_width -= 10;
                 },
 
+            Decl::EnumDecl{name, variants, ..}
+                => {
+                    self.print_data(format!("EnumDecl{{ Name: {:?} }}", name));
+
+                    self.space_width += 10;
+                    self.print_data("Variants{{ }}".to_string());
+                    for variant in variants {
+                        self.print_data(format!("Variant name: {:?}, fields: {:?}", variant.0, variant.1));
+                    }
+                    self.space_width -= 10;
+                },
+
+            Decl::Template{type_params, decl, ..}
+                => {
+                    self.print_data("Template{{ }}".to_string());
+
+                    self.space_width += 10;
+                    for (param, bound) in type_params {
+                        self.print_data(format!("Type param: {:?}, bound: {:?}", param, bound));
+                    }
+                    self.visit_decl(decl);
+                    self.space_width -= 10;
+                },
+
             // Decl::Program{decls}
             //     => {
             //         for decl in decls {
@@ -0,0 +1,35 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn select_send_waits_for_a_receiver() {
+    // the select parks on its send case before the receiver has started waiting
+    let (stdout, code) = run("select_send", r#"
+func sender(ch: chan<int64>) -> int64 {
+    select {
+        case ch <- 42:
+            printi64(1);
+            println();
+    }
+    return 0;
+}
+
+func receiver(ch: chan<int64>) -> int64 {
+    var v = <-ch;
+    printi64(v);
+    println();
+    return 0;
+}
+
+func main() -> int64 {
+    var ch = chan<int64>();
+    spawn sender(ch);
+    spawn receiver(ch);
+    waitall();
+    return 0;
+}
+"#);
+    assert_eq!(stdout, "42\n1\n");
+    assert_eq!(code, 0);
+}
@@ -0,0 +1,50 @@
+/* every test file uses only some of these */
+#![allow(dead_code)]
+
+use std::fs;
+use std::process::Command;
+
+/* Compiles `source` and runs the executable, giving its stdout and exit code. 
+   The compiler reads runtime.c from and writes main.o to the current directory, 
+   so every program is built in a directory of its own. */
+pub fn run(name: &str, source: &str) -> (String, i32) {
+    let dir = std::env::temp_dir().join(format!("baasha-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/runtime.c"), dir.join("runtime.c")).unwrap();
+    // the lexer doesn't accept a newline at the end of the file
+    fs::write(dir.join("main.bs"), source.trim()).unwrap();
+
+    let compiler = Command::new(env!("CARGO_BIN_EXE_baasha"))
+        .args(&["-f", "main.bs", "-o", "main"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(dir.join("main").exists(), "{} didn't compile:\n{}", name, String::from_utf8_lossy(&compiler.stderr));
+
+    let program = Command::new(dir.join("main")).current_dir(&dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    return (String::from_utf8(program.stdout).unwrap(), program.status.code().unwrap());
+}
+
+/* Compiles a program made of `files`, the first one being the root, and gives the codes of the
+   diagnostics it reports, in order. */
+pub fn error_codes(name: &str, files: &[(&str, &str)]) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("baasha-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/runtime.c"), dir.join("runtime.c")).unwrap();
+    for (file_name, source) in files {
+        fs::write(dir.join(file_name), source.trim()).unwrap();
+    }
+
+    let compiler = Command::new(env!("CARGO_BIN_EXE_baasha"))
+        .args(&["-f", files[0].0, "-o", "main", "--error-format", "json"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    return String::from_utf8(compiler.stdout).unwrap()
+        .lines()
+        .filter_map(|line| line.split("\"code\": \"").nth(1))
+        .map(|rest| rest[..rest.find('"').unwrap()].to_string())
+        .collect();
+}
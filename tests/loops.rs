@@ -0,0 +1,113 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn while_loop() {
+    let (stdout, code) = run("while", r#"
+func main() -> int64 {
+    var i = 1;
+    var sum = 0;
+    while i <= 10 {
+        sum = sum + i;
+        i = i + 1;
+    }
+    printi64(sum);
+    println();
+    return 3;
+}
+"#);
+    assert_eq!(stdout, "55\n");
+    assert_eq!(code, 3);
+}
+
+#[test]
+fn break_and_continue() {
+    let (stdout, code) = run("break_continue", r#"
+func main() -> int64 {
+    var i = 0;
+    while true {
+        i = i + 1;
+        if i / 2 * 2 == i {
+            continue;
+        }
+        if i > 7 {
+            break;
+        }
+        printi64(i);
+        println();
+    }
+    return i;
+}
+"#);
+    assert_eq!(stdout, "1\n3\n5\n7\n");
+    assert_eq!(code, 9);
+}
+
+#[test]
+fn counted_and_infinite_loop() {
+    let (stdout, code) = run("loop", r#"
+func main() -> int64 {
+    var n = 0;
+    loop 4 {
+        n = n + 1;
+    }
+    loop {
+        n = n + 10;
+        if n > 30 {
+            break;
+        }
+    }
+    printi64(n);
+    println();
+    var count = 0;
+    loop 3 {
+        var j = 0;
+        while true {
+            j = j + 1;
+            if j == 2 {
+                break;
+            }
+        }
+        count = count + j;
+    }
+    printi64(count);
+    println();
+    return 0;
+}
+"#);
+    assert_eq!(stdout, "34\n6\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn variables_declared_in_a_long_loop() {
+    // every iteration reuses the same stack slots, a task's small stack would overflow otherwise
+    let (stdout, code) = run("loop_stack", r#"
+struct Pair {
+    a: int64,
+    b: int64
+}
+
+func count(n: int64, ch: chan<int64>) -> int64 {
+    var total = 0;
+    loop n {
+        var p = Pair{a: 1, b: 2};
+        var sum = p.a + p.b;
+        total = total + sum - 2;
+    }
+    ch <- total;
+    return 0;
+}
+
+func main() -> int64 {
+    var ch = chan<int64>(1);
+    spawn count(100000, ch);
+    printi64(<-ch);
+    println();
+    return 0;
+}
+"#);
+    assert_eq!(stdout, "100000\n");
+    assert_eq!(code, 0);
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn match_covering_every_field_combination() {
+    let (stdout, code) = run("match_fields", r#"
+enum Opt {
+    Pair(bool, int64),
+    Flag{on: bool, off: bool},
+    Empty
+}
+
+func score(o: Opt) -> int64 {
+    return match o {
+        Opt::Pair(true, _) => 1,
+        Opt::Pair(false, n) => n,
+        Opt::Flag{on: true} => 3,
+        Opt::Flag{on: false, off: true} => 4,
+        Opt::Flag{off: false} => 5,
+        Opt::Empty => 6
+    };
+}
+
+func main() -> int64 {
+    printi64(score(Opt::Pair(true, 7)));
+    println();
+    printi64(score(Opt::Pair(false, 7)));
+    println();
+    printi64(score(Opt::Flag{on: false, off: false}));
+    println();
+    return score(Opt::Empty);
+}
+"#);
+    assert_eq!(stdout, "1\n7\n5\n");
+    assert_eq!(code, 6);
+}
@@ -0,0 +1,26 @@
+mod common;
+
+use common::error_codes;
+
+#[test]
+fn pub_item_after_a_broken_declaration() {
+    let codes = error_codes("pub_recovery", &[("main.bs", r#"
+import util;
+
+func main() -> int64 {
+    printi64(util::twice(4));
+    println();
+    return 0;
+}
+"#), ("util.bs", r#"
+struct Broken {
+    x int64
+}
+
+pub func twice(n: int64) -> int64 {
+    return n * 2;
+}
+"#)]);
+    // only the syntax error, `twice` is still public
+    assert_eq!(codes, vec!["E0100"]);
+}
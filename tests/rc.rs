@@ -0,0 +1,151 @@
+mod common;
+
+use common::run;
+
+const NODE: &str = r#"
+struct Node {
+    val: int64,
+    next: rc<Node>
+}
+
+func make(val: int64) -> rc<Node> {
+    var node = rc(Node{val: val, next: null});
+    return node;
+}
+"#;
+
+#[test]
+fn every_rc_is_freed() {
+    let (stdout, code) = run("rc_freed", &(NODE.to_string() + r#"
+func work() -> int64 {
+    var a = rc(Node{val: 1, next: null});
+    var b = a;
+    printi64(rclive());
+    println();
+    b = rc(Node{val: 2, next: null});
+    printi64(rclive());
+    println();
+    b = a;
+    printi64(rclive());
+    println();
+    if true {
+        var c = rc(Node{val: 3, next: null});
+        printi64(c.val);
+        println();
+    }
+    printi64(rclive());
+    println();
+    var d = make(4);
+    printi64(rclive());
+    println();
+    d.next = make(5);
+    d.next.next = make(6);
+    printi64(rclive());
+    println();
+    d = null;
+    printi64(rclive());
+    println();
+    return 0;
+}
+
+func main() -> int64 {
+    work();
+    printi64(rclive());
+    println();
+    return rclive();
+}
+"#));
+    // copy, assignment, scope exit, function return, a chain of nested rcs and then everything
+    assert_eq!(stdout, "1\n2\n1\n3\n1\n2\n4\n1\n0\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn cycles_leak() {
+    let (stdout, code) = run("rc_cycle", &(NODE.to_string() + r#"
+func cycle() -> int64 {
+    var a = make(1);
+    var b = rc(Node{val: 2, next: a});
+    a.next = b;
+    return 0;
+}
+
+func main() -> int64 {
+    cycle();
+    printi64(rclive());
+    println();
+    return 0;
+}
+"#));
+    assert_eq!(stdout, "2\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn trailing_values_of_blocks() {
+    // values left at the end of a nested block are freed, those of a function's trailing `if` are returned
+    let (stdout, code) = run("rc_block", &(NODE.to_string() + r#"
+func pick(n: int64) -> rc<Node> {
+    var a = make(1);
+    if n == 1 {
+        a
+    } else {
+        make(2)
+    }
+}
+
+func work() -> int64 {
+    loop 3 {
+        make(3)
+    }
+    if true {
+        rc(Node{val: 4, next: null})
+    }
+    printi64(rclive());
+    println();
+    var p = pick(1);
+    var q = pick(2);
+    printi64(p.val + q.val);
+    println();
+    printi64(rclive());
+    println();
+    return 0;
+}
+
+func main() -> int64 {
+    work();
+    printi64(rclive());
+    println();
+    return 0;
+}
+"#));
+    assert_eq!(stdout, "0\n3\n2\n0\n");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn spawned_tasks_keep_their_rcs() {
+    let (stdout, code) = run("rc_spawn", &(NODE.to_string() + r#"
+func show(node: rc<Node>) -> int64 {
+    printi64(node.val);
+    println();
+    return 0;
+}
+
+func work() -> int64 {
+    var a = make(7);
+    spawn show(a);
+    return 0;
+}
+
+func main() -> int64 {
+    work();
+    waitall();
+    printi64(rclive());
+    println();
+    return 0;
+}
+"#));
+    assert_eq!(stdout, "7\n0\n");
+    assert_eq!(code, 0);
+}